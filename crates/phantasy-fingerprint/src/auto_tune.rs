@@ -0,0 +1,221 @@
+use crate::compute_fingerprint::compute_fingerprint_with_config;
+use crate::find_matches::find_match_outcome;
+use crate::fingerprint_config::FingerprintConfig;
+use crate::fingerprint_config::PeakNeighborhood;
+use crate::fingerprint_error::FingerprintError;
+use crate::hash_bloom::splitmix64;
+use crate::match_config::MatchConfig;
+
+/// A snippet whose source is known, to judge configs by.
+#[derive(Debug, Clone)]
+pub struct LabeledSnippet {
+    pub pcm: Vec<f32>,
+    /// Index of the track the snippet comes from, `None` for one that should match nothing
+    pub track: Option<usize>,
+    /// Where in that track the snippet begins, in seconds, if known. A match elsewhere in the
+    /// right track then counts as wrong
+    pub offset_sec: Option<f32>,
+}
+
+/// How hard `auto_tune` may search, and what the chosen config must respect.
+#[derive(Debug, Clone)]
+pub struct TuneConstraints {
+    /// Where the search starts. Fields the search doesn't vary keep their values from here
+    pub base: FingerprintConfig,
+    /// Most configs to evaluate, the base among them. Each fingerprints the whole corpus once
+    pub budget: usize,
+    /// Reject configs whose track fingerprints average more pairs than this per second of
+    /// audio, which bounds the size of the index. `None` accepts any size
+    pub max_pairs_per_sec: Option<f32>,
+    /// How far from the labeled offset a match may land and still count
+    pub offset_tolerance_sec: f32,
+    /// What counts as a match
+    pub match_config: MatchConfig,
+    /// Picks which configs are tried when the budget doesn't cover them all
+    pub seed: u64,
+}
+
+impl Default for TuneConstraints {
+    fn default() -> Self {
+        Self {
+            base: FingerprintConfig::default(),
+            budget: 16,
+            max_pairs_per_sec: None,
+            offset_tolerance_sec: 0.1,
+            match_config: MatchConfig::default(),
+            seed: 0,
+        }
+    }
+}
+
+/// How a config fared on the labeled snippets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuneScore {
+    /// Fraction of the snippets with a source that matched it
+    pub recall: f32,
+    /// Fraction of matches that were right, 1 when nothing matched
+    pub precision: f32,
+    /// Pairs per second of track audio, which the size of an index follows
+    pub pairs_per_sec: f32,
+}
+
+/// The config `auto_tune` settled on.
+#[derive(Debug, Clone)]
+pub struct TunedConfig {
+    pub config: FingerprintConfig,
+    pub score: TuneScore,
+    /// How many configs were evaluated to find it
+    pub evaluated: usize,
+}
+
+/// Search for the config that best matches `snippets` to their sources among `tracks`, all at
+/// `sample_rate`, within `constraints`.
+///
+/// Varies the fields that matter most across domains (peaks per frame, peak neighborhood, and
+/// `delta_t_bin`) on a small grid, trying as much of it as `constraints.budget` allows in a
+/// seeded random order. The best config has the highest recall, then precision, then the
+/// smallest index. Returns `None` when no config evaluated fits `max_pairs_per_sec`.
+///
+/// Include snippets that match nothing, so configs loose enough to match everything pay for
+/// it in precision.
+pub fn auto_tune(
+    tracks: &[Vec<f32>],
+    snippets: &[LabeledSnippet],
+    sample_rate: usize,
+    constraints: &TuneConstraints,
+) -> Result<Option<TunedConfig>, FingerprintError> {
+    let candidates = candidate_configs(&constraints.base, constraints.budget, constraints.seed);
+    let evaluated = candidates.len();
+    let mut best: Option<(FingerprintConfig, TuneScore)> = None;
+    for config in candidates {
+        let Some(score) = evaluate(&config, tracks, snippets, sample_rate, constraints)? else {
+            continue;
+        };
+        let better = best.as_ref().is_none_or(|(_, best)| {
+            (score.recall, score.precision, -score.pairs_per_sec)
+                > (best.recall, best.precision, -best.pairs_per_sec)
+        });
+        if better {
+            best = Some((config, score));
+        }
+    }
+    Ok(best.map(|(config, score)| TunedConfig {
+        config,
+        score,
+        evaluated,
+    }))
+}
+
+/// `base`, then up to `budget - 1` variations of it in an order shuffled by `seed`.
+fn candidate_configs(base: &FingerprintConfig, budget: usize, seed: u64) -> Vec<FingerprintConfig> {
+    let neighborhoods = [
+        None,
+        Some(PeakNeighborhood::default()),
+        Some(PeakNeighborhood {
+            time_frames: 4,
+            freq_bins: 2,
+        }),
+    ];
+    let mut grid = Vec::new();
+    for max_peaks_per_frame in [5, 8, 10, 15] {
+        for min_peaks_per_frame in [1, 3] {
+            for peak_neighborhood in neighborhoods {
+                for delta_t_bin in [1, 2] {
+                    grid.push(FingerprintConfig {
+                        min_peaks_per_frame,
+                        max_peaks_per_frame,
+                        peak_neighborhood,
+                        delta_t_bin,
+                        ..base.clone()
+                    });
+                }
+            }
+        }
+    }
+    grid.retain(|config| config != base);
+    // Fisher-Yates, so a small budget still samples the whole grid
+    for i in (1..grid.len()).rev() {
+        let j = (splitmix64(seed.wrapping_add(i as u64)) % (i as u64 + 1)) as usize;
+        grid.swap(i, j);
+    }
+    grid.truncate(budget.saturating_sub(1));
+    grid.insert(0, base.clone());
+    grid
+}
+
+/// Score `config`, or `None` if its index is over the size limit.
+fn evaluate(
+    config: &FingerprintConfig,
+    tracks: &[Vec<f32>],
+    snippets: &[LabeledSnippet],
+    sample_rate: usize,
+    constraints: &TuneConstraints,
+) -> Result<Option<TuneScore>, FingerprintError> {
+    let track_fps = tracks
+        .iter()
+        .map(|pcm| compute_fingerprint_with_config(pcm, sample_rate, config))
+        .collect::<Result<Vec<_>, _>>()?;
+    let total_pairs: usize = track_fps
+        .iter()
+        .map(|fp| fp.pairs.len() + fp.side_pairs.len())
+        .sum();
+    let total_secs = tracks.iter().map(Vec::len).sum::<usize>() as f32 / sample_rate as f32;
+    let pairs_per_sec = total_pairs as f32 / total_secs.max(f32::EPSILON);
+    if constraints
+        .max_pairs_per_sec
+        .is_some_and(|max| pairs_per_sec > max)
+    {
+        return Ok(None);
+    }
+
+    // Snippets keep every pair, whatever the base does to tracks
+    let snippet_config = FingerprintConfig {
+        max_pairs_per_track: None,
+        trim_head_secs: 0.0,
+        trim_tail_secs: 0.0,
+        ..config.clone()
+    };
+    let (mut with_source, mut matched, mut right) = (0, 0, 0);
+    for snippet in snippets {
+        let snippet_fp =
+            compute_fingerprint_with_config(&snippet.pcm, sample_rate, &snippet_config)?;
+        // The most significant match across the corpus is the answer
+        let best = track_fps
+            .iter()
+            .enumerate()
+            .filter_map(|(i, track_fp)| {
+                find_match_outcome(
+                    track_fp,
+                    &snippet_fp,
+                    sample_rate,
+                    None,
+                    false,
+                    &constraints.match_config,
+                )
+                .into_match()
+                .map(|result| (i, result))
+            })
+            .min_by(|(_, a), (_, b)| a.p_value.total_cmp(&b.p_value).then(b.count.cmp(&a.count)));
+        if snippet.track.is_some() {
+            with_source += 1;
+        }
+        if let Some((track, result)) = best {
+            matched += 1;
+            let at_offset = snippet.offset_sec.is_none_or(|offset| {
+                (offset - result.offset_sec).abs() <= constraints.offset_tolerance_sec
+            });
+            if snippet.track == Some(track) && at_offset {
+                right += 1;
+            }
+        }
+    }
+    Ok(Some(TuneScore {
+        recall: right as f32 / with_source.max(1) as f32,
+        precision: if matched == 0 {
+            1.0
+        } else {
+            right as f32 / matched as f32
+        },
+        pairs_per_sec,
+    }))
+}
@@ -0,0 +1,594 @@
+use crate::fingerprint_config::FingerprintConfig;
+use crate::fingerprint_data::FPHashEntry;
+use crate::fingerprint_data::FingerprintData;
+use crate::fingerprint_data::SourceInfo;
+use crate::fingerprint_error::FingerprintError;
+use crate::fingerprint_file::decode_and_fingerprint;
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::{self};
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// Where fingerprints are cached when the caller has no preference, relative to the CWD.
+pub const DEFAULT_CACHE_DIR: &str = "hashes";
+
+/// Load from `cache_dir` if possible, else build and save
+pub fn load_or_build_fingerprint(
+    track_path: &Path,
+    cache_dir: &Path,
+    sample_rate: usize,
+) -> Result<FingerprintData, FingerprintError> {
+    load_or_build_fingerprint_with_config(
+        track_path,
+        cache_dir,
+        sample_rate,
+        &FingerprintConfig::default(),
+    )
+}
+
+/// Load from `cache_dir` if possible, else build with `config` and save.
+///
+/// A cached fingerprint is loaded whatever config it was built with, so clear the cache after
+/// changing settings that alter the pairs, such as `channel_mode`. Its `config_hash` tells
+/// which config that was.
+pub fn load_or_build_fingerprint_with_config(
+    track_path: &Path,
+    cache_dir: &Path,
+    sample_rate: usize,
+    config: &FingerprintConfig,
+) -> Result<FingerprintData, FingerprintError> {
+    load_or_build_fingerprint_as(
+        track_path,
+        cache_dir,
+        sample_rate,
+        config,
+        FingerprintFormat::default(),
+    )
+}
+
+/// Like `load_or_build_fingerprint_with_config`, saving a newly built fingerprint in `format`,
+/// e.g. JSON to inspect it. A cached fingerprint is loaded whichever format it is in.
+pub fn load_or_build_fingerprint_as(
+    track_path: &Path,
+    cache_dir: &Path,
+    sample_rate: usize,
+    config: &FingerprintConfig,
+    format: FingerprintFormat,
+) -> Result<FingerprintData, FingerprintError> {
+    if !cache_dir.exists() {
+        fs::create_dir_all(cache_dir)?;
+    }
+
+    let hash_file = cache_file_for_format(track_path, cache_dir, format);
+    let cached = if hash_file.exists() {
+        Some(hash_file.clone())
+    } else {
+        find_cache_file(track_path, cache_dir)
+    };
+
+    let Some(cached) = cached else {
+        return build_and_save_fingerprint_with_config(track_path, &hash_file, sample_rate, config);
+    };
+    // load
+    debug!("Loading fingerprint from {:?}", cached);
+    match load_fingerprint(&cached) {
+        // Same-named files in different folders share a cache file
+        Ok(FingerprintData {
+            source: Some(source),
+            ..
+        }) if !is_same_file(&source.path, track_path) => {
+            warn!(
+                "{:?} was built from {:?}, rebuilding for {:?}",
+                cached, source.path, track_path
+            );
+            build_and_save_fingerprint_with_config(track_path, &hash_file, sample_rate, config)
+        }
+        Err(FingerprintError::Corrupt { .. }) => {
+            warn!("{:?} is corrupt, rebuilding", cached);
+            let data = build_and_save_fingerprint_with_config(
+                track_path,
+                &hash_file,
+                sample_rate,
+                config,
+            )?;
+            if cached != hash_file {
+                fs::remove_file(&cached)?;
+            }
+            Ok(data)
+        }
+        result => result,
+    }
+}
+
+/// Whether `a` and `b` name the same file, comparing them as given when either can't be
+/// resolved, such as a source that has since moved.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// The file in `cache_dir` a fingerprint of `track_path` is saved to, in the default format.
+pub fn cache_file_for(track_path: &Path, cache_dir: &Path) -> PathBuf {
+    cache_file_for_format(track_path, cache_dir, FingerprintFormat::default())
+}
+
+/// The file in `cache_dir` a fingerprint of `track_path` is saved to in `format`, named after
+/// its whole file name so `song.mp3` and `song.flac` are cached apart.
+pub fn cache_file_for_format(
+    track_path: &Path,
+    cache_dir: &Path,
+    format: FingerprintFormat,
+) -> PathBuf {
+    let file_name = track_path.file_name().unwrap_or_default().to_string_lossy();
+    cache_dir.join(format!("{}.{}", file_name, format.extension()))
+}
+
+/// The cached fingerprint of `track_path` in `cache_dir`, in whichever format it was saved.
+/// Prefers the default format, so a rebuild supersedes an older JSON cache.
+pub fn find_cache_file(track_path: &Path, cache_dir: &Path) -> Option<PathBuf> {
+    [FingerprintFormat::Binary, FingerprintFormat::Json]
+        .into_iter()
+        .map(|format| cache_file_for_format(track_path, cache_dir, format))
+        .find(|hash_file| hash_file.exists())
+}
+
+/// Whether `path` is named like a cache file, in either format.
+pub fn is_cache_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        [FingerprintFormat::Binary, FingerprintFormat::Json]
+            .iter()
+            .any(|format| ext == format.extension())
+    })
+}
+
+/// Map the quick hash of every cached fingerprint's source to its cache file, to reuse it for
+/// copies of the same file. Caches that can't be loaded or predate quick hashes are left out.
+pub fn cache_files_by_quick_hash(
+    cache_dir: &Path,
+) -> Result<HashMap<u64, PathBuf>, FingerprintError> {
+    let mut by_hash = HashMap::new();
+    if !cache_dir.exists() {
+        return Ok(by_hash);
+    }
+    for entry in fs::read_dir(cache_dir)? {
+        let hash_file = entry?.path();
+        if !is_cache_file(&hash_file) {
+            continue;
+        }
+        match load_fingerprint(&hash_file) {
+            Ok(FingerprintData {
+                source:
+                    Some(SourceInfo {
+                        quick_hash: Some(quick_hash),
+                        ..
+                    }),
+                ..
+            }) => {
+                by_hash.insert(quick_hash, hash_file);
+            }
+            Ok(_) => {}
+            Err(e) => debug!("Not indexing {:?}: {:?}", hash_file, e),
+        }
+    }
+    Ok(by_hash)
+}
+
+/// Save a copy of the fingerprint in `existing` for `track_path`, a file with the same
+/// contents, instead of decoding and fingerprinting it again. The copy is saved in the format
+/// `hash_file` is named for, whatever the format of `existing`.
+pub fn save_fingerprint_for_duplicate(
+    existing: &Path,
+    track_path: &Path,
+    hash_file: &Path,
+) -> Result<FingerprintData, FingerprintError> {
+    let mut data = load_fingerprint(existing)?;
+    data.source = Some(SourceInfo::read(track_path)?);
+    save_fingerprint(&data, hash_file)?;
+    Ok(data)
+}
+
+/// How a fingerprint is written to its cache file. Saving picks the format by extension, while
+/// loading tells the formats apart by their contents, so older caches load whatever they're
+/// called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FingerprintFormat {
+    /// Pretty-printed JSON in a `.json` file, easy to inspect when debugging
+    Json,
+    /// Pairs packed into 10 bytes each, after the rest of the fingerprint as JSON, in a `.fp`
+    /// file. Several times smaller than `Json` and quicker to load
+    #[default]
+    Binary,
+}
+
+impl FingerprintFormat {
+    /// The extension of cache files in this format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            FingerprintFormat::Json => "json",
+            FingerprintFormat::Binary => "fp",
+        }
+    }
+
+    /// The format a fingerprint saved to `path` is written in: JSON for a `.json` file, binary
+    /// for anything else.
+    pub fn from_path(path: &Path) -> FingerprintFormat {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => FingerprintFormat::Json,
+            _ => FingerprintFormat::Binary,
+        }
+    }
+
+    /// The format of a cache file body, from its first bytes. `None` if it is neither.
+    pub fn detect(body: &[u8]) -> Option<FingerprintFormat> {
+        if body.starts_with(BINARY_MAGIC) {
+            Some(FingerprintFormat::Binary)
+        } else if body.trim_ascii_start().starts_with(b"{") {
+            Some(FingerprintFormat::Json)
+        } else {
+            None
+        }
+    }
+}
+
+impl FingerprintData {
+    /// Save to `path`, as JSON for a `.json` file and in the compact binary format otherwise,
+    /// such as for `.fp`. See [`save_fingerprint`].
+    pub fn write_to(&self, path: &Path) -> Result<(), FingerprintError> {
+        save_fingerprint(self, path)
+    }
+
+    /// Load from `path`, in either format whatever its extension. See [`load_fingerprint`].
+    pub fn read_from(path: &Path) -> Result<FingerprintData, FingerprintError> {
+        load_fingerprint(path)
+    }
+}
+
+/// Cache files start with this, then the length and CRC-32 of the body that follows.
+const HEADER_MAGIC: &str = "phantasy-fingerprint";
+/// Binary bodies start with this and a format version. JSON bodies never do
+const BINARY_MAGIC: &[u8] = b"PHFB\x01";
+
+/// Load a fingerprint saved by [`save_fingerprint`] or [`save_fingerprint_as`], in either
+/// format.
+///
+/// Returns [`FingerprintError::Corrupt`] when the body doesn't match the length and CRC in its
+/// header, is in neither format, doesn't parse, or doesn't match its checksum. Files from
+/// before the header was added are plain JSON and are still accepted.
+pub fn load_fingerprint(hash_file: &Path) -> Result<FingerprintData, FingerprintError> {
+    Ok(load_fingerprint_with_format(hash_file)?.0)
+}
+
+fn load_fingerprint_with_format(
+    hash_file: &Path,
+) -> Result<(FingerprintData, FingerprintFormat), FingerprintError> {
+    let corrupt = || FingerprintError::Corrupt {
+        path: hash_file.to_path_buf(),
+    };
+    let bytes = fs::read(hash_file)?;
+    let body = if bytes.starts_with(HEADER_MAGIC.as_bytes()) {
+        let newline = bytes.iter().position(|&b| b == b'\n').ok_or_else(corrupt)?;
+        let header = std::str::from_utf8(&bytes[..newline]).map_err(|_| corrupt())?;
+        let body = &bytes[newline + 1..];
+        let mut fields = header.split(' ').skip(1);
+        let len = fields.next().and_then(|f| f.parse::<usize>().ok());
+        let crc = fields.next().and_then(|f| u32::from_str_radix(f, 16).ok());
+        if len != Some(body.len()) || crc != Some(crc32(body)) {
+            return Err(corrupt());
+        }
+        body
+    } else {
+        &bytes[..]
+    };
+    let format = FingerprintFormat::detect(body).ok_or_else(corrupt)?;
+    // A truncated legacy file fails to parse rather than failing a header check
+    let data = match format {
+        FingerprintFormat::Json => serde_json::from_slice(body).map_err(|e| {
+            debug!("Failed to parse {:?}: {}", hash_file, e);
+            corrupt()
+        })?,
+        FingerprintFormat::Binary => decode_binary(body).ok_or_else(corrupt)?,
+    };
+    if let Some(checksum) = data.checksum
+        && checksum != data.content_hash()
+    {
+        return Err(corrupt());
+    }
+    Ok((data, format))
+}
+
+/// Decode and fingerprint `track_path`, recording its metadata, and save it to `hash_file`.
+pub fn build_and_save_fingerprint(
+    track_path: &Path,
+    hash_file: &Path,
+    sample_rate: usize,
+) -> Result<FingerprintData, FingerprintError> {
+    build_and_save_fingerprint_with_config(
+        track_path,
+        hash_file,
+        sample_rate,
+        &FingerprintConfig::default(),
+    )
+}
+
+/// Like `build_and_save_fingerprint`, tuned by `config`.
+pub fn build_and_save_fingerprint_with_config(
+    track_path: &Path,
+    hash_file: &Path,
+    sample_rate: usize,
+    config: &FingerprintConfig,
+) -> Result<FingerprintData, FingerprintError> {
+    // build
+    info!("Building fingerprint for {:?}", track_path);
+    let (data, _) = decode_and_fingerprint(track_path, sample_rate, config)?;
+    save_fingerprint(&data, hash_file)?;
+    Ok(data)
+}
+
+/// Save `data` to `hash_file` behind a length and CRC header, in the format its extension
+/// names, see [`FingerprintFormat::from_path`].
+///
+/// Writes to a temporary file next to `hash_file` and renames it into place, so a crash
+/// mid-save leaves either the old cache or none rather than a partial one.
+pub fn save_fingerprint(data: &FingerprintData, hash_file: &Path) -> Result<(), FingerprintError> {
+    save_fingerprint_as(data, hash_file, FingerprintFormat::from_path(hash_file))
+}
+
+/// Like `save_fingerprint`, in `format`.
+pub fn save_fingerprint_as(
+    data: &FingerprintData,
+    hash_file: &Path,
+    format: FingerprintFormat,
+) -> Result<(), FingerprintError> {
+    let body = match format {
+        FingerprintFormat::Json => serde_json::to_vec_pretty(data)?,
+        FingerprintFormat::Binary => encode_binary(data)?,
+    };
+    let mut tmp_name = hash_file.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_file = PathBuf::from(tmp_name);
+
+    let f = File::create(&tmp_file)?;
+    let mut writer = BufWriter::new(f);
+    writeln!(
+        writer,
+        "{} {} {:08x}",
+        HEADER_MAGIC,
+        body.len(),
+        crc32(&body)
+    )?;
+    writer.write_all(&body)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&tmp_file, hash_file)?;
+    Ok(())
+}
+
+/// `BINARY_MAGIC`, then the length of and the fingerprint without its pairs as JSON, then the
+/// count of and the mid pairs, then the count of and the side pairs. Everything little-endian,
+/// each pair as `anchor_time`, `f1`, `f2`, `delta_t`.
+fn encode_binary(data: &FingerprintData) -> Result<Vec<u8>, FingerprintError> {
+    let meta = serde_json::to_vec(&FingerprintData {
+        pairs: Vec::new(),
+        side_pairs: Vec::new(),
+        source: data.source.clone(),
+        channels: data.channels,
+        checksum: data.checksum,
+        config_hash: data.config_hash,
+    })?;
+    let n_pairs = data.pairs.len() + data.side_pairs.len();
+    let mut body = Vec::with_capacity(BINARY_MAGIC.len() + 12 + meta.len() + 10 * n_pairs);
+    body.extend_from_slice(BINARY_MAGIC);
+    body.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+    body.extend_from_slice(&meta);
+    for pairs in [&data.pairs, &data.side_pairs] {
+        body.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+        for pair in pairs {
+            body.extend_from_slice(&pair.anchor_time.to_le_bytes());
+            body.extend_from_slice(&pair.f1.to_le_bytes());
+            body.extend_from_slice(&pair.f2.to_le_bytes());
+            body.extend_from_slice(&pair.delta_t.to_le_bytes());
+        }
+    }
+    Ok(body)
+}
+
+/// Read what `encode_binary` wrote, `None` if it is cut short or malformed.
+fn decode_binary(body: &[u8]) -> Option<FingerprintData> {
+    fn take<'a>(rest: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        let (head, tail) = rest.split_at_checked(n)?;
+        *rest = tail;
+        Some(head)
+    }
+    fn take_u32(rest: &mut &[u8]) -> Option<u32> {
+        Some(u32::from_le_bytes(take(rest, 4)?.try_into().ok()?))
+    }
+    fn take_pairs(rest: &mut &[u8]) -> Option<Vec<FPHashEntry>> {
+        let n = take_u32(rest)? as usize;
+        let bytes = take(rest, n.checked_mul(10)?)?;
+        let u16_at = |pair: &[u8], i: usize| u16::from_le_bytes([pair[i], pair[i + 1]]);
+        Some(
+            bytes
+                .chunks_exact(10)
+                .map(|pair| FPHashEntry {
+                    anchor_time: u32::from_le_bytes([pair[0], pair[1], pair[2], pair[3]]),
+                    f1: u16_at(pair, 4),
+                    f2: u16_at(pair, 6),
+                    delta_t: u16_at(pair, 8),
+                })
+                .collect(),
+        )
+    }
+
+    let mut rest = body.strip_prefix(BINARY_MAGIC)?;
+    let meta_len = take_u32(&mut rest)? as usize;
+    let mut data: FingerprintData = serde_json::from_slice(take(&mut rest, meta_len)?).ok()?;
+    data.pairs = take_pairs(&mut rest)?;
+    data.side_pairs = take_pairs(&mut rest)?;
+    rest.is_empty().then_some(data)
+}
+
+/// CRC-32 (IEEE), as used by zip and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+    use crate::test_signal::write_wav;
+
+    /// A fingerprint using every field, so a round trip that drops one is caught.
+    fn sample() -> FingerprintData {
+        let pair = |anchor_time, f1, f2, delta_t| FPHashEntry {
+            f1,
+            f2,
+            delta_t,
+            anchor_time,
+        };
+        let mut data = FingerprintData {
+            pairs: vec![
+                pair(0, 12, 40, 3),
+                pair(7, 511, 2, 9),
+                pair(u32::MAX, 0, 0, 1),
+            ],
+            side_pairs: vec![pair(3, 100, 101, 2)],
+            source: Some(SourceInfo {
+                path: PathBuf::from("music/track.ogg"),
+                size: 123_456,
+                modified: 1_700_000_000,
+                quick_hash: Some(0xdead_beef),
+            }),
+            channels: Some(2),
+            checksum: None,
+            config_hash: Some(FingerprintConfig::default().stable_hash()),
+        };
+        data.checksum = Some(data.content_hash());
+        data
+    }
+
+    /// A path in the system temp directory no other test uses.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("phantasy-cache-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn binary_encoding_round_trips() {
+        let data = sample();
+        assert_eq!(decode_binary(&encode_binary(&data).unwrap()), Some(data));
+    }
+
+    #[test]
+    fn json_cache_converts_to_binary_unchanged() {
+        let data = sample();
+        let json = temp_path("round-trip.json");
+        let fp = temp_path("round-trip.fp");
+        data.write_to(&json).unwrap();
+        let (from_json, json_format) = load_fingerprint_with_format(&json).unwrap();
+        from_json.write_to(&fp).unwrap();
+        let (from_fp, fp_format) = load_fingerprint_with_format(&fp).unwrap();
+        let _ = fs::remove_file(&json);
+        let _ = fs::remove_file(&fp);
+
+        assert_eq!(json_format, FingerprintFormat::Json);
+        assert_eq!(fp_format, FingerprintFormat::Binary);
+        assert_eq!(from_json, data);
+        assert_eq!(from_fp, data);
+    }
+
+    #[test]
+    fn detect_reads_the_body_not_the_extension() {
+        let data = sample();
+        assert_eq!(
+            FingerprintFormat::detect(&encode_binary(&data).unwrap()),
+            Some(FingerprintFormat::Binary)
+        );
+        assert_eq!(
+            FingerprintFormat::detect(&serde_json::to_vec_pretty(&data).unwrap()),
+            Some(FingerprintFormat::Json)
+        );
+        assert_eq!(
+            FingerprintFormat::detect(b"  \n{\"pairs\": []}"),
+            Some(FingerprintFormat::Json)
+        );
+        assert_eq!(FingerprintFormat::detect(b"RIFF...."), None);
+        assert_eq!(FingerprintFormat::detect(b""), None);
+    }
+
+    #[test]
+    fn caches_load_whatever_their_extension() {
+        let data = sample();
+        let binary_as_json = temp_path("binary.json");
+        let json_as_fp = temp_path("json.fp");
+        let legacy_as_fp = temp_path("legacy.fp");
+        save_fingerprint_as(&data, &binary_as_json, FingerprintFormat::Binary).unwrap();
+        save_fingerprint_as(&data, &json_as_fp, FingerprintFormat::Json).unwrap();
+        // From before the header was added
+        fs::write(&legacy_as_fp, serde_json::to_vec(&data).unwrap()).unwrap();
+
+        let loaded = [&binary_as_json, &json_as_fp, &legacy_as_fp].map(|path| {
+            let loaded = load_fingerprint_with_format(path);
+            let _ = fs::remove_file(path);
+            loaded.unwrap()
+        });
+        assert_eq!(loaded[0], (data.clone(), FingerprintFormat::Binary));
+        assert_eq!(loaded[1], (data.clone(), FingerprintFormat::Json));
+        assert_eq!(loaded[2], (data, FingerprintFormat::Json));
+    }
+
+    #[test]
+    fn unknown_body_is_corrupt() {
+        let path = temp_path("unknown.fp");
+        fs::write(&path, b"not a fingerprint").unwrap();
+        let loaded = load_fingerprint(&path);
+        let _ = fs::remove_file(&path);
+        assert!(matches!(loaded, Err(FingerprintError::Corrupt { .. })));
+    }
+
+    #[test]
+    fn same_named_tracks_are_cached_apart() {
+        let cache_dir = Path::new("hashes");
+        assert_ne!(
+            cache_file_for(Path::new("music/song.mp3"), cache_dir),
+            cache_file_for(Path::new("music/song.flac"), cache_dir)
+        );
+
+        // Same file name in two folders, so sharing a cache file
+        let dir = temp_path("same-name");
+        let cache_dir = dir.join("hashes");
+        let tracks = ["a", "b"].map(|folder| dir.join(folder).join("song.wav"));
+        for (seed, track) in tracks.iter().enumerate() {
+            fs::create_dir_all(track.parent().unwrap()).unwrap();
+            write_wav(track, &noise(3.0, seed as u64), SAMPLE_RATE as u32, 1);
+        }
+        let load = |track: &Path| load_or_build_fingerprint(track, &cache_dir, SAMPLE_RATE);
+        let built = tracks.each_ref().map(|track| load(track));
+        let reloaded = load(&tracks[0]);
+        let _ = fs::remove_dir_all(&dir);
+
+        let [a, b] = built.map(Result::unwrap);
+        assert_ne!(a.pairs, b.pairs);
+        assert_eq!(b.source.unwrap().path, tracks[1]);
+        // Rebuilt for `a` rather than taken from `b`'s cache
+        assert_eq!(reloaded.unwrap(), a);
+    }
+}
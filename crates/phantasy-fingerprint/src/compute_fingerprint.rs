@@ -0,0 +1,334 @@
+use crate::find_peaks::find_peaks;
+use crate::fingerprint_config::FingerprintConfig;
+use crate::fingerprint_data::FPHashEntry;
+use crate::fingerprint_data::FingerprintData;
+use crate::fingerprint_error::FingerprintError;
+use crate::normalize_loudness::normalize_loudness;
+use crate::spectrogram_backend::RustFftSpectrogram;
+use crate::spectrogram_backend::Spectrogram;
+use crate::spectrogram_backend::SpectrogramConfig;
+use crate::spectrogram_backend::SpectrogramMatrix;
+
+/// Samples per spectrogram window
+pub const WINDOW_SIZE: usize = 1024;
+/// Samples between the starts of consecutive spectrogram windows
+pub const HOP_SIZE: usize = 512;
+/// Each anchor is paired with peaks from this many following frames
+pub(crate) const TARGET_ZONE_FRAMES: usize = 9;
+/// How many peaks of each target frame an anchor is paired with
+pub(crate) const FAN_VALUE: usize = 5;
+
+/// Build a basic fingerprint from PCM data
+pub fn compute_fingerprint(
+    pcm: &[f32],
+    sample_rate: usize,
+) -> Result<FingerprintData, FingerprintError> {
+    compute_fingerprint_with_config(pcm, sample_rate, &FingerprintConfig::default())
+}
+
+/// Build a fingerprint from PCM data, tuned by `config`
+pub fn compute_fingerprint_with_config(
+    pcm: &[f32],
+    sample_rate: usize,
+    config: &FingerprintConfig,
+) -> Result<FingerprintData, FingerprintError> {
+    compute_fingerprint_with_backend(pcm, sample_rate, config, &RustFftSpectrogram)
+}
+
+/// Build a fingerprint from PCM data, tuned by `config`, with spectrograms from `backend`
+pub fn compute_fingerprint_with_backend(
+    pcm: &[f32],
+    sample_rate: usize,
+    config: &FingerprintConfig,
+    backend: &impl Spectrogram,
+) -> Result<FingerprintData, FingerprintError> {
+    config.validate_for(sample_rate)?;
+
+    // 0) Optionally bring the signal to a common loudness
+    let normalized;
+    let pcm = match config.target_lufs {
+        Some(target_lufs) => {
+            normalized = normalize_loudness(pcm, sample_rate, target_lufs)?;
+            &normalized
+        }
+        None => pcm,
+    };
+
+    // 1) Build a spectrogram
+    //    For demonstration, we’ll keep it smaller windows to be faster
+    let spec = backend.compute(
+        pcm,
+        &SpectrogramConfig {
+            sample_rate,
+            window_size: WINDOW_SIZE,
+            hop_size: HOP_SIZE,
+        },
+    )?;
+
+    fingerprint_spectrogram(&spec, config, Some(sample_rate))
+}
+
+/// Pick peaks from an already-computed spectrogram and pair them, tuned by `config`.
+///
+/// For spectrograms computed elsewhere, e.g. by accelerated code or read from a cache. `spec`
+/// must be laid out as `compute_spectrogram` returns it, with `WINDOW_SIZE` Hann windows every
+/// `HOP_SIZE` samples, for the pairs to match fingerprints built from PCM. Loudness
+/// normalization needs the PCM, so `config.target_lufs` is not applied here, and trimming
+/// needs the sample rate, so neither are `config.trim_head_secs` and `config.trim_tail_secs`.
+pub fn compute_fingerprint_from_spectrogram(
+    spec: &SpectrogramMatrix,
+    config: &FingerprintConfig,
+) -> Result<FingerprintData, FingerprintError> {
+    fingerprint_spectrogram(spec, config, None)
+}
+
+/// Everything after the spectrogram, trimming the head and tail when `sample_rate` is known.
+fn fingerprint_spectrogram(
+    spec: &SpectrogramMatrix,
+    config: &FingerprintConfig,
+    sample_rate: Option<usize>,
+) -> Result<FingerprintData, FingerprintError> {
+    config.validate()?;
+
+    // 2) Find local maxima in each time slice
+    let peaks_by_time = find_peaks(spec, config);
+
+    // 3) Create pairs (f1, f2, delta_t)
+    //    We'll pair each peak with a handful of future peaks to get (f1, f2, Δt).
+    let mut pairs = Vec::new();
+    for (t, peaks) in peaks_by_time.iter().enumerate() {
+        let future_end = (t + 1 + TARGET_ZONE_FRAMES).min(peaks_by_time.len());
+        pair_peaks(
+            t as u32,
+            peaks,
+            &peaks_by_time[t + 1..future_end],
+            &mut pairs,
+        );
+    }
+
+    // 4) Optionally drop pairs in the intro and outro, before capping so they don't use up
+    //    the budget
+    if let Some(sample_rate) = sample_rate
+        && (config.trim_head_secs > 0.0 || config.trim_tail_secs > 0.0)
+    {
+        let frames_per_sec = sample_rate as f32 / HOP_SIZE as f32;
+        let n_frames = peaks_by_time.len() as u32;
+        let head = (config.trim_head_secs * frames_per_sec).ceil() as u32;
+        let end = n_frames.saturating_sub((config.trim_tail_secs * frames_per_sec).ceil() as u32);
+        pairs.retain(|pair| {
+            pair.anchor_time >= head && pair.anchor_time + u32::from(pair.delta_t) < end
+        });
+    }
+
+    // 5) Optionally drop the pairs anchored on the quietest peaks
+    if let Some(max_pairs) = config.max_pairs_per_track
+        && pairs.len() > max_pairs
+    {
+        let steps = config.sub_bin_resolution.unwrap_or(1) as usize;
+        let anchor_magnitude =
+            |pair: &FPHashEntry| spec[pair.f1 as usize / steps][pair.anchor_time as usize];
+        pairs.sort_by(|a, b| anchor_magnitude(b).total_cmp(&anchor_magnitude(a)));
+        pairs.truncate(max_pairs);
+        pairs.sort_by_key(|pair| pair.anchor_time);
+    }
+
+    // 6) Optionally coarsen delta_t, last so the steps above still see exact frames
+    bin_delta_t(&mut pairs, config.delta_t_bin);
+
+    let mut data = FingerprintData {
+        pairs,
+        side_pairs: Vec::new(),
+        source: None,
+        channels: None,
+        checksum: None,
+        config_hash: Some(config.stable_hash()),
+    };
+    data.checksum = Some(data.content_hash());
+    Ok(data)
+}
+
+/// Build a fingerprint from the mid and side channels of a stereo signal, for
+/// `ChannelMode::MidSide`. `config.channel_mode` itself is not consulted.
+///
+/// A side channel with next to no energy, as from a mono recording stored as stereo, would only
+/// yield pairs of noise, so it contributes no side pairs.
+pub fn compute_fingerprint_mid_side(
+    mid: &[f32],
+    side: &[f32],
+    sample_rate: usize,
+    config: &FingerprintConfig,
+) -> Result<FingerprintData, FingerprintError> {
+    // Side energy this far below mid is rounding noise rather than a stereo image
+    const MIN_SIDE_TO_MID_ENERGY: f32 = 1e-6;
+
+    let mut data = compute_fingerprint_with_config(mid, sample_rate, config)?;
+    let energy = |pcm: &[f32]| pcm.iter().map(|s| s * s).sum::<f32>();
+    if !side.is_empty() && energy(side) > energy(mid) * MIN_SIDE_TO_MID_ENERGY {
+        data.side_pairs = compute_fingerprint_with_config(side, sample_rate, config)?.pairs;
+        data.checksum = Some(data.content_hash());
+    }
+    Ok(data)
+}
+
+/// Replace each `delta_t` with the nearest multiple of `bin`, counted in bins, so 1 leaves pairs
+/// untouched.
+pub(crate) fn bin_delta_t(pairs: &mut [FPHashEntry], bin: u16) {
+    if bin <= 1 {
+        return;
+    }
+    for pair in pairs {
+        pair.delta_t = (pair.delta_t + bin / 2) / bin;
+    }
+}
+
+/// Pair every peak of the frame at `anchor_time` with peaks of the frames right after it.
+///
+/// `future[i]` holds the peaks of frame `anchor_time + 1 + i`.
+pub(crate) fn pair_peaks(
+    anchor_time: u32,
+    peaks: &[u16],
+    future: &[Vec<u16>],
+    pairs: &mut Vec<FPHashEntry>,
+) {
+    for &f1 in peaks {
+        for (delta_t, future_peaks) in (1..).zip(future) {
+            // pick up to FAN_VALUE peaks from the future frame
+            for &f2 in future_peaks.iter().take(FAN_VALUE) {
+                pairs.push(FPHashEntry {
+                    f1,
+                    f2,
+                    delta_t,
+                    anchor_time,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_spectrogram::compute_spectrogram;
+    use crate::find_matches::find_matches;
+    use crate::fingerprint_config::PeakNeighborhood;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+
+    #[test]
+    fn noise_track_pair_count_is_clamped() {
+        let pcm = noise(10.0, 0);
+        let uncapped = compute_fingerprint(&pcm, SAMPLE_RATE).unwrap();
+        let config = FingerprintConfig {
+            max_pairs_per_track: Some(1000),
+            ..FingerprintConfig::default()
+        };
+        let capped = compute_fingerprint_with_config(&pcm, SAMPLE_RATE, &config).unwrap();
+        assert!(uncapped.pairs.len() > 1000);
+        assert_eq!(capped.pairs.len(), 1000);
+
+        // The kept pairs are the uncapped ones with the loudest anchors, still in time order
+        assert!(
+            capped
+                .pairs
+                .iter()
+                .all(|pair| uncapped.pairs.contains(pair))
+        );
+        assert!(capped.pairs.is_sorted_by_key(|pair| pair.anchor_time));
+        let spec = compute_spectrogram(&pcm, SAMPLE_RATE, WINDOW_SIZE, HOP_SIZE).unwrap();
+        let anchor_magnitude =
+            |pair: &FPHashEntry| spec[pair.f1 as usize][pair.anchor_time as usize];
+        let quietest_kept = capped
+            .pairs
+            .iter()
+            .map(anchor_magnitude)
+            .fold(f32::INFINITY, f32::min);
+        assert!(
+            uncapped
+                .pairs
+                .iter()
+                .filter(|pair| !capped.pairs.contains(pair))
+                .all(|pair| anchor_magnitude(pair) <= quietest_kept)
+        );
+    }
+
+    #[test]
+    fn precomputed_spectrogram_matches_the_full_pipeline() {
+        let pcm = noise(5.0, 1);
+        let spec = compute_spectrogram(&pcm, SAMPLE_RATE, WINDOW_SIZE, HOP_SIZE).unwrap();
+        for config in [
+            FingerprintConfig::default(),
+            FingerprintConfig {
+                min_peaks_per_frame: 2,
+                max_peaks_per_frame: 8,
+                sub_bin_resolution: Some(4),
+                max_pairs_per_track: Some(5000),
+                peak_neighborhood: Some(PeakNeighborhood::default()),
+                delta_t_bin: 2,
+                ..FingerprintConfig::default()
+            },
+        ] {
+            assert_eq!(
+                compute_fingerprint_from_spectrogram(&spec, &config).unwrap(),
+                compute_fingerprint_with_config(&pcm, SAMPLE_RATE, &config).unwrap(),
+                "{config:?}"
+            );
+        }
+    }
+
+    /// Two-note phrases played with `step_frames` frames between notes. Each note is a short
+    /// burst centred in one analysis window, so that frame alone holds its peaks.
+    fn phrases(step_frames: usize) -> Vec<f32> {
+        const PHRASES: [(f32, f32); 4] = [
+            (440.0, 1250.0),
+            (620.0, 2900.0),
+            (1800.0, 760.0),
+            (3300.0, 1500.0),
+        ];
+        let burst = WINDOW_SIZE / 4;
+        let mut pcm = vec![0.0; (3 * PHRASES.len() + 1) * step_frames * HOP_SIZE + WINDOW_SIZE];
+        for (i, (first, second)) in PHRASES.into_iter().enumerate() {
+            // A rest after each phrase keeps it out of the next one's target zone
+            for (step, freq) in [(3 * i + 1, first), (3 * i + 2, second)] {
+                let start = step * step_frames * HOP_SIZE + (WINDOW_SIZE - burst) / 2;
+                for (j, sample) in pcm[start..start + burst].iter_mut().enumerate() {
+                    let envelope = (std::f32::consts::PI * j as f32 / burst as f32)
+                        .sin()
+                        .powi(2);
+                    *sample += envelope
+                        * (std::f32::consts::TAU * freq * j as f32 / SAMPLE_RATE as f32).sin();
+                }
+            }
+        }
+        pcm
+    }
+
+    #[test]
+    fn slower_performance_matches_only_with_delta_t_binning() {
+        // Played an eighth slower, the notes of a phrase are 8 frames apart instead of 7
+        let track = phrases(7);
+        let slower = phrases(8);
+        // The third phrase, whose first note is at frame 7 * 8 = 56 of the slower take
+        let snippet = &slower[52 * HOP_SIZE..76 * HOP_SIZE];
+        let expected_offset = (7 * 7 - 4) as f32 * HOP_SIZE as f32 / SAMPLE_RATE as f32;
+
+        let matched = |delta_t_bin| {
+            let config = FingerprintConfig {
+                min_peak_magnitude: Some(5.0),
+                delta_t_bin,
+                ..FingerprintConfig::default()
+            };
+            let track_fp = compute_fingerprint_with_config(&track, SAMPLE_RATE, &config).unwrap();
+            let snippet_fp =
+                compute_fingerprint_with_config(snippet, SAMPLE_RATE, &config).unwrap();
+            find_matches(&track_fp, &snippet_fp, SAMPLE_RATE, None, false)
+        };
+        assert!(matched(1).is_none());
+        // 7 and 8 frames both round to 4 bins of 2
+        let found = matched(2).expect("binned snippet should match");
+        assert!(
+            (found.offset_sec - expected_offset).abs() < 1e-3,
+            "{}",
+            found.offset_sec
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use crate::fingerprint_error::FingerprintError;
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+use rustfft::num_traits::Zero;
+
+/// Compute a spectrogram of `pcm` with Hann window. Return matrix of shape (n_freq, n_frames).
+///
+/// Errors with `TooShort` when `pcm` does not fill a single window.
+pub fn compute_spectrogram(
+    pcm: &[f32],
+    _sample_rate: usize,
+    window_size: usize,
+    hop_size: usize,
+) -> Result<Vec<Vec<f32>>, FingerprintError> {
+    if pcm.len() < window_size {
+        return Err(FingerprintError::TooShort {
+            samples: pcm.len(),
+            required: window_size,
+        });
+    }
+
+    let n_hops = (pcm.len().saturating_sub(window_size)) / hop_size + 1;
+    let n_freqs = window_size / 2;
+
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(window_size);
+
+    let mut spectrogram = vec![vec![0.0; n_hops]; n_freqs];
+    let mut buffer = vec![Complex::<f32>::zero(); window_size];
+
+    let window_func = hann_window(window_size);
+
+    for hop_idx in 0..n_hops {
+        let offset = hop_idx * hop_size;
+        let frame = &pcm[offset..offset + window_size];
+        for ((slot, &sample), &weight) in buffer.iter_mut().zip(frame).zip(&window_func) {
+            slot.re = sample * weight;
+            slot.im = 0.0;
+        }
+        fft.process(&mut buffer);
+
+        for (row, bin) in spectrogram.iter_mut().zip(&buffer) {
+            row[hop_idx] = (bin.re * bin.re + bin.im * bin.im).sqrt();
+        }
+    }
+
+    Ok(spectrogram)
+}
+
+/// Hann window weights for `window_size` samples.
+pub(crate) fn hann_window(window_size: usize) -> Vec<f32> {
+    (0..window_size)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / window_size as f32).cos())
+        .collect()
+}
@@ -0,0 +1,845 @@
+use crate::fingerprint_error::FingerprintError;
+use ogg::OggReadError;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::path::Path;
+use tracing::warn;
+
+/// The codec carried inside an OGG container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OggCodec {
+    Vorbis,
+    Opus,
+}
+
+/// The channels an OGG stream carries, which `decode_ogg_to_mono_f32` averages down to one.
+///
+/// The same content decoded from different layouts gives different fingerprints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Mono,
+    Stereo,
+    /// More than two channels, e.g. 6 for 5.1 surround
+    Multichannel(u8),
+}
+
+impl ChannelLayout {
+    pub fn from_channels(channels: u8) -> ChannelLayout {
+        match channels {
+            1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            n => ChannelLayout::Multichannel(n),
+        }
+    }
+
+    pub fn channels(&self) -> u8 {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Multichannel(n) => *n,
+        }
+    }
+}
+
+/// Identify the codec of an OGG file from the magic bytes of its first packet.
+pub fn detect_ogg_codec(path: &Path) -> Result<OggCodec, FingerprintError> {
+    let packet = read_ident_packet(path)?;
+    codec_of(path, &packet)
+}
+
+/// Read the channel layout of an OGG file from its identification header.
+pub fn detect_ogg_channel_layout(path: &Path) -> Result<ChannelLayout, FingerprintError> {
+    let packet = read_ident_packet(path)?;
+    // Vorbis: packet type, "vorbis", u32 version, then channels. Opus: "OpusHead", version, channels
+    let channels_at = match codec_of(path, &packet)? {
+        OggCodec::Vorbis => 11,
+        OggCodec::Opus => 9,
+    };
+    let channels = *packet
+        .get(channels_at)
+        .ok_or_else(|| FingerprintError::decode(path, "Truncated identification header"))?;
+    Ok(ChannelLayout::from_channels(channels))
+}
+
+/// The rate PCM decoded from an OGG file comes out at.
+///
+/// Vorbis decodes at the rate in its identification header. Opus always decodes at 48 kHz,
+/// whatever input rate its header records.
+pub fn detect_ogg_sample_rate(path: &Path) -> Result<u32, FingerprintError> {
+    let packet = read_ident_packet(path)?;
+    sample_rate_of(path, codec_of(path, &packet)?, &packet)
+}
+
+fn sample_rate_of(path: &Path, codec: OggCodec, packet: &[u8]) -> Result<u32, FingerprintError> {
+    match codec {
+        // Packet type, "vorbis", u32 version, u8 channels, then the u32 rate
+        OggCodec::Vorbis => match packet.get(12..16) {
+            Some(&[a, b, c, d]) => Ok(u32::from_le_bytes([a, b, c, d])),
+            _ => Err(FingerprintError::decode(
+                path,
+                "Truncated identification header",
+            )),
+        },
+        OggCodec::Opus => Ok(48_000),
+    }
+}
+
+/// The first packet of an OGG file, which identifies the codec.
+fn read_ident_packet(path: &Path) -> Result<Vec<u8>, FingerprintError> {
+    read_ident_packet_from(open(path)?, path)
+}
+
+/// The first packet of the OGG container in `source`, with errors reported against `path`.
+fn read_ident_packet_from(
+    source: impl Read + Seek,
+    path: &Path,
+) -> Result<Vec<u8>, FingerprintError> {
+    use ogg::PacketReader;
+
+    let mut reader = PacketReader::new(source);
+    let packet = reader
+        .read_packet()
+        .map_err(|e| ogg_error(path, e))?
+        .ok_or_else(|| FingerprintError::decode(path, "Empty OGG container"))?;
+    Ok(packet.data)
+}
+
+fn codec_of(path: &Path, packet: &[u8]) -> Result<OggCodec, FingerprintError> {
+    if packet.starts_with(b"\x01vorbis") {
+        Ok(OggCodec::Vorbis)
+    } else if packet.starts_with(b"OpusHead") {
+        Ok(OggCodec::Opus)
+    } else {
+        let magic = &packet[..packet.len().min(8)];
+        Err(FingerprintError::unsupported_format(
+            path,
+            format!(
+                "codec with magic {:?} in OGG container, expected Vorbis or Opus",
+                String::from_utf8_lossy(magic)
+            ),
+        ))
+    }
+}
+
+/// File extensions `decode_to_mono_f32` can read, lowercase and without the dot.
+pub fn supported_extensions() -> &'static [&'static str] {
+    if cfg!(feature = "opus") {
+        &["ogg", "oga", "opus", "mp3", "flac", "wav"]
+    } else {
+        &["ogg", "oga", "mp3", "flac", "wav"]
+    }
+}
+
+/// Whether `path` looks decodable, so scanners can skip other files instead of failing on them.
+///
+/// Checks the extension, and with `probe` also reads the container header to confirm a codec
+/// this build can decode.
+pub fn can_decode(path: &Path, probe: bool) -> bool {
+    let extension_ok = path.extension().is_some_and(|ext| {
+        supported_extensions()
+            .iter()
+            .any(|supported| ext.eq_ignore_ascii_case(supported))
+    });
+    if !extension_ok {
+        return false;
+    }
+    if !probe {
+        return true;
+    }
+    if !is_ogg(path) {
+        return probe_symphonia(path).is_ok();
+    }
+    match detect_ogg_codec(path) {
+        Ok(OggCodec::Vorbis) => true,
+        Ok(OggCodec::Opus) => cfg!(feature = "opus"),
+        Err(_) => false,
+    }
+}
+
+/// Whether `path` has an OGG extension, and so is decoded with lewton or libopus rather than
+/// symphonia.
+pub fn is_ogg(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ["ogg", "oga", "opus"]
+            .iter()
+            .any(|ogg| ext.eq_ignore_ascii_case(ogg))
+    })
+}
+
+/// Decode an OGG, MP3, FLAC, or WAV file to raw mono f32 PCM, along with its sample rate.
+///
+/// Dispatches on the extension: OGG goes to `decode_ogg_to_mono_f32`, the rest to symphonia.
+/// Samples are on the same i16 scale whichever decoder reads them, so fingerprints of the
+/// same audio agree across formats.
+pub fn decode_to_mono_f32(path: &Path) -> Result<(Vec<f32>, u32), FingerprintError> {
+    decode_mono(path, &mut CorruptPackets::new(false))
+}
+
+/// Like `decode_to_mono_f32`, but packets that fail to decode are skipped as in
+/// `decode_ogg_to_mono_f32_lenient`. Also returns how many were skipped.
+pub fn decode_to_mono_f32_lenient(path: &Path) -> Result<(Vec<f32>, u32, usize), FingerprintError> {
+    let mut corrupt = CorruptPackets::new(true);
+    let (pcm, sample_rate) = decode_mono(path, &mut corrupt)?;
+    Ok((pcm, sample_rate, corrupt.skipped))
+}
+
+fn decode_mono(
+    path: &Path,
+    corrupt: &mut CorruptPackets,
+) -> Result<(Vec<f32>, u32), FingerprintError> {
+    if is_ogg(path) {
+        return decode_ogg_mono(path, corrupt);
+    }
+    let (sample_rate, layout) = probe_symphonia(path)?;
+    if layout.channels() > 2 {
+        warn!(
+            "Downmixing {} channels of {:?} to mono, its fingerprint may not match stereo sources",
+            layout.channels(),
+            path
+        );
+    }
+    let mut pcm = Vec::new();
+    decode_symphonia_frames(path, corrupt, &mut |frame| pcm.push(mono_of(frame)))?;
+    Ok((pcm, sample_rate))
+}
+
+/// The channel layout of any file `decode_to_mono_f32` can read.
+pub fn detect_channel_layout(path: &Path) -> Result<ChannelLayout, FingerprintError> {
+    if is_ogg(path) {
+        detect_ogg_channel_layout(path)
+    } else {
+        Ok(probe_symphonia(path)?.1)
+    }
+}
+
+/// The rate PCM decoded from any file `decode_to_mono_f32` can read comes out at.
+pub fn detect_sample_rate(path: &Path) -> Result<u32, FingerprintError> {
+    if is_ogg(path) {
+        detect_ogg_sample_rate(path)
+    } else {
+        Ok(probe_symphonia(path)?.0)
+    }
+}
+
+/// Decode an OGG file to raw mono f32 PCM, dispatching on the codec inside the container.
+/// Also returns the stream's sample rate, which the PCM is at: offsets in samples only turn
+/// into seconds with it, and fingerprints only match at the rate they were computed for.
+///
+/// Warns when downmixing more than two channels, since those rarely match stereo sources.
+pub fn decode_ogg_to_mono_f32(path: &Path) -> Result<(Vec<f32>, u32), FingerprintError> {
+    decode_ogg_mono(path, &mut CorruptPackets::new(false))
+}
+
+fn decode_ogg_mono(
+    path: &Path,
+    corrupt: &mut CorruptPackets,
+) -> Result<(Vec<f32>, u32), FingerprintError> {
+    let sample_rate = detect_ogg_sample_rate(path)?;
+    let layout = detect_ogg_channel_layout(path)?;
+    if layout.channels() > 2 {
+        warn!(
+            "Downmixing {} channels of {:?} to mono, its fingerprint may not match stereo sources",
+            layout.channels(),
+            path
+        );
+    }
+    let mut pcm = Vec::new();
+    decode_ogg_frames(path, corrupt, &mut |frame| pcm.push(mono_of(frame)))?;
+    Ok((pcm, sample_rate))
+}
+
+/// Like `decode_ogg_to_mono_f32`, but packets that fail to decode are skipped with a warning
+/// giving their position, rather than failing the whole file. Also returns how many were
+/// skipped, after the sample rate, so the caller can decide whether the file is too damaged to
+/// trust.
+///
+/// A few bad packets, as ripped files often have, cost a few tens of milliseconds each and
+/// barely dent a fingerprint. Read errors still fail, as does a long run of bad packets, which
+/// means the rest of the stream is lost.
+pub fn decode_ogg_to_mono_f32_lenient(
+    path: &Path,
+) -> Result<(Vec<f32>, u32, usize), FingerprintError> {
+    let mut corrupt = CorruptPackets::new(true);
+    let (pcm, sample_rate) = decode_ogg_mono(path, &mut corrupt)?;
+    Ok((pcm, sample_rate, corrupt.skipped))
+}
+
+/// Decode an OGG file to its mid (L+R)/2 and side (L−R)/2 channels.
+///
+/// Mid is exactly what `decode_ogg_to_mono_f32` returns for stereo. Files that aren't stereo
+/// have no side channel, so theirs comes back empty, with a warning for multichannel files.
+pub fn decode_ogg_to_mid_side_f32(path: &Path) -> Result<(Vec<f32>, Vec<f32>), FingerprintError> {
+    decode_mid_side(
+        path,
+        detect_ogg_channel_layout(path)?,
+        decode_ogg_frames,
+        &mut CorruptPackets::new(false),
+    )
+}
+
+/// Like `decode_ogg_to_mid_side_f32`, for any file `decode_to_mono_f32` can read.
+pub fn decode_to_mid_side_f32(path: &Path) -> Result<(Vec<f32>, Vec<f32>), FingerprintError> {
+    decode_mid_side(
+        path,
+        detect_channel_layout(path)?,
+        decode_frames,
+        &mut CorruptPackets::new(false),
+    )
+}
+
+/// Like `decode_to_mid_side_f32`, skipping packets that fail to decode as
+/// `decode_to_mono_f32_lenient` does. Also returns how many were skipped.
+pub fn decode_to_mid_side_f32_lenient(
+    path: &Path,
+) -> Result<(Vec<f32>, Vec<f32>, usize), FingerprintError> {
+    let mut corrupt = CorruptPackets::new(true);
+    let (mid, side) = decode_mid_side(
+        path,
+        detect_channel_layout(path)?,
+        decode_frames,
+        &mut corrupt,
+    )?;
+    Ok((mid, side, corrupt.skipped))
+}
+
+/// A decoder passing each multichannel sample frame of a file to a callback in turn.
+type DecodeFrames =
+    fn(&Path, &mut CorruptPackets, &mut dyn FnMut(&[f32])) -> Result<(), FingerprintError>;
+
+/// Decode `path` with `decode`, splitting its frames into mid and side going by `layout`.
+fn decode_mid_side(
+    path: &Path,
+    layout: ChannelLayout,
+    decode: DecodeFrames,
+    corrupt: &mut CorruptPackets,
+) -> Result<(Vec<f32>, Vec<f32>), FingerprintError> {
+    let mut mid = Vec::new();
+    let mut side = Vec::new();
+    match layout {
+        ChannelLayout::Stereo => decode(path, corrupt, &mut |frame| {
+            mid.push((frame[0] + frame[1]) / 2.0);
+            side.push((frame[0] - frame[1]) / 2.0);
+        })?,
+        _ => {
+            if layout.channels() > 2 {
+                warn!(
+                    "{:?} has {} channels, fingerprinting it without a side channel",
+                    path,
+                    layout.channels()
+                );
+            }
+            decode(path, corrupt, &mut |frame| mid.push(mono_of(frame)))?
+        }
+    }
+    Ok((mid, side))
+}
+
+/// Decode an OGG container held in memory to raw mono f32 PCM, along with the rate it decoded
+/// at. `name` stands in for a path in errors, e.g. the URL the bytes were downloaded from.
+///
+/// For audio that never touches the disk. Vorbis and Opus both need to seek within the
+/// container, so it has to be whole before decoding starts.
+pub fn decode_ogg_bytes_to_mono_f32(
+    bytes: &[u8],
+    name: &str,
+) -> Result<(Vec<f32>, u32), FingerprintError> {
+    let path = Path::new(name);
+    let packet = read_ident_packet_from(Cursor::new(bytes), path)?;
+    let codec = codec_of(path, &packet)?;
+    let sample_rate = sample_rate_of(path, codec, &packet)?;
+    let mut pcm = Vec::new();
+    let corrupt = &mut CorruptPackets::new(false);
+    let on_frame = &mut |frame: &[f32]| pcm.push(mono_of(frame));
+    match codec {
+        OggCodec::Vorbis => decode_vorbis_frames(Cursor::new(bytes), path, corrupt, on_frame)?,
+        OggCodec::Opus => decode_opus_frames(Cursor::new(bytes), path, corrupt, on_frame)?,
+    }
+    Ok((pcm, sample_rate))
+}
+
+/// Decode an OGG file, passing each multichannel sample frame to `on_frame` in turn.
+fn decode_ogg_frames(
+    path: &Path,
+    corrupt: &mut CorruptPackets,
+    on_frame: &mut dyn FnMut(&[f32]),
+) -> Result<(), FingerprintError> {
+    match detect_ogg_codec(path)? {
+        OggCodec::Vorbis => decode_vorbis_frames(open(path)?, path, corrupt, on_frame),
+        OggCodec::Opus => decode_opus_frames(open(path)?, path, corrupt, on_frame),
+    }
+}
+
+/// Decode any supported file, passing each multichannel sample frame to `on_frame` in turn.
+fn decode_frames(
+    path: &Path,
+    corrupt: &mut CorruptPackets,
+    on_frame: &mut dyn FnMut(&[f32]),
+) -> Result<(), FingerprintError> {
+    if is_ogg(path) {
+        decode_ogg_frames(path, corrupt, on_frame)
+    } else {
+        decode_symphonia_frames(path, corrupt, on_frame)
+    }
+}
+
+/// Bad packets in a row after which the rest of the stream is given up on
+const MAX_CONSECUTIVE_CORRUPT: usize = 64;
+
+/// Whether packets that fail to decode may be skipped, and how many have been.
+struct CorruptPackets {
+    skip: bool,
+    skipped: usize,
+    consecutive: usize,
+}
+
+impl CorruptPackets {
+    fn new(skip: bool) -> Self {
+        Self {
+            skip,
+            skipped: 0,
+            consecutive: 0,
+        }
+    }
+
+    /// Whether to carry on past a packet of `path` that failed with `error` at `position_secs`
+    /// into the audio, warning about it if so.
+    fn try_skip(&mut self, path: &Path, position_secs: f64, error: &dyn std::fmt::Debug) -> bool {
+        if !self.skip || self.consecutive >= MAX_CONSECUTIVE_CORRUPT {
+            return false;
+        }
+        warn!(
+            "Skipping corrupt packet in {:?} at ~{:.2} sec: {:?}",
+            path, position_secs, error
+        );
+        self.skipped += 1;
+        self.consecutive += 1;
+        true
+    }
+
+    fn decoded(&mut self) {
+        self.consecutive = 0;
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, FingerprintError> {
+    Ok(BufReader::new(File::open(path)?))
+}
+
+/// The average of every channel in a sample frame.
+fn mono_of(frame: &[f32]) -> f32 {
+    frame.iter().sum::<f32>() / frame.len() as f32
+}
+
+/// Decode an OGG/Vorbis file to raw mono f32 PCM (using i16 as intermediate).
+pub fn decode_vorbis_to_mono_f32(path: &Path) -> Result<Vec<f32>, FingerprintError> {
+    let mut pcm = Vec::new();
+    decode_vorbis_frames(
+        open(path)?,
+        path,
+        &mut CorruptPackets::new(false),
+        &mut |frame| pcm.push(mono_of(frame)),
+    )?;
+    Ok(pcm)
+}
+
+fn decode_vorbis_frames(
+    source: impl Read + Seek,
+    path: &Path,
+    corrupt: &mut CorruptPackets,
+    on_frame: &mut dyn FnMut(&[f32]),
+) -> Result<(), FingerprintError> {
+    use lewton::inside_ogg::OggStreamReader;
+
+    let mut ogg_reader = OggStreamReader::new(source).map_err(|e| vorbis_error(path, e))?;
+
+    let sample_rate = ogg_reader.ident_hdr.audio_sample_rate as f64;
+    let mut decoded_frames = 0;
+    let mut frame = Vec::new();
+    loop {
+        let packet = match ogg_reader.read_dec_packet_generic::<Vec<Vec<i16>>>() {
+            Ok(Some(packet)) => packet,
+            Ok(None) => break,
+            // Read errors mean the file itself can't be read, so there is nothing to skip to
+            Err(lewton::VorbisError::OggError(OggReadError::ReadError(e))) => return Err(e.into()),
+            Err(e) => {
+                if corrupt.try_skip(path, decoded_frames as f64 / sample_rate, &e) {
+                    continue;
+                }
+                return Err(vorbis_error(path, e));
+            }
+        };
+        corrupt.decoded();
+        let num_channels = packet.len();
+        if num_channels == 0 {
+            continue;
+        }
+        let samples_per_channel = packet[0].len();
+        decoded_frames += samples_per_channel;
+        for i in 0..samples_per_channel {
+            frame.clear();
+            frame.extend(packet.iter().map(|channel| channel[i] as f32));
+            on_frame(&frame);
+        }
+    }
+    Ok(())
+}
+
+/// Decode an OGG/Opus file to raw mono f32 PCM (using i16 as intermediate).
+///
+/// Opus always decodes at 48 kHz regardless of the input rate recorded in the header.
+pub fn decode_opus_to_mono_f32(path: &Path) -> Result<Vec<f32>, FingerprintError> {
+    let mut pcm = Vec::new();
+    decode_opus_frames(
+        open(path)?,
+        path,
+        &mut CorruptPackets::new(false),
+        &mut |frame| pcm.push(mono_of(frame)),
+    )?;
+    Ok(pcm)
+}
+
+#[cfg(feature = "opus")]
+fn decode_opus_frames(
+    source: impl Read + Seek,
+    path: &Path,
+    corrupt: &mut CorruptPackets,
+    on_frame: &mut dyn FnMut(&[f32]),
+) -> Result<(), FingerprintError> {
+    use ogg::PacketReader;
+    use opus::Channels;
+    use opus::Decoder;
+
+    // Largest Opus frame is 120 ms, which is 5760 samples per channel at 48 kHz
+    const MAX_FRAME_SAMPLES: usize = 5760;
+
+    let mut reader = PacketReader::new(source);
+
+    // First packet is the OpusHead identification header, second is OpusTags
+    let head = reader
+        .read_packet()
+        .map_err(|e| ogg_error(path, e))?
+        .ok_or_else(|| FingerprintError::decode(path, "Missing OpusHead"))?;
+    if head.data.len() < 19 {
+        return Err(FingerprintError::decode(path, "Truncated OpusHead"));
+    }
+    let num_channels = head.data[9] as usize;
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize;
+    let channels = match num_channels {
+        1 => Channels::Mono,
+        2 => Channels::Stereo,
+        n => {
+            return Err(FingerprintError::unsupported_format(
+                path,
+                format!("Opus with {} channels", n),
+            ));
+        }
+    };
+    reader
+        .read_packet()
+        .map_err(|e| ogg_error(path, e))?
+        .ok_or_else(|| FingerprintError::decode(path, "Missing OpusTags"))?;
+
+    let mut decoder =
+        Decoder::new(48_000, channels).map_err(|e| FingerprintError::decode(path, e))?;
+    let mut buffer = vec![0i16; MAX_FRAME_SAMPLES * num_channels];
+    let mut to_skip = pre_skip;
+
+    let mut decoded_frames = 0;
+    let mut frame = Vec::with_capacity(num_channels);
+    loop {
+        let position_secs = decoded_frames as f64 / 48_000.0;
+        let packet = match reader.read_packet() {
+            Ok(Some(packet)) => packet,
+            Ok(None) => break,
+            Err(OggReadError::ReadError(e)) => return Err(e.into()),
+            Err(e) => {
+                if corrupt.try_skip(path, position_secs, &e) {
+                    continue;
+                }
+                return Err(ogg_error(path, e));
+            }
+        };
+        let samples_per_channel = match decoder.decode(&packet.data, &mut buffer, false) {
+            Ok(samples_per_channel) => samples_per_channel,
+            Err(e) => {
+                if corrupt.try_skip(path, position_secs, &e) {
+                    continue;
+                }
+                return Err(FingerprintError::decode(path, e));
+            }
+        };
+        corrupt.decoded();
+        decoded_frames += samples_per_channel;
+        let skip = to_skip.min(samples_per_channel);
+        to_skip -= skip;
+        let decoded = &buffer[..samples_per_channel * num_channels];
+        for samples in decoded.chunks_exact(num_channels).skip(skip) {
+            frame.clear();
+            frame.extend(samples.iter().map(|&sample| sample as f32));
+            on_frame(&frame);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "opus"))]
+fn decode_opus_frames(
+    _source: impl Read + Seek,
+    path: &Path,
+    _corrupt: &mut CorruptPackets,
+    _on_frame: &mut dyn FnMut(&[f32]),
+) -> Result<(), FingerprintError> {
+    Err(FingerprintError::unsupported_format(
+        path,
+        "Opus support was not compiled in (enable the `opus` feature)",
+    ))
+}
+
+/// Open `path` with symphonia, hinting at the format from its extension, and find the first
+/// track with audio in it.
+fn open_symphonia(
+    path: &Path,
+) -> Result<(Box<dyn symphonia::core::formats::FormatReader>, u32), FingerprintError> {
+    use symphonia::core::codecs::CODEC_TYPE_NULL;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::probe::Hint;
+
+    let source = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let probed = symphonia::default::get_probe()
+        .format(&hint, source, &Default::default(), &Default::default())
+        .map_err(|e| symphonia_error(path, e))?;
+    let track_id = probed
+        .format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| FingerprintError::decode(path, "No audio track"))?
+        .id;
+    Ok((probed.format, track_id))
+}
+
+/// The sample rate and channel layout symphonia reads from the header of `path`.
+fn probe_symphonia(path: &Path) -> Result<(u32, ChannelLayout), FingerprintError> {
+    let (format, track_id) = open_symphonia(path)?;
+    let params = &format
+        .tracks()
+        .iter()
+        .find(|track| track.id == track_id)
+        .ok_or_else(|| FingerprintError::decode(path, "No audio track"))?
+        .codec_params;
+    let sample_rate = params
+        .sample_rate
+        .ok_or_else(|| FingerprintError::decode(path, "Header has no sample rate"))?;
+    let channels = params
+        .channels
+        .ok_or_else(|| FingerprintError::decode(path, "Header has no channel layout"))?
+        .count();
+    Ok((sample_rate, ChannelLayout::from_channels(channels as u8)))
+}
+
+/// Decode an MP3, FLAC, or WAV file with symphonia, passing each multichannel sample frame to
+/// `on_frame` in turn. Samples go through i16 to match the scale of the OGG decoders.
+fn decode_symphonia_frames(
+    path: &Path,
+    corrupt: &mut CorruptPackets,
+    on_frame: &mut dyn FnMut(&[f32]),
+) -> Result<(), FingerprintError> {
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::errors::Error;
+
+    let (mut format, track_id) = open_symphonia(path)?;
+    let params = format
+        .tracks()
+        .iter()
+        .find(|track| track.id == track_id)
+        .ok_or_else(|| FingerprintError::decode(path, "No audio track"))?
+        .codec_params
+        .clone();
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&params, &Default::default())
+        .map_err(|e| symphonia_error(path, e))?;
+    let sample_rate = params.sample_rate.unwrap_or(48_000) as f64;
+
+    let mut decoded_frames = 0;
+    let mut samples: Option<SampleBuffer<i16>> = None;
+    let mut frame = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // Symphonia signals the end of the stream as an unexpected EOF
+            Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(symphonia_error(path, e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(Error::DecodeError(e)) => {
+                if corrupt.try_skip(path, decoded_frames as f64 / sample_rate, &e) {
+                    continue;
+                }
+                return Err(FingerprintError::decode(path, e));
+            }
+            Err(e) => return Err(symphonia_error(path, e)),
+        };
+        corrupt.decoded();
+        let num_channels = decoded.spec().channels.count();
+        if num_channels == 0 || decoded.frames() == 0 {
+            continue;
+        }
+        let buffer = match &mut samples {
+            Some(buffer) if buffer.capacity() >= decoded.frames() * num_channels => buffer,
+            _ => samples.insert(SampleBuffer::new(
+                decoded.capacity() as u64,
+                *decoded.spec(),
+            )),
+        };
+        buffer.copy_interleaved_ref(decoded);
+        for samples in buffer.samples().chunks_exact(num_channels) {
+            frame.clear();
+            frame.extend(samples.iter().map(|&sample| sample as f32));
+            on_frame(&frame);
+        }
+        decoded_frames += buffer.samples().len() / num_channels;
+    }
+    Ok(())
+}
+
+fn symphonia_error(path: &Path, error: symphonia::core::errors::Error) -> FingerprintError {
+    use symphonia::core::errors::Error;
+
+    match error {
+        Error::IoError(e) => FingerprintError::Io(e),
+        Error::Unsupported(what) => FingerprintError::unsupported_format(path, what),
+        e => FingerprintError::decode(path, e),
+    }
+}
+
+/// Surface read failures from the OGG layer as IO errors, anything else as a decode error.
+fn ogg_error(path: &Path, error: OggReadError) -> FingerprintError {
+    match error {
+        OggReadError::ReadError(e) => FingerprintError::Io(e),
+        e => FingerprintError::decode(path, e),
+    }
+}
+
+fn vorbis_error(path: &Path, error: lewton::VorbisError) -> FingerprintError {
+    match error {
+        lewton::VorbisError::OggError(e) => ogg_error(path, e),
+        e => FingerprintError::decode(path, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint_config::FingerprintConfig;
+    use crate::fingerprint_file::fingerprint_file;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+
+    /// Samples per FLAC frame, which the header codes as 0b1100
+    const BLOCK_SIZE: usize = 4096;
+
+    /// MSB-first CRC of `bytes` with `poly`, as FLAC frames use with 8 and 16 bits.
+    fn crc(bytes: &[u8], poly: u16, bits: u32) -> u16 {
+        let top = 1 << (bits - 1);
+        let mask = ((1u32 << bits) - 1) as u16;
+        bytes.iter().fold(0u16, |mut crc, &byte| {
+            crc ^= (byte as u16) << (bits - 8);
+            for _ in 0..8 {
+                let shifted = crc << 1;
+                crc = if crc & top != 0 {
+                    shifted ^ poly
+                } else {
+                    shifted
+                } & mask;
+            }
+            crc
+        })
+    }
+
+    /// Write `samples` to `path` as 16-bit mono FLAC at `SAMPLE_RATE`, one uncompressed frame
+    /// per `BLOCK_SIZE` samples. Frame `corrupt` gets a reserved subframe type, under a valid
+    /// CRC, so it parses but fails to decode.
+    fn write_flac(path: &Path, samples: &[i16], corrupt: usize) {
+        assert_eq!(samples.len() % BLOCK_SIZE, 0);
+        let mut bytes = b"fLaC".to_vec();
+        // The last metadata block, STREAMINFO, 34 bytes long
+        bytes.extend([0x80, 0, 0, 34]);
+        bytes.extend((BLOCK_SIZE as u16).to_be_bytes());
+        bytes.extend((BLOCK_SIZE as u16).to_be_bytes());
+        // Unknown frame sizes
+        bytes.extend([0; 6]);
+        let rate_channels_bits_total =
+            (SAMPLE_RATE as u64) << 44 | (16 - 1) << 36 | samples.len() as u64;
+        bytes.extend(rate_channels_bits_total.to_be_bytes());
+        // No MD5
+        bytes.extend([0; 16]);
+
+        for (index, block) in samples.chunks(BLOCK_SIZE).enumerate() {
+            // Sync code with fixed block sizes, 4096 samples at 22.05 kHz, mono 16-bit, and
+            // the frame number, which fits in one byte of its UTF-8 coding
+            let mut frame = vec![0xff, 0xf8, 0xc6, 0x08, index as u8];
+            frame.push(crc(&frame, 0x07, 8) as u8);
+            // A verbatim subframe, unless a reserved type
+            frame.push(if index == corrupt { 0x04 } else { 0x02 });
+            frame.extend(block.iter().flat_map(|sample| sample.to_be_bytes()));
+            frame.extend(crc(&frame, 0x8005, 16).to_be_bytes());
+            bytes.extend(frame);
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn lenient_decoding_skips_a_corrupt_packet() {
+        let samples: Vec<i16> = noise(2.0, 0)[..10 * BLOCK_SIZE]
+            .iter()
+            .map(|sample| (sample * i16::MAX as f32) as i16)
+            .collect();
+        let path =
+            std::env::temp_dir().join(format!("phantasy-corrupt-{}.flac", std::process::id()));
+        write_flac(&path, &samples, 3);
+
+        let strict = decode_to_mono_f32(&path);
+        let lenient = decode_to_mono_f32_lenient(&path);
+        let lenient_config = FingerprintConfig {
+            skip_corrupt_packets: true,
+            ..FingerprintConfig::default()
+        };
+        let fingerprinted = [FingerprintConfig::default(), lenient_config]
+            .map(|config| fingerprint_file(&path, &config).map(|(_, info)| info));
+        let _ = std::fs::remove_file(&path);
+
+        assert!(
+            matches!(strict, Err(FingerprintError::Decode { .. })),
+            "{strict:?}"
+        );
+        let (pcm, sample_rate, skipped) = lenient.unwrap();
+        assert_eq!(skipped, 1);
+        assert_eq!(sample_rate, SAMPLE_RATE as u32);
+        // Everything but the bad frame, in order
+        let expected: Vec<f32> = samples[..3 * BLOCK_SIZE]
+            .iter()
+            .chain(&samples[4 * BLOCK_SIZE..])
+            .map(|&sample| sample as f32)
+            .collect();
+        assert_eq!(pcm, expected);
+
+        let [strict, lenient] = fingerprinted;
+        assert!(strict.is_err());
+        let info = lenient.unwrap();
+        assert_eq!(
+            info.duration,
+            std::time::Duration::from_secs_f64((9 * BLOCK_SIZE) as f64 / SAMPLE_RATE as f64)
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use crate::decode::decode_ogg_bytes_to_mono_f32;
+use crate::fingerprint_error::FingerprintError;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tracing::warn;
+
+/// Streams longer than this are still decoded, but with a warning, since they are held in
+/// memory whole. About ten minutes of high quality Vorbis
+pub const LARGE_STREAM_BYTES: usize = 16 * 1024 * 1024;
+
+/// Bytes read from the source at a time
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Decode an OGG container arriving from `source`, e.g. a download in progress, to raw mono
+/// f32 PCM, along with the rate it decoded at. `name` stands in for a path in errors.
+///
+/// The OGG readers seek within the container, so the stream is buffered in memory until it
+/// ends, with a warning once it passes `LARGE_STREAM_BYTES`. Decoding then runs on tokio's
+/// blocking pool, so this must be called from within a runtime.
+pub async fn decode_ogg_stream_to_mono_f32(
+    mut source: impl AsyncRead + Unpin,
+    name: &str,
+) -> Result<(Vec<f32>, u32), FingerprintError> {
+    let mut bytes = Vec::new();
+    let mut chunk = vec![0; READ_CHUNK];
+    let mut warned = false;
+    loop {
+        let read = source.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+        if !warned && bytes.len() > LARGE_STREAM_BYTES {
+            warn!(
+                "{} is over {} MiB, buffering all of it in memory to decode",
+                name,
+                LARGE_STREAM_BYTES / (1024 * 1024)
+            );
+            warned = true;
+        }
+    }
+
+    let name = name.to_owned();
+    tokio::task::spawn_blocking(move || decode_ogg_bytes_to_mono_f32(&bytes, &name))
+        .await
+        .map_err(std::io::Error::other)?
+}
@@ -0,0 +1,40 @@
+use crate::find_matches::OffsetBin;
+use serde::Serialize;
+use std::io::Write;
+
+/// An offset histogram labelled with what was matched against what, ready for plotting.
+#[derive(Debug, Clone, Serialize)]
+pub struct OffsetHistogram<'a> {
+    /// Identifies the snippet, e.g. the sample path and its begin/end seconds
+    pub snippet: &'a str,
+    /// Identifies the track, e.g. its path
+    pub track: &'a str,
+    /// Every voted offset, as returned by `offset_histogram`
+    pub bins: &'a [OffsetBin],
+}
+
+impl OffsetHistogram<'_> {
+    /// Write the histogram as CSV with `offset_sec,count,rank` columns.
+    ///
+    /// The snippet and track identifiers go in leading `#` comment lines, which gnuplot skips
+    /// and pandas reads with `comment="#"`.
+    pub fn write_csv(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "# snippet: {}", self.snippet)?;
+        writeln!(writer, "# track: {}", self.track)?;
+        writeln!(writer, "offset_sec,count,rank")?;
+        for bin in self.bins {
+            writeln!(writer, "{},{},{}", bin.offset_sec, bin.count, bin.rank)?;
+        }
+        writer.flush()
+    }
+
+    /// Write the histogram as a JSON object with `snippet`, `track`, and `bins` fields.
+    #[cfg(feature = "io")]
+    pub fn write_json(
+        &self,
+        writer: impl Write,
+    ) -> Result<(), crate::fingerprint_error::FingerprintError> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use crate::fingerprint_data::FingerprintData;
+use crate::fingerprint_error::FingerprintError;
+use arrow_array::RecordBatch;
+use arrow_array::StringArray;
+use arrow_array::UInt16Array;
+use arrow_array::UInt32Array;
+use arrow_schema::DataType;
+use arrow_schema::Field;
+use arrow_schema::Schema;
+use parquet::arrow::ArrowWriter;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Write the pairs of every fingerprint in `library` to a Parquet file at `path`, one row per
+/// pair, for querying hash distributions from pandas, polars, or DuckDB.
+///
+/// Columns are `track_id`, `f1`, `f2`, `delta_t`, and `anchor_time`. Each track is written as
+/// its own record batch, so memory stays bounded by the largest fingerprint.
+pub fn export_parquet<'a>(
+    library: impl IntoIterator<Item = (&'a str, &'a FingerprintData)>,
+    path: &Path,
+) -> Result<(), FingerprintError> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("track_id", DataType::Utf8, false),
+        Field::new("f1", DataType::UInt16, false),
+        Field::new("f2", DataType::UInt16, false),
+        Field::new("delta_t", DataType::UInt16, false),
+        Field::new("anchor_time", DataType::UInt32, false),
+    ]));
+
+    let file = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, Arc::clone(&schema), None)?;
+    for (track_id, fp) in library {
+        let pairs = &fp.pairs;
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(StringArray::from(vec![track_id; pairs.len()])),
+                Arc::new(UInt16Array::from_iter_values(pairs.iter().map(|p| p.f1))),
+                Arc::new(UInt16Array::from_iter_values(pairs.iter().map(|p| p.f2))),
+                Arc::new(UInt16Array::from_iter_values(
+                    pairs.iter().map(|p| p.delta_t),
+                )),
+                Arc::new(UInt32Array::from_iter_values(
+                    pairs.iter().map(|p| p.anchor_time),
+                )),
+            ],
+        )?;
+        writer.write(&batch)?;
+    }
+    writer.close()?;
+    Ok(())
+}
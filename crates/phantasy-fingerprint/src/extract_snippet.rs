@@ -0,0 +1,37 @@
+/// Extract snippet from PCM given time range in seconds.
+pub fn extract_snippet(pcm: &[f32], sr: f32, begin: f32, end: f32) -> &[f32] {
+    let start_idx = (begin * sr).round() as usize;
+    let end_idx = (end * sr).round() as usize;
+    let start_idx = start_idx.min(pcm.len());
+    let end_idx = end_idx.min(pcm.len());
+    &pcm[start_idx..end_idx]
+}
+
+/// Extract snippet like `extract_snippet`, fading `fade_sec` in and out at the edges.
+///
+/// A hard cut away from a zero crossing is a broadband click, which shows up as spurious peaks
+/// in the first and last frames of the snippet's fingerprint.
+pub fn extract_snippet_with_fade(
+    pcm: &[f32],
+    sr: f32,
+    begin: f32,
+    end: f32,
+    fade_sec: f32,
+) -> Vec<f32> {
+    let mut snippet = extract_snippet(pcm, sr, begin, end).to_vec();
+    apply_fade(&mut snippet, (fade_sec * sr).round().max(0.0) as usize);
+    snippet
+}
+
+/// Apply a raised-cosine fade of `fade_len` samples to both ends of `samples`.
+///
+/// The fades are shortened to half of `samples` each so they never overlap.
+pub fn apply_fade(samples: &mut [f32], fade_len: usize) {
+    let fade_len = fade_len.min(samples.len() / 2);
+    let len = samples.len();
+    for i in 0..fade_len {
+        let gain = 0.5 - 0.5 * (std::f32::consts::PI * (i as f32 + 0.5) / fade_len as f32).cos();
+        samples[i] *= gain;
+        samples[len - 1 - i] *= gain;
+    }
+}
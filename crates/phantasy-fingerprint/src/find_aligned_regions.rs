@@ -0,0 +1,256 @@
+use crate::compute_fingerprint::TARGET_ZONE_FRAMES;
+use crate::find_matches::MAX_P_VALUE;
+use crate::find_matches::MIN_MATCH_COUNT;
+use crate::find_matches::frames_per_sec;
+use crate::find_matches::offset_p_value;
+use crate::fingerprint_data::FPHashEntry;
+use crate::fingerprint_data::FingerprintData;
+use crate::fingerprint_index::FingerprintIndex;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+/// Offsets (in frames) this close together are considered the same alignment
+const OFFSET_TOLERANCE_FRAMES: i32 = 2;
+/// Votes a query frame needs on the winning offset to count as part of the shared region
+const MIN_VOTES_PER_FRAME: usize = 2;
+
+/// How `find_aligned_regions` slices the query into windows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlignedRegionsConfig {
+    /// Length of each query window matched independently, in seconds
+    pub query_window_secs: f32,
+    /// Distance between the starts of consecutive query windows, in seconds.
+    /// Smaller hops find shorter shared segments but cost more.
+    pub query_hop_secs: f32,
+}
+
+impl Default for AlignedRegionsConfig {
+    fn default() -> Self {
+        Self {
+            query_window_secs: 10.0,
+            query_hop_secs: 5.0,
+        }
+    }
+}
+
+/// A stretch of the query that lines up with a stretch of the track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlignedRegion {
+    pub query_start_sec: f32,
+    pub query_end_sec: f32,
+    pub track_start_sec: f32,
+    pub track_end_sec: f32,
+    /// Collision count of the strongest window in the region
+    pub count: usize,
+}
+
+/// Find every region where `query_fp` overlaps `track_fp`, for matching two long recordings.
+///
+/// The query is split into overlapping windows that are each matched on their own, then
+/// consecutive windows agreeing on an offset are merged into a single region. As in
+/// `find_matches`, side pairs only collide with side pairs.
+pub fn find_aligned_regions(
+    query_fp: &FingerprintData,
+    track_fp: &FingerprintData,
+    sample_rate: usize,
+    config: &AlignedRegionsConfig,
+) -> Vec<AlignedRegion> {
+    let frames_per_sec = frames_per_sec(sample_rate);
+    let window_frames = (config.query_window_secs * frames_per_sec).round().max(1.0) as u32;
+    let hop_frames = (config.query_hop_secs * frames_per_sec).round().max(1.0) as u32;
+
+    let track_index = FingerprintIndex::from_fingerprint(track_fp);
+    let track_span = track_fp
+        .pairs
+        .iter()
+        .chain(&track_fp.side_pairs)
+        .map(|p| p.anchor_time as usize + 1)
+        .max()
+        .unwrap_or(0);
+
+    // Best (first frame, end frame, offset, count) of every query window that matched, in
+    // query order. The frames bound the part of the window that actually shares content.
+    let query_pairs: Vec<(&FPHashEntry, bool)> = query_fp
+        .pairs
+        .iter()
+        .map(|p| (p, false))
+        .chain(query_fp.side_pairs.iter().map(|p| (p, true)))
+        .collect();
+    let query_end = query_pairs
+        .iter()
+        .map(|(p, _)| p.anchor_time)
+        .max()
+        .unwrap_or(0);
+    // Every window spans the full length, the last ending with the query, as a window cut
+    // short by the end has so few chance collisions that a handful look significant
+    let last_start = (query_end + 1).saturating_sub(window_frames);
+    let window_starts = (0..last_start)
+        .step_by(hop_frames as usize)
+        .chain(std::iter::once(last_start));
+    let mut windows: Vec<(u32, u32, i32, usize)> = Vec::new();
+    for window_start in window_starts {
+        let in_window =
+            |anchor: u32| (window_start..window_start + window_frames).contains(&anchor);
+        let mut offset_count: HashMap<i32, usize> = HashMap::new();
+        let window_pairs = || query_pairs.iter().filter(|(p, _)| in_window(p.anchor_time));
+        for &(query_ent, side) in window_pairs() {
+            let key = (query_ent.f1, query_ent.f2, query_ent.delta_t);
+            for &(_, track_anchor_time) in track_index.get(&key, side) {
+                let diff = track_anchor_time as i32 - query_ent.anchor_time as i32;
+                *offset_count.entry(diff).or_insert(0) += 1;
+            }
+        }
+
+        let Some((&offset, &count)) = offset_count.iter().max_by_key(|(_, c)| **c) else {
+            continue;
+        };
+        let p_value = offset_p_value(
+            count,
+            offset_count
+                .iter()
+                .filter(|(other, _)| **other != offset)
+                .map(|(_, count)| *count),
+            track_span + window_frames as usize,
+        );
+        // Long windows collide by chance on many offsets, so as in `find_matches` the best one
+        // must also be unlikely to be chance
+        if count <= MIN_MATCH_COUNT || p_value > MAX_P_VALUE {
+            continue;
+        }
+
+        // Chance collisions land on the winning offset too, but only truly shared frames
+        // collect several votes each, so those bound the region
+        let mut votes_per_frame: BTreeMap<u32, usize> = BTreeMap::new();
+        for &(query_ent, side) in window_pairs() {
+            let key = (query_ent.f1, query_ent.f2, query_ent.delta_t);
+            let votes = track_index
+                .get(&key, side)
+                .iter()
+                .filter(|&&(_, track_anchor_time)| {
+                    track_anchor_time as i32 - query_ent.anchor_time as i32 == offset
+                })
+                .count();
+            *votes_per_frame.entry(query_ent.anchor_time).or_insert(0) += votes;
+        }
+        // A stray frame can still collect a few, so keep the run of shared frames with the most
+        // votes, splitting runs where frames lie further apart than a pair can reach
+        let mut runs: Vec<(u32, u32, usize)> = Vec::new();
+        for (frame, votes) in votes_per_frame {
+            if votes < MIN_VOTES_PER_FRAME {
+                continue;
+            }
+            match runs.last_mut() {
+                Some(run) if frame - run.1 <= TARGET_ZONE_FRAMES as u32 => {
+                    run.1 = frame;
+                    run.2 += votes;
+                }
+                _ => runs.push((frame, frame, votes)),
+            }
+        }
+        let Some(&(first, last, _)) = runs.iter().max_by_key(|run| run.2) else {
+            continue;
+        };
+        windows.push((first, last + 1, offset, count));
+    }
+
+    // Merge overlapping windows that agree on the offset, keeping the strongest window's offset
+    let mut merged: Vec<(u32, u32, i32, usize)> = Vec::new();
+    for (start, end, offset, count) in windows {
+        match merged.last_mut() {
+            Some(last)
+                if start <= last.1 + hop_frames
+                    && (offset - last.2).abs() <= OFFSET_TOLERANCE_FRAMES =>
+            {
+                last.0 = last.0.min(start);
+                last.1 = last.1.max(end);
+                if count > last.3 {
+                    last.2 = offset;
+                    last.3 = count;
+                }
+            }
+            _ => merged.push((start, end, offset, count)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(start, end, offset, count)| AlignedRegion {
+            query_start_sec: start as f32 / frames_per_sec,
+            query_end_sec: end as f32 / frames_per_sec,
+            track_start_sec: (start as i32 + offset) as f32 / frames_per_sec,
+            track_end_sec: (end as i32 + offset) as f32 / frames_per_sec,
+            count,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_fingerprint::compute_fingerprint;
+    use crate::compute_fingerprint::compute_fingerprint_mid_side;
+    use crate::fingerprint_config::FingerprintConfig;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+
+    #[test]
+    fn side_pairs_find_regions_the_mid_channel_misses() {
+        // The mid channels have nothing in common, the side channels share 8 seconds
+        let shared = noise(8.0, 0);
+        let side = |seed| [noise(10.0, seed), shared.clone(), noise(10.0, seed + 1)].concat();
+        let config = FingerprintConfig::default();
+        let query_fp =
+            compute_fingerprint_mid_side(&noise(28.0, 10), &side(20), SAMPLE_RATE, &config)
+                .unwrap();
+        let track_fp =
+            compute_fingerprint_mid_side(&noise(28.0, 11), &side(30), SAMPLE_RATE, &config)
+                .unwrap();
+
+        let regions = find_aligned_regions(
+            &query_fp,
+            &track_fp,
+            SAMPLE_RATE,
+            &AlignedRegionsConfig::default(),
+        );
+        assert_eq!(regions.len(), 1, "{:?}", regions);
+        let region = &regions[0];
+        assert_eq!(region.query_start_sec, region.track_start_sec);
+        assert!(
+            (region.query_start_sec - 10.0).abs() < 0.25
+                && (region.query_end_sec - 18.0).abs() < 0.25,
+            "{region:?}"
+        );
+    }
+
+    #[test]
+    fn known_overlap_is_found_with_its_span_in_both_files() {
+        // 8 seconds shared, at 12 s into the query and 5 s into the track
+        let shared = noise(8.0, 0);
+        let query = [noise(12.0, 1), shared.clone(), noise(10.0, 2)].concat();
+        let track = [noise(5.0, 3), shared, noise(15.0, 4)].concat();
+        let query_fp = compute_fingerprint(&query, SAMPLE_RATE).unwrap();
+        let track_fp = compute_fingerprint(&track, SAMPLE_RATE).unwrap();
+
+        for config in [
+            AlignedRegionsConfig::default(),
+            AlignedRegionsConfig {
+                query_window_secs: 4.0,
+                query_hop_secs: 1.0,
+            },
+        ] {
+            let regions = find_aligned_regions(&query_fp, &track_fp, SAMPLE_RATE, &config);
+            assert_eq!(regions.len(), 1, "{config:?}: {regions:?}");
+            let region = &regions[0];
+            // Frames straddling either edge of the overlap, and pairs reaching past its end,
+            // can't collide, so allow a target zone's worth either side
+            let close = |actual: f32, expected: f32| (actual - expected).abs() < 0.25;
+            assert!(
+                close(region.query_start_sec, 12.0)
+                    && close(region.query_end_sec, 20.0)
+                    && close(region.track_start_sec, 5.0)
+                    && close(region.track_end_sec, 13.0),
+                "{config:?}: {region:?}"
+            );
+        }
+    }
+}
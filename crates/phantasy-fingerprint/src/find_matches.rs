@@ -0,0 +1,411 @@
+use crate::compute_fingerprint::HOP_SIZE;
+use crate::fingerprint_data::FPHashEntry;
+use crate::fingerprint_data::FingerprintData;
+use crate::match_config::MatchConfig;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The outcome of matching a snippet against a single track.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchResult {
+    /// Where the snippet begins within the track, in seconds
+    pub offset_sec: f32,
+    /// How many hash collisions agreed on that offset
+    pub count: usize,
+    /// The chance that some offset would collect `count` votes by coincidence alone, given how
+    /// many collisions the track had overall. Lower is more significant, and unlike `count` it
+    /// is comparable between long and short tracks
+    pub p_value: f64,
+    /// The fraction of the snippet, in `COHERENCE_SLICES` equal slices of its anchor span, where
+    /// at least one collision voted for the winning offset. A true match is supported throughout
+    /// the snippet, while coincidental collisions tend to bunch up around one or two frames
+    pub coherence: f32,
+    /// The collisions that voted for the winning offset, only collected in explain mode
+    pub supporting_pairs: Option<Vec<SupportingPair>>,
+    /// How many segments of the snippet matched at this offset on their own, when matched with
+    /// `find_matches_segmented`. More agreeing segments make a match more robust to noise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agreeing_segments: Option<usize>,
+}
+
+/// One bar of the offset histogram built while matching.
+#[derive(Debug, Clone, Serialize)]
+pub struct OffsetBin {
+    /// Where the snippet would begin within the track, in seconds
+    pub offset_sec: f32,
+    /// How many hash collisions voted for this offset
+    pub count: usize,
+    /// Position among all offsets when sorted by count, starting at 1 for the best
+    pub rank: usize,
+}
+
+/// Matches less significant than this are discarded, see [`MatchResult::p_value`].
+pub const MAX_P_VALUE: f64 = 1e-3;
+
+/// Matches with this many agreeing collisions or fewer are discarded however significant.
+pub const MIN_MATCH_COUNT: usize = 5;
+
+/// Matches supported in less than this fraction of the snippet are discarded however many
+/// collisions they have, see [`MatchResult::coherence`].
+pub const MIN_COHERENCE: f32 = 0.3;
+
+/// How many slices the snippet is divided into when measuring [`MatchResult::coherence`].
+pub const COHERENCE_SLICES: usize = 10;
+
+/// A single hash collision that voted for the winning offset.
+#[derive(Debug, Clone, Serialize)]
+pub struct SupportingPair {
+    /// Anchor frame of the pair within the snippet
+    pub snippet_anchor: u32,
+    /// Anchor frame of the pair within the track
+    pub track_anchor: u32,
+    /// The shared (f1, f2, delta_t) hash
+    pub hash: (u16, u16, u16),
+    /// Whether the pairs came from the side channel rather than the mid (or mono) channel
+    pub side: bool,
+}
+
+/// What matching a snippet against a track concluded, see [`MatchConfig`] for where the lines
+/// between the outcomes fall.
+#[derive(Debug, Clone, Serialize)]
+pub enum MatchOutcome {
+    /// No offset collected enough collisions to be worth a guess
+    NoMatch,
+    /// No offset is a confident match, but these are plausible, best first
+    Ambiguous { candidates: Vec<MatchResult> },
+    /// The best offset is a confident match
+    Match(MatchResult),
+}
+
+impl MatchOutcome {
+    /// The confident match, if there is one, dropping any guesses.
+    pub fn into_match(self) -> Option<MatchResult> {
+        match self {
+            MatchOutcome::Match(result) => Some(result),
+            MatchOutcome::NoMatch | MatchOutcome::Ambiguous { .. } => None,
+        }
+    }
+}
+
+/// See how many collisions `track_fp` has with `snippet_fp`.
+///
+/// When `search_window` is given as `(begin, end)` seconds, only track anchors inside it are
+/// considered. When `explain` is set, the collisions supporting the best offset are returned too.
+///
+/// The best offset must have more than `MIN_MATCH_COUNT` collisions, a p-value of at most
+/// `MAX_P_VALUE`, and a coherence of at least `MIN_COHERENCE` to count as a match. Use
+/// `find_match_outcome` to hear about near misses too.
+pub fn find_matches(
+    track_fp: &FingerprintData,
+    snippet_fp: &FingerprintData,
+    sample_rate: usize,
+    search_window: Option<(f32, f32)>,
+    explain: bool,
+) -> Option<MatchResult> {
+    find_match_outcome(
+        track_fp,
+        snippet_fp,
+        sample_rate,
+        search_window,
+        explain,
+        &MatchConfig::default(),
+    )
+    .into_match()
+}
+
+/// Like `find_matches`, but telling a snippet that clearly isn't in the track apart from one
+/// with several plausible offsets, none of them confident, using the thresholds in `config`.
+pub fn find_match_outcome(
+    track_fp: &FingerprintData,
+    snippet_fp: &FingerprintData,
+    sample_rate: usize,
+    search_window: Option<(f32, f32)>,
+    explain: bool,
+    config: &MatchConfig,
+) -> MatchOutcome {
+    // Each "time step" in the spectrogram corresponds to `hop_size / sample_rate` seconds.
+    // (We used hop_size=512 in the fingerprint)
+    let frames_per_sec = frames_per_sec(sample_rate);
+
+    // 1) Map (f1, f2, delta_t) -> list of anchor_times for the track, per channel
+    let track_maps = TrackMaps::build(track_fp, frames_per_sec, search_window);
+
+    // 2) For each snippet hash, check collisions
+    //    We'll compute an "offset difference" = track_anchor_time - snippet_anchor_time
+    //    The best match is the offset that appears the most frequently
+    let offset_count = count_offsets(&track_maps, snippet_fp);
+
+    // 3) Rank offsets by collisions, breaking ties by offset so the outcome is stable
+    let mut ranked: Vec<(i32, usize)> = offset_count.iter().map(|(&o, &c)| (o, c)).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    // A longer track collides more often by chance, so judge an offset against how the votes
+    // for every other possible offset are spread
+    let n_offsets = anchor_span(track_maps.mid.values().flatten().copied())
+        + anchor_span(snippet_fp.pairs.iter().map(|p| p.anchor_time));
+    let p_value_of = |offset: i32, count: usize| {
+        offset_p_value(
+            count,
+            offset_count
+                .iter()
+                .filter(|(other, _)| **other != offset)
+                .map(|(_, count)| *count),
+            n_offsets,
+        )
+    };
+
+    // 4) Walk the snippet again to find the collisions behind an offset, and check they are
+    //    spread through it rather than bunched in one place
+    let result_for = |offset: i32, count: usize, p_value: f64| {
+        let pairs = supporting_pairs(&track_maps, snippet_fp, offset);
+        MatchResult {
+            offset_sec: offset as f32 / frames_per_sec,
+            count,
+            p_value,
+            coherence: coherence(snippet_fp, &pairs),
+            supporting_pairs: explain.then_some(pairs),
+            agreeing_segments: None,
+        }
+    };
+
+    let Some(&(best_offset, best_count)) = ranked.first() else {
+        return MatchOutcome::NoMatch;
+    };
+    let best_p_value = p_value_of(best_offset, best_count);
+    if best_count > config.min_count && best_p_value <= config.max_p_value {
+        let best = result_for(best_offset, best_count, best_p_value);
+        if best.coherence >= config.min_coherence {
+            return MatchOutcome::Match(best);
+        }
+    }
+
+    // 5) Short of a match, offer the best offsets that are still worth a guess
+    let candidates: Vec<MatchResult> = ranked
+        .iter()
+        .take(config.max_candidates)
+        .filter(|(_, count)| *count >= config.ambiguous_min_count)
+        .filter_map(|&(offset, count)| {
+            let p_value = p_value_of(offset, count);
+            (p_value <= config.ambiguous_max_p_value).then(|| result_for(offset, count, p_value))
+        })
+        .collect();
+    if candidates.is_empty() {
+        MatchOutcome::NoMatch
+    } else {
+        MatchOutcome::Ambiguous { candidates }
+    }
+}
+
+/// The collisions between the track and `snippet_fp` that voted for `offset`.
+fn supporting_pairs(
+    track_maps: &TrackMaps,
+    snippet_fp: &FingerprintData,
+    offset: i32,
+) -> Vec<SupportingPair> {
+    let mut pairs = Vec::new();
+    for (track_map, snippet_pairs, side) in track_maps.channels(snippet_fp) {
+        for snippet_ent in snippet_pairs {
+            let key = (snippet_ent.f1, snippet_ent.f2, snippet_ent.delta_t);
+            for &track_anchor in track_map.get(&key).into_iter().flatten() {
+                if track_anchor as i32 - snippet_ent.anchor_time as i32 == offset {
+                    pairs.push(SupportingPair {
+                        snippet_anchor: snippet_ent.anchor_time,
+                        track_anchor,
+                        hash: key,
+                        side,
+                    });
+                }
+            }
+        }
+    }
+    pairs
+}
+
+/// Every offset that received at least one vote when matching `snippet_fp` against `track_fp`,
+/// ordered by rank. This is the full histogram `find_matches` picks its winner from, with no
+/// threshold applied.
+pub fn offset_histogram(
+    track_fp: &FingerprintData,
+    snippet_fp: &FingerprintData,
+    sample_rate: usize,
+    search_window: Option<(f32, f32)>,
+) -> Vec<OffsetBin> {
+    let frames_per_sec = frames_per_sec(sample_rate);
+    let track_maps = TrackMaps::build(track_fp, frames_per_sec, search_window);
+    let mut offsets: Vec<(i32, usize)> =
+        count_offsets(&track_maps, snippet_fp).into_iter().collect();
+    // Ties are broken by offset so the output is stable between runs
+    offsets.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    offsets
+        .into_iter()
+        .enumerate()
+        .map(|(i, (offset, count))| OffsetBin {
+            offset_sec: offset as f32 / frames_per_sec,
+            count,
+            rank: i + 1,
+        })
+        .collect()
+}
+
+/// The fraction of `COHERENCE_SLICES` equal slices of the snippet's anchor span that hold at
+/// least one of `supporting` anchors.
+fn coherence(snippet_fp: &FingerprintData, supporting: &[SupportingPair]) -> f32 {
+    let (first, last) = snippet_fp
+        .pairs
+        .iter()
+        .chain(&snippet_fp.side_pairs)
+        .fold((u32::MAX, 0), |(first, last), p| {
+            (first.min(p.anchor_time), last.max(p.anchor_time))
+        });
+    let span = last.saturating_sub(first) as usize + 1;
+    // A snippet shorter than the slices can't be judged on its spread
+    let slices = COHERENCE_SLICES.min(span);
+    let mut hit = [false; COHERENCE_SLICES];
+    for pair in supporting {
+        let frame = pair.snippet_anchor.saturating_sub(first) as usize;
+        hit[(frame * slices / span).min(slices - 1)] = true;
+    }
+    hit.iter().filter(|&&h| h).count() as f32 / slices as f32
+}
+
+/// How many frames lie between the first and last of `anchors`, inclusive.
+fn anchor_span(anchors: impl Iterator<Item = u32>) -> usize {
+    let (min, max) = anchors.fold((u32::MAX, 0), |(min, max), a| (min.min(a), max.max(a)));
+    max.saturating_sub(min) as usize + 1
+}
+
+/// The chance that the tallest of `n_offsets` histogram bars reaches `count` when the rest are
+/// `others`, with any offsets missing from `others` counting as zero.
+///
+/// Bars are modelled as Poisson with the mean of the others. Collisions come in bursts (a
+/// repeated note collides at many offsets at once), so heights are first divided by how much
+/// more the others vary than Poisson would, which is the usual quasi-Poisson correction. The
+/// result is then corrected for having picked the tallest of `n_offsets` bars.
+pub(crate) fn offset_p_value(count: usize, others: impl Iterator<Item = usize>, n_offsets: usize) -> f64 {
+    let n_others = n_offsets.saturating_sub(1).max(1) as f64;
+    let (sum, sum_sq) = others.fold((0.0, 0.0), |(sum, sum_sq), c| {
+        let c = c as f64;
+        (sum + c, sum_sq + c * c)
+    });
+    let mean = sum / n_others;
+    if mean == 0.0 {
+        // Nothing else collided at all, so nothing suggests this offset is chance
+        return 0.0;
+    }
+    let variance = (sum_sq / n_others - mean * mean).max(0.0);
+    let dispersion = (variance / mean).max(1.0);
+
+    let lambda = mean / dispersion;
+    let k = (count as f64 / dispersion).floor() as usize;
+    let p_single = poisson_tail(lambda, k);
+    // 1 - (1 - p)^n, without losing tiny p to rounding
+    -(n_offsets as f64 * (-p_single).ln_1p()).exp_m1()
+}
+
+/// `P(X >= k)` for `X ~ Poisson(lambda)`.
+fn poisson_tail(lambda: f64, k: usize) -> f64 {
+    if k == 0 {
+        return 1.0;
+    }
+    if (k as f64) <= lambda {
+        // The tail is large here, so the complement loses no precision
+        let mut term = (-lambda).exp();
+        let mut below = term;
+        for i in 1..k {
+            term *= lambda / i as f64;
+            below += term;
+        }
+        return (1.0 - below).clamp(0.0, 1.0);
+    }
+    // Sum the tail directly, starting from P(X = k) computed in log space to avoid overflow
+    let ln_factorial: f64 = (2..=k).map(|i| (i as f64).ln()).sum();
+    let mut term = (k as f64 * lambda.ln() - lambda - ln_factorial).exp();
+    let mut tail = 0.0;
+    let mut i = k;
+    while term > tail * f64::EPSILON {
+        tail += term;
+        i += 1;
+        term *= lambda / i as f64;
+    }
+    tail.min(1.0)
+}
+
+pub(crate) fn frames_per_sec(sample_rate: usize) -> f32 {
+    sample_rate as f32 / HOP_SIZE as f32
+}
+
+type TrackMap = HashMap<(u16, u16, u16), Vec<u32>>;
+
+/// The track's pairs indexed by hash, kept apart per channel so mid pairs only ever collide
+/// with mid pairs and side pairs with side pairs. Mono pairs count as mid, which they equal, so
+/// mono and mid/side fingerprints match each other wherever either lacks side pairs.
+struct TrackMaps {
+    mid: TrackMap,
+    side: TrackMap,
+}
+
+impl TrackMaps {
+    fn build(
+        track_fp: &FingerprintData,
+        frames_per_sec: f32,
+        search_window: Option<(f32, f32)>,
+    ) -> TrackMaps {
+        TrackMaps {
+            mid: build_track_map(&track_fp.pairs, frames_per_sec, search_window),
+            side: build_track_map(&track_fp.side_pairs, frames_per_sec, search_window),
+        }
+    }
+
+    /// Each track map with the snippet pairs of the same channel, and whether it is the side.
+    fn channels<'a>(
+        &'a self,
+        snippet_fp: &'a FingerprintData,
+    ) -> [(&'a TrackMap, &'a [FPHashEntry], bool); 2] {
+        [
+            (&self.mid, &snippet_fp.pairs, false),
+            (&self.side, &snippet_fp.side_pairs, true),
+        ]
+    }
+}
+
+/// Index pairs by hash. Anchors outside the search window never enter the map, so they can't
+/// cast votes.
+fn build_track_map(
+    pairs: &[FPHashEntry],
+    frames_per_sec: f32,
+    search_window: Option<(f32, f32)>,
+) -> TrackMap {
+    let window_frames = search_window.map(|(begin, end)| {
+        let begin = (begin * frames_per_sec).floor().max(0.0) as u32;
+        let end = (end * frames_per_sec).ceil().max(0.0) as u32;
+        begin..=end
+    });
+    let mut track_map: TrackMap = HashMap::new();
+    for hash_ent in pairs {
+        if let Some(window) = &window_frames
+            && !window.contains(&hash_ent.anchor_time)
+        {
+            continue;
+        }
+        let key = (hash_ent.f1, hash_ent.f2, hash_ent.delta_t);
+        track_map.entry(key).or_default().push(hash_ent.anchor_time);
+    }
+    track_map
+}
+
+/// Tally `track_anchor_time - snippet_anchor_time` over every hash collision of either channel.
+fn count_offsets(track_maps: &TrackMaps, snippet_fp: &FingerprintData) -> HashMap<i32, usize> {
+    let mut offset_count: HashMap<i32, usize> = HashMap::new();
+    for (track_map, snippet_pairs, _) in track_maps.channels(snippet_fp) {
+        for snippet_ent in snippet_pairs {
+            let key = (snippet_ent.f1, snippet_ent.f2, snippet_ent.delta_t);
+            if let Some(track_times) = track_map.get(&key) {
+                for &track_anchor_time in track_times {
+                    let diff = track_anchor_time as i32 - snippet_ent.anchor_time as i32;
+                    *offset_count.entry(diff).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+    offset_count
+}
@@ -0,0 +1,113 @@
+use crate::find_matches::MatchResult;
+use crate::find_matches::find_matches;
+use crate::find_matches::frames_per_sec;
+use crate::fingerprint_data::FPHashEntry;
+use crate::fingerprint_data::FingerprintData;
+
+/// Offsets (in frames) this close together are considered the same alignment
+const OFFSET_TOLERANCE_FRAMES: f32 = 2.0;
+
+/// How `find_matches_segmented` slices the snippet and how many slices must agree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentVotingConfig {
+    /// Length of each snippet segment matched independently, in seconds
+    pub segment_secs: f32,
+    /// Distance between the starts of consecutive segments, in seconds
+    pub hop_secs: f32,
+    /// Segments that must match at the same offset for it to be accepted
+    pub min_agreeing: usize,
+}
+
+impl Default for SegmentVotingConfig {
+    fn default() -> Self {
+        Self {
+            segment_secs: 3.0,
+            hop_secs: 1.5,
+            min_agreeing: 2,
+        }
+    }
+}
+
+/// Like `find_matches`, but robust to a noisy stretch of the snippet.
+///
+/// The snippet is split into overlapping segments that are each matched on their own, and an
+/// offset is only accepted when at least `config.min_agreeing` segments land on it. A burst of
+/// noise can then win at most the segments it falls in. The result is that of the strongest
+/// agreeing segment, with `agreeing_segments` set. A snippet with fewer segments than
+/// `min_agreeing` needs all of them to agree, so one shorter than a segment is matched whole.
+pub fn find_matches_segmented(
+    track_fp: &FingerprintData,
+    snippet_fp: &FingerprintData,
+    sample_rate: usize,
+    search_window: Option<(f32, f32)>,
+    explain: bool,
+    config: &SegmentVotingConfig,
+) -> Option<MatchResult> {
+    let frames_per_sec = frames_per_sec(sample_rate);
+    let segment_frames = (config.segment_secs * frames_per_sec).round().max(1.0) as u32;
+    let hop_frames = (config.hop_secs * frames_per_sec).round().max(1.0) as u32;
+    let tolerance_sec = OFFSET_TOLERANCE_FRAMES / frames_per_sec;
+
+    // Anchor times are kept as they are, so every segment reports offsets from the same origin
+    let snippet_end = snippet_fp
+        .pairs
+        .iter()
+        .chain(&snippet_fp.side_pairs)
+        .map(|p| p.anchor_time)
+        .max()
+        .unwrap_or(0);
+    let mut results: Vec<MatchResult> = Vec::new();
+    let mut n_segments = 0;
+    let mut segment_start = 0;
+    loop {
+        n_segments += 1;
+        let in_segment = |pairs: &[FPHashEntry]| -> Vec<FPHashEntry> {
+            pairs
+                .iter()
+                .filter(|p| {
+                    (segment_start..segment_start + segment_frames).contains(&p.anchor_time)
+                })
+                .cloned()
+                .collect()
+        };
+        let segment_fp = FingerprintData {
+            pairs: in_segment(&snippet_fp.pairs),
+            side_pairs: in_segment(&snippet_fp.side_pairs),
+            source: None,
+            channels: None,
+            checksum: None,
+            config_hash: None,
+        };
+        if let Some(result) =
+            find_matches(track_fp, &segment_fp, sample_rate, search_window, explain)
+        {
+            results.push(result);
+        }
+        // The last segment is the first to reach the end of the snippet
+        if segment_start + segment_frames > snippet_end {
+            break;
+        }
+        segment_start += hop_frames;
+    }
+
+    // Each segment votes for the offsets near its own, and the best supported one wins, with
+    // ties going to the segment with more collisions
+    let agreeing = |result: &MatchResult| {
+        results
+            .iter()
+            .filter(|other| (other.offset_sec - result.offset_sec).abs() <= tolerance_sec)
+            .count()
+    };
+    let (best, agree) = results
+        .iter()
+        .map(|result| (result, agreeing(result)))
+        .max_by_key(|(result, agree)| (*agree, result.count))?;
+    // A snippet too short for that many segments can't be held to it
+    if agree < config.min_agreeing.min(n_segments) {
+        return None;
+    }
+    Some(MatchResult {
+        agreeing_segments: Some(agree),
+        ..best.clone()
+    })
+}
@@ -0,0 +1,368 @@
+use crate::fingerprint_config::FingerprintConfig;
+use crate::fingerprint_config::PeakNeighborhood;
+use crate::fingerprint_config::PeakParams;
+use std::cmp::Ordering;
+
+/// Find "peaks" per time slice: pick the top frequencies by magnitude.
+///
+/// The number of peaks in each frame is proportional to its energy relative to the loudest
+/// frame, clamped to the configured min/max, so busy frames yield more points than quiet ones.
+/// Bins not above `config.min_peak_magnitude` are never picked. With
+/// `config.sub_bin_resolution` set, peaks are in fractions of a bin rather than whole bins.
+/// With `config.local_maxima` set, only bins louder than their neighbors and well above the
+/// frame's mean compete, and at most its `max_peaks` are picked.
+/// With `config.peak_neighborhood` set, only the strongest peak of each neighborhood is kept.
+pub fn find_peaks(spectrogram: &[Vec<f32>], config: &FingerprintConfig) -> Vec<Vec<u16>> {
+    // spectrogram[freq_bin][time]
+    let n_freqs = spectrogram.len();
+    if n_freqs == 0 {
+        return Vec::new();
+    }
+    let n_hops = spectrogram[0].len();
+
+    let columns: Vec<Vec<f32>> = (0..n_hops)
+        .map(|time_idx| spectrogram.iter().map(|row| row[time_idx]).collect())
+        .collect();
+    let frame_energies: Vec<f32> = columns.iter().map(|column| frame_energy(column)).collect();
+    let loudest = frame_energies.iter().copied().fold(0.0, f32::max);
+
+    let peaks: Vec<Vec<u16>> = columns
+        .iter()
+        .zip(frame_energies)
+        .map(|(column, energy)| {
+            let top_n = peak_count(
+                energy,
+                loudest,
+                config.min_peaks_per_frame,
+                config.max_peaks_per_frame,
+            );
+            top_peaks(column, top_n, config)
+        })
+        .collect();
+
+    match config.peak_neighborhood {
+        Some(neighborhood) => suppress_neighbors(
+            &peaks,
+            &columns,
+            neighborhood,
+            config.sub_bin_resolution.unwrap_or(1),
+        ),
+        None => peaks,
+    }
+}
+
+/// Non-maximum suppression: keep only the peaks that are the strongest within `neighborhood`.
+///
+/// Peaks of equal magnitude, as in a steady tone, are resolved in favour of the earliest frame
+/// and then the lowest bin, so a run of them thins out rather than vanishing entirely.
+fn suppress_neighbors(
+    peaks: &[Vec<u16>],
+    columns: &[Vec<f32>],
+    neighborhood: PeakNeighborhood,
+    steps: u16,
+) -> Vec<Vec<u16>> {
+    let bin_of = |peak: u16| (peak / steps) as usize;
+    let magnitude = |t: usize, peak: u16| {
+        let m = columns[t][bin_of(peak)];
+        if m.is_nan() { f32::NEG_INFINITY } else { m }
+    };
+
+    let mut kept: Vec<Vec<u16>> = vec![Vec::new(); peaks.len()];
+    for (t, frame_peaks) in peaks.iter().enumerate() {
+        for &peak in frame_peaks {
+            let here = (magnitude(t, peak), t, bin_of(peak));
+            let from = t.saturating_sub(neighborhood.time_frames);
+            let to = (t + neighborhood.time_frames).min(peaks.len() - 1);
+            let beaten = (from..=to).any(|u| {
+                peaks[u].iter().any(|&other| {
+                    let there = (magnitude(u, other), u, bin_of(other));
+                    there != here
+                        && there.2.abs_diff(here.2) <= neighborhood.freq_bins
+                        && beats(there, here)
+                })
+            });
+            if !beaten {
+                kept[t].push(peak);
+            }
+        }
+    }
+    kept
+}
+
+/// Whether the peak `a` outranks `b`, both as (magnitude, frame, bin).
+fn beats(a: (f32, usize, usize), b: (f32, usize, usize)) -> bool {
+    a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)).then(b.2.cmp(&a.2)) == Ordering::Greater
+}
+
+/// Total energy of one spectrogram column, ignoring NaN bins.
+pub(crate) fn frame_energy(column: &[f32]) -> f32 {
+    column.iter().filter(|m| !m.is_nan()).map(|m| m * m).sum()
+}
+
+/// How many peaks a frame with `energy` gets when the loudest frame has `loudest`.
+pub(crate) fn peak_count(energy: f32, loudest: f32, min_peaks: usize, max_peaks: usize) -> usize {
+    let top_n = if loudest > 0.0 {
+        (max_peaks as f32 * energy / loudest).round() as usize
+    } else {
+        0
+    };
+    top_n.clamp(min_peaks, max_peaks.max(min_peaks))
+}
+
+/// The `top_n` strongest bins of one spectrogram column above the magnitude gate, strongest
+/// first, refined to sub-bin frequencies when configured. Only local maxima are considered
+/// when `config.local_maxima` is set.
+pub(crate) fn top_peaks(column: &[f32], top_n: usize, config: &FingerprintConfig) -> Vec<u16> {
+    // gather (freq_bin, magnitude)
+    let (mut freq_mags, top_n): (Vec<(u16, f32)>, usize) = match &config.local_maxima {
+        Some(params) => (local_maxima(column, params), top_n.min(params.max_peaks)),
+        None => (
+            column
+                .iter()
+                .enumerate()
+                .map(|(f, &m)| (f as u16, m))
+                .collect(),
+            top_n,
+        ),
+    };
+    // sort by magnitude descending, NaN last, ties broken by lowest bin for stable output
+    let magnitude = |m: f32| if m.is_nan() { f32::NEG_INFINITY } else { m };
+    freq_mags.sort_by(|a, b| {
+        magnitude(b.1)
+            .total_cmp(&magnitude(a.1))
+            .then(a.0.cmp(&b.0))
+    });
+    // pick top N, stopping at the first bin under the gate
+    let mut peaks = Vec::with_capacity(top_n);
+    for (f, m) in freq_mags.into_iter().take(top_n) {
+        // NaN never clears the gate
+        if config
+            .min_peak_magnitude
+            .is_some_and(|min| m.partial_cmp(&min) != Some(Ordering::Greater))
+        {
+            break;
+        }
+        let peak = match config.sub_bin_resolution {
+            Some(steps) => interpolate_peak(column, f, steps),
+            None => f,
+        };
+        // both bins either side of a frequency can interpolate to the same peak
+        if !peaks.contains(&peak) {
+            peaks.push(peak);
+        }
+    }
+    peaks
+}
+
+/// The bins of `column` louder than every bin within `params.neighborhood` of them and above
+/// `params.threshold_ratio` times the column's mean magnitude, as (freq_bin, magnitude).
+///
+/// A plateau of equal bins counts once, at its lowest bin. NaN bins never qualify and don't
+/// stop their neighbors from doing so.
+fn local_maxima(column: &[f32], params: &PeakParams) -> Vec<(u16, f32)> {
+    let (sum, count) = column
+        .iter()
+        .filter(|m| !m.is_nan())
+        .fold((0.0, 0), |(sum, count), m| (sum + m, count + 1));
+    if count == 0 {
+        return Vec::new();
+    }
+    let threshold = params.threshold_ratio * sum / count as f32;
+    column
+        .iter()
+        .enumerate()
+        .filter(|&(f, &m)| {
+            if m.is_nan() || m <= threshold {
+                return false;
+            }
+            let from = f.saturating_sub(params.neighborhood);
+            let to = (f + params.neighborhood).min(column.len() - 1);
+            (from..=to).all(|g| {
+                let other = column[g];
+                g == f || other.is_nan() || if g < f { m > other } else { m >= other }
+            })
+        })
+        .map(|(f, &m)| (f as u16, m))
+        .collect()
+}
+
+/// Estimate the true frequency of the peak at `bin` by fitting a parabola through it and its
+/// neighbours (on log magnitude), quantized to `steps` per bin.
+///
+/// Recordings at slightly different tunings then still agree on the peak even when it falls
+/// on different sides of a bin boundary. Edge bins keep their integer position.
+fn interpolate_peak(column: &[f32], bin: u16, steps: u16) -> u16 {
+    let k = bin as usize;
+    let log_mag = |m: f32| {
+        if m.is_nan() {
+            f32::MIN
+        } else {
+            m.max(f32::MIN_POSITIVE).ln()
+        }
+    };
+    let offset = match (
+        k.checked_sub(1).and_then(|i| column.get(i)),
+        column.get(k + 1),
+    ) {
+        (Some(&left), Some(&right)) => {
+            let (alpha, beta, gamma) = (log_mag(left), log_mag(column[k]), log_mag(right));
+            let denominator = alpha - 2.0 * beta + gamma;
+            if denominator.abs() > f32::EPSILON {
+                (0.5 * (alpha - gamma) / denominator).clamp(-0.5, 0.5)
+            } else {
+                0.0
+            }
+        }
+        _ => 0.0,
+    };
+    ((k as f32 + offset) * steps as f32)
+        .round()
+        .clamp(0.0, u16::MAX as f32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_fingerprint::HOP_SIZE;
+    use crate::compute_fingerprint::WINDOW_SIZE;
+    use crate::compute_spectrogram::compute_spectrogram;
+    use std::f32::consts::TAU;
+
+    const SAMPLE_RATE: usize = 22_050;
+
+    /// A second each of a 20-partial chord at full, 40%, and 2% amplitude.
+    fn varying_loudness() -> Vec<f32> {
+        [1.0, 0.4, 0.02]
+            .iter()
+            .flat_map(|&amplitude| {
+                (0..SAMPLE_RATE).map(move |i| {
+                    let t = i as f32 / SAMPLE_RATE as f32;
+                    (1..=20)
+                        .map(|k| amplitude * (TAU * 220.0 * k as f32 * t).sin() / 20.0)
+                        .sum::<f32>()
+                })
+            })
+            .collect()
+    }
+
+    /// Peak counts of the frames lying wholly within each second.
+    fn counts_per_second(config: &FingerprintConfig) -> Vec<Vec<usize>> {
+        let spectrogram =
+            compute_spectrogram(&varying_loudness(), SAMPLE_RATE, WINDOW_SIZE, HOP_SIZE).unwrap();
+        let peaks = find_peaks(&spectrogram, config);
+        (0..3)
+            .map(|second| {
+                let start = (second * SAMPLE_RATE).div_ceil(HOP_SIZE);
+                let end = ((second + 1) * SAMPLE_RATE - WINDOW_SIZE) / HOP_SIZE;
+                peaks[start..=end].iter().map(Vec::len).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn peak_count_follows_loudness() {
+        let config = FingerprintConfig {
+            min_peaks_per_frame: 1,
+            max_peaks_per_frame: 10,
+            ..FingerprintConfig::default()
+        };
+        let [loud, medium, quiet] = counts_per_second(&config).try_into().unwrap();
+        assert!(loud.iter().all(|&n| n == 10), "{loud:?}");
+        assert!(medium.iter().all(|&n| n > 1 && n < 10), "{medium:?}");
+        assert!(quiet.iter().all(|&n| n == 1), "{quiet:?}");
+    }
+
+    #[test]
+    fn default_keeps_five_peaks_per_frame() {
+        for counts in counts_per_second(&FingerprintConfig::default()) {
+            assert!(counts.iter().all(|&n| n == 5), "{counts:?}");
+        }
+    }
+
+    #[test]
+    fn nan_magnitudes_rank_last() {
+        let spectrogram = vec![vec![1.0], vec![f32::NAN], vec![3.0], vec![2.0]];
+        let config = FingerprintConfig {
+            min_peaks_per_frame: 4,
+            max_peaks_per_frame: 4,
+            ..FingerprintConfig::default()
+        };
+        assert_eq!(find_peaks(&spectrogram, &config), vec![vec![2, 3, 0, 1]]);
+    }
+
+    #[test]
+    fn equal_magnitudes_rank_by_bin() {
+        let spectrogram = vec![vec![2.0], vec![5.0], vec![2.0], vec![5.0]];
+        let config = FingerprintConfig {
+            min_peaks_per_frame: 4,
+            max_peaks_per_frame: 4,
+            ..FingerprintConfig::default()
+        };
+        assert_eq!(find_peaks(&spectrogram, &config), vec![vec![1, 3, 0, 2]]);
+    }
+
+    #[test]
+    fn interpolation_finds_a_sine_between_two_bins() {
+        let bin_hz = SAMPLE_RATE as f32 / WINDOW_SIZE as f32;
+        let strongest_peaks = |bins: f32, steps: Option<u16>| {
+            let pcm: Vec<f32> = (0..SAMPLE_RATE)
+                .map(|i| (TAU * bins * bin_hz * i as f32 / SAMPLE_RATE as f32).sin())
+                .collect();
+            let spectrogram =
+                compute_spectrogram(&pcm, SAMPLE_RATE, WINDOW_SIZE, HOP_SIZE).unwrap();
+            let config = FingerprintConfig {
+                min_peaks_per_frame: 1,
+                max_peaks_per_frame: 1,
+                sub_bin_resolution: steps,
+                ..FingerprintConfig::default()
+            };
+            find_peaks(&spectrogram, &config)
+                .into_iter()
+                .map(|peaks| peaks[0])
+                .collect::<Vec<u16>>()
+        };
+
+        for bins in [100.3, 100.5, 100.7] {
+            assert!(
+                strongest_peaks(bins, None)
+                    .iter()
+                    .all(|&peak| (peak as f32 - bins).abs() <= 0.5)
+            );
+            // Within a tenth of a bin, in tenths of a bin
+            let expected = (bins * 10.0).round() as i32;
+            let refined = strongest_peaks(bins, Some(10));
+            assert!(
+                refined
+                    .iter()
+                    .all(|&peak| (peak as i32 - expected).abs() <= 1),
+                "{bins}: {refined:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn suppression_thins_a_sustained_tone() {
+        let tone: Vec<f32> = (0..2 * SAMPLE_RATE)
+            .map(|i| (TAU * 440.0 * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect();
+        let spectrogram = compute_spectrogram(&tone, SAMPLE_RATE, WINDOW_SIZE, HOP_SIZE).unwrap();
+        let count = |peak_neighborhood| {
+            let config = FingerprintConfig {
+                peak_neighborhood,
+                ..FingerprintConfig::default()
+            };
+            find_peaks(&spectrogram, &config)
+                .iter()
+                .map(Vec::len)
+                .sum::<usize>()
+        };
+
+        let unsuppressed = count(None);
+        let suppressed = count(Some(PeakNeighborhood::default()));
+        assert!(suppressed > 0);
+        assert!(
+            suppressed * 4 < unsuppressed,
+            "{suppressed} of {unsuppressed}"
+        );
+    }
+}
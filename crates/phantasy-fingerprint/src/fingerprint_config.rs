@@ -0,0 +1,582 @@
+use crate::compute_fingerprint::WINDOW_SIZE;
+use crate::fingerprint_data::fnv1a;
+use crate::resample::ResampleQuality;
+
+/// Shortest a `WINDOW_SIZE` window may last at the analysis rate, below which its bins are too
+/// wide to tell notes apart
+const MIN_WINDOW_SECS: f32 = 0.01;
+/// Longest a `WINDOW_SIZE` window may last at the analysis rate, above which its frames, and
+/// the hops between them, are too long to follow notes
+const MAX_WINDOW_SECS: f32 = 0.25;
+
+/// Tunable parameters for `compute_fingerprint_with_config`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FingerprintConfig {
+    /// Fewest peaks kept from any frame, however quiet. Defaults to 5
+    pub min_peaks_per_frame: usize,
+    /// Most peaks kept from any frame, reached by the loudest frame. Defaults to 5, the same as
+    /// the minimum, which keeps a fixed five peaks per frame; set the minimum lower to scale the
+    /// count with each frame's energy instead
+    pub max_peaks_per_frame: usize,
+    /// Bins must exceed this magnitude to become peaks, so weak bins in quiet frames stay out
+    /// of the constellation. `None` keeps every bin eligible.
+    pub min_peak_magnitude: Option<f32>,
+    /// Refine peaks between bins by parabolic interpolation and record them in this many steps
+    /// per bin. `None` keeps whole bins. Tracks and snippets must use the same setting, and
+    /// `steps * WINDOW_SIZE / 2` must fit in a `u16`.
+    pub sub_bin_resolution: Option<u16>,
+    /// Normalize the signal to this integrated loudness (LUFS) before fingerprinting, so
+    /// differently mastered sources produce comparable spectra. `None` leaves levels alone.
+    /// The streaming fingerprinter never sees the whole signal and ignores this.
+    pub target_lufs: Option<f64>,
+    /// Keep at most this many pairs, preferring those whose anchor peak is loudest, to bound
+    /// how much a dense or noisy track can grow an index. `None` keeps every pair. Meant for
+    /// tracks only: leave it unset for snippets, whose every pair is a chance to match. The
+    /// streaming fingerprinter emits pairs as it goes and ignores this.
+    pub max_pairs_per_track: Option<usize>,
+    /// Drop any peak with a stronger peak within this neighborhood, so a sustained note leaves
+    /// one peak per neighborhood instead of a run of near-identical ones hashing alike. Defaults
+    /// to `None`, keeping every peak. The streaming fingerprinter ignores this.
+    pub peak_neighborhood: Option<PeakNeighborhood>,
+    /// Only let bins that are local maxima in frequency, and well above their frame's mean,
+    /// become peaks, so they spread across harmonics rather than clustering on the slopes of the
+    /// loudest one. Defaults to `None`, ranking every bin by magnitude alone. Tracks and
+    /// snippets must use the same setting.
+    pub local_maxima: Option<PeakParams>,
+    /// Which channels of a stereo file to fingerprint. Tracks and snippets must use the same
+    /// mode for the side channel to take part in matching.
+    pub channel_mode: ChannelMode,
+    /// Leave out pairs anchored in the first this many seconds, such as a talk-over intro or a
+    /// station jingle that would otherwise collide with every snippet sharing it. Anchor times
+    /// still count from the start of the untrimmed audio, so reported offsets are unaffected,
+    /// but a snippet from the trimmed part can no longer match. Meant for tracks only, like
+    /// `max_pairs_per_track`. The streaming fingerprinter ignores this.
+    pub trim_head_secs: f32,
+    /// Leave out pairs reaching into the last this many seconds, e.g. an outro or fade, as
+    /// `trim_head_secs` does for the start.
+    pub trim_tail_secs: f32,
+    /// Round each pair's `delta_t` to the nearest multiple of this many frames, so recordings
+    /// at slightly different tempos still hash alike, at the cost of telling fewer pairs apart.
+    /// Defaults to 1, keeping exact frames. Tracks and snippets must use the same setting.
+    /// Offsets are unaffected, as anchor times stay exact.
+    pub delta_t_bin: u16,
+    /// How decoded files are resampled when their rate differs from the rate asked to analyse
+    /// them at, see [`ResampleQuality`]. In-memory PCM is never resampled.
+    pub resample_quality: ResampleQuality,
+    /// Skip packets of a file that fail to decode, warning where they were, rather than
+    /// failing the whole file, as ripped files with a few bad packets need. Defaults to
+    /// `false`. A long run of bad packets still fails, see `decode_to_mono_f32_lenient`.
+    /// In-memory PCM is unaffected.
+    pub skip_corrupt_packets: bool,
+}
+
+/// How the channels of a decoded file are turned into fingerprints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelMode {
+    /// Average every channel into one
+    #[default]
+    Mono,
+    /// Fingerprint the mid (L+R) and side (L−R) channels separately, matching each against its
+    /// own kind. Distinguishes masters with different stereo images at about twice the cost.
+    ///
+    /// The mid channel is exactly the mono downmix, so mid pairs hash like mono pairs. A mono
+    /// snippet, such as a phone recording, matches a mid/side track through its mid pairs, and
+    /// a mid/side snippet matches a mono track through its own, without fingerprinting either
+    /// again. Only the side pairs go unused then
+    MidSide,
+}
+
+/// How far around a peak to look for a stronger one, see
+/// [`FingerprintConfig::peak_neighborhood`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeakNeighborhood {
+    /// Frames either side of the peak
+    pub time_frames: usize,
+    /// Bins either side of the peak
+    pub freq_bins: usize,
+}
+
+impl Default for PeakNeighborhood {
+    /// A small neighborhood that only thins out directly adjacent duplicates.
+    fn default() -> Self {
+        Self {
+            time_frames: 2,
+            freq_bins: 1,
+        }
+    }
+}
+
+/// Which bins qualify as peaks, see [`FingerprintConfig::local_maxima`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakParams {
+    /// Bins either side that a peak must be louder than
+    pub neighborhood: usize,
+    /// How many times the frame's mean magnitude a peak must exceed
+    pub threshold_ratio: f32,
+    /// Most peaks kept from any frame, on top of `max_peaks_per_frame`
+    pub max_peaks: usize,
+}
+
+impl Default for PeakParams {
+    /// Maxima over three bins either side at twice the mean, five per frame at most.
+    fn default() -> Self {
+        Self {
+            neighborhood: 3,
+            threshold_ratio: 2.0,
+            max_peaks: 5,
+        }
+    }
+}
+
+impl Default for FingerprintConfig {
+    fn default() -> Self {
+        Self {
+            min_peaks_per_frame: 5,
+            max_peaks_per_frame: 5,
+            min_peak_magnitude: None,
+            sub_bin_resolution: None,
+            target_lufs: None,
+            max_pairs_per_track: None,
+            peak_neighborhood: None,
+            local_maxima: None,
+            channel_mode: ChannelMode::Mono,
+            trim_head_secs: 0.0,
+            trim_tail_secs: 0.0,
+            delta_t_bin: 1,
+            resample_quality: ResampleQuality::Balanced,
+            skip_corrupt_packets: false,
+        }
+    }
+}
+
+/// Why a `FingerprintConfig` can't be used.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ConfigError {
+    #[error("max_peaks_per_frame must be at least 1")]
+    NoPeaks,
+    #[error("min_peaks_per_frame ({min}) exceeds max_peaks_per_frame ({max})")]
+    PeakRangeInverted { min: usize, max: usize },
+    #[error("min_peak_magnitude must be a non-negative number, got {0}")]
+    InvalidMagnitude(f32),
+    #[error("sub_bin_resolution must be between 1 and {max}, got {steps}")]
+    InvalidSubBinResolution { steps: u16, max: u16 },
+    #[error("target_lufs must be a finite level at or below 0 LUFS, got {0}")]
+    InvalidTargetLufs(f64),
+    #[error("max_pairs_per_track must be at least 1")]
+    NoPairs,
+    #[error("{field} must be a non-negative number of seconds, got {secs}")]
+    InvalidTrim { field: &'static str, secs: f32 },
+    #[error("local_maxima.max_peaks must be at least 1")]
+    NoLocalMaxima,
+    #[error("local_maxima.threshold_ratio must be a non-negative number, got {0}")]
+    InvalidThresholdRatio(f32),
+    #[error("delta_t_bin must be at least 1")]
+    ZeroDeltaTBin,
+    #[error("sample_rate must be positive")]
+    ZeroSampleRate,
+    #[error(
+        "a {window_size}-sample window needs a sample_rate between {min} and {max} Hz, got {sample_rate}"
+    )]
+    UnsupportedSampleRate {
+        sample_rate: usize,
+        window_size: usize,
+        min: usize,
+        max: usize,
+    },
+}
+
+impl FingerprintConfig {
+    /// Check that the fields make sense together, before they cause panics or silent garbage.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_peaks_per_frame == 0 {
+            return Err(ConfigError::NoPeaks);
+        }
+        if self.min_peaks_per_frame > self.max_peaks_per_frame {
+            return Err(ConfigError::PeakRangeInverted {
+                min: self.min_peaks_per_frame,
+                max: self.max_peaks_per_frame,
+            });
+        }
+        if let Some(magnitude) = self.min_peak_magnitude
+            && !(magnitude.is_finite() && magnitude >= 0.0)
+        {
+            return Err(ConfigError::InvalidMagnitude(magnitude));
+        }
+        if let Some(steps) = self.sub_bin_resolution {
+            // The highest bin times the steps must still fit in a u16 peak
+            let max = (u16::MAX as usize / (WINDOW_SIZE / 2)) as u16;
+            if steps == 0 || steps > max {
+                return Err(ConfigError::InvalidSubBinResolution { steps, max });
+            }
+        }
+        if let Some(lufs) = self.target_lufs
+            && !(lufs.is_finite() && lufs <= 0.0)
+        {
+            return Err(ConfigError::InvalidTargetLufs(lufs));
+        }
+        if let Some(params) = self.local_maxima {
+            if params.max_peaks == 0 {
+                return Err(ConfigError::NoLocalMaxima);
+            }
+            if !(params.threshold_ratio.is_finite() && params.threshold_ratio >= 0.0) {
+                return Err(ConfigError::InvalidThresholdRatio(params.threshold_ratio));
+            }
+        }
+        if self.max_pairs_per_track == Some(0) {
+            return Err(ConfigError::NoPairs);
+        }
+        if self.delta_t_bin == 0 {
+            return Err(ConfigError::ZeroDeltaTBin);
+        }
+        for (field, secs) in [
+            ("trim_head_secs", self.trim_head_secs),
+            ("trim_tail_secs", self.trim_tail_secs),
+        ] {
+            if !(secs.is_finite() && secs >= 0.0) {
+                return Err(ConfigError::InvalidTrim { field, secs });
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `validate`, also checking the config against the rate of the audio it will analyse.
+    ///
+    /// The window and hop are fixed in samples, so the rate decides how long they last: a
+    /// `WINDOW_SIZE` window must last between 10 and 250 ms, which at 1024 samples allows
+    /// 4096 to 102400 Hz. The frequency axis always runs from 0 to the Nyquist frequency of
+    /// `sample_rate`, so no other setting depends on it.
+    pub fn validate_for(&self, sample_rate: usize) -> Result<(), ConfigError> {
+        if sample_rate == 0 {
+            return Err(ConfigError::ZeroSampleRate);
+        }
+        let min = (WINDOW_SIZE as f32 / MAX_WINDOW_SECS).ceil() as usize;
+        let max = (WINDOW_SIZE as f32 / MIN_WINDOW_SECS).floor() as usize;
+        if !(min..=max).contains(&sample_rate) {
+            return Err(ConfigError::UnsupportedSampleRate {
+                sample_rate,
+                window_size: WINDOW_SIZE,
+                min,
+                max,
+            });
+        }
+        self.validate()
+    }
+
+    /// A hash of every setting, stable across runs and Rust releases, recorded with each
+    /// fingerprint so `FingerprintData::content_hash` covers the config as well as the pairs.
+    pub fn stable_hash(&self) -> u64 {
+        // Destructured in full so a new field can't be left out by accident
+        let FingerprintConfig {
+            min_peaks_per_frame,
+            max_peaks_per_frame,
+            min_peak_magnitude,
+            sub_bin_resolution,
+            target_lufs,
+            max_pairs_per_track,
+            peak_neighborhood,
+            local_maxima,
+            channel_mode,
+            trim_head_secs,
+            trim_tail_secs,
+            delta_t_bin,
+            resample_quality,
+            // Only decides whether a damaged file fingerprints at all, not its pairs
+            skip_corrupt_packets: _,
+        } = self;
+        // Each optional field is a presence byte, then its value when present
+        let mut bytes = Vec::new();
+        let push_u64 = |bytes: &mut Vec<u8>, value: u64| bytes.extend(value.to_le_bytes());
+        push_u64(&mut bytes, *min_peaks_per_frame as u64);
+        push_u64(&mut bytes, *max_peaks_per_frame as u64);
+        bytes.push(min_peak_magnitude.is_some() as u8);
+        push_u64(
+            &mut bytes,
+            min_peak_magnitude.map_or(0, |m| m.to_bits() as u64),
+        );
+        bytes.push(sub_bin_resolution.is_some() as u8);
+        push_u64(&mut bytes, sub_bin_resolution.unwrap_or(0) as u64);
+        bytes.push(target_lufs.is_some() as u8);
+        push_u64(&mut bytes, target_lufs.map_or(0, f64::to_bits));
+        bytes.push(max_pairs_per_track.is_some() as u8);
+        push_u64(&mut bytes, max_pairs_per_track.unwrap_or(0) as u64);
+        bytes.push(peak_neighborhood.is_some() as u8);
+        let neighborhood = peak_neighborhood.unwrap_or(PeakNeighborhood {
+            time_frames: 0,
+            freq_bins: 0,
+        });
+        push_u64(&mut bytes, neighborhood.time_frames as u64);
+        push_u64(&mut bytes, neighborhood.freq_bins as u64);
+        bytes.push(local_maxima.is_some() as u8);
+        let maxima = local_maxima.unwrap_or(PeakParams {
+            neighborhood: 0,
+            threshold_ratio: 0.0,
+            max_peaks: 0,
+        });
+        push_u64(&mut bytes, maxima.neighborhood as u64);
+        push_u64(&mut bytes, maxima.threshold_ratio.to_bits() as u64);
+        push_u64(&mut bytes, maxima.max_peaks as u64);
+        bytes.push(match channel_mode {
+            ChannelMode::Mono => 0,
+            ChannelMode::MidSide => 1,
+        });
+        push_u64(&mut bytes, trim_head_secs.to_bits() as u64);
+        push_u64(&mut bytes, trim_tail_secs.to_bits() as u64);
+        push_u64(&mut bytes, *delta_t_bin as u64);
+        bytes.push(match resample_quality {
+            ResampleQuality::Fast => 0,
+            ResampleQuality::Balanced => 1,
+            ResampleQuality::High => 2,
+        });
+        fnv1a(bytes)
+    }
+}
+
+impl FingerprintConfig {
+    /// Start from the defaults and adjust with chained setters.
+    pub fn builder() -> FingerprintConfigBuilder {
+        FingerprintConfigBuilder::default()
+    }
+
+    /// Tuned for finding samples in produced music: the default, five peaks in every frame.
+    pub fn music() -> FingerprintConfig {
+        FingerprintConfig::default()
+    }
+
+    /// Tuned for spoken word, which has fewer sustained partials than music and varies more in
+    /// level between recordings: fewer peaks per frame, scaled with its energy and thinned out
+    /// in time, at broadcast loudness (EBU R128, -23 LUFS).
+    pub fn speech() -> FingerprintConfig {
+        FingerprintConfig {
+            min_peaks_per_frame: 1,
+            max_peaks_per_frame: 6,
+            target_lufs: Some(-23.0),
+            peak_neighborhood: Some(PeakNeighborhood::default()),
+            ..FingerprintConfig::default()
+        }
+    }
+
+    /// Tuned for short stings and jingles, which must match from a few seconds of audio: more
+    /// peaks per frame, with a floor so quiet frames still contribute.
+    pub fn jingle() -> FingerprintConfig {
+        FingerprintConfig {
+            min_peaks_per_frame: 3,
+            max_peaks_per_frame: 15,
+            ..FingerprintConfig::default()
+        }
+    }
+}
+
+/// Builds a [`FingerprintConfig`] one setting at a time, validating it once done, e.g.
+/// `FingerprintConfig::builder().max_peaks_per_frame(8).target_lufs(-14.0).build()`.
+#[derive(Debug, Clone, Default)]
+pub struct FingerprintConfigBuilder {
+    config: FingerprintConfig,
+}
+
+impl FingerprintConfigBuilder {
+    /// Start from `config`, e.g. one of the presets, rather than the defaults.
+    pub fn from_config(config: FingerprintConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn min_peaks_per_frame(mut self, min: usize) -> Self {
+        self.config.min_peaks_per_frame = min;
+        self
+    }
+
+    pub fn max_peaks_per_frame(mut self, max: usize) -> Self {
+        self.config.max_peaks_per_frame = max;
+        self
+    }
+
+    pub fn min_peak_magnitude(mut self, magnitude: f32) -> Self {
+        self.config.min_peak_magnitude = Some(magnitude);
+        self
+    }
+
+    pub fn sub_bin_resolution(mut self, steps: u16) -> Self {
+        self.config.sub_bin_resolution = Some(steps);
+        self
+    }
+
+    pub fn target_lufs(mut self, lufs: f64) -> Self {
+        self.config.target_lufs = Some(lufs);
+        self
+    }
+
+    pub fn max_pairs_per_track(mut self, max: usize) -> Self {
+        self.config.max_pairs_per_track = Some(max);
+        self
+    }
+
+    pub fn peak_neighborhood(mut self, neighborhood: PeakNeighborhood) -> Self {
+        self.config.peak_neighborhood = Some(neighborhood);
+        self
+    }
+
+    pub fn local_maxima(mut self, params: PeakParams) -> Self {
+        self.config.local_maxima = Some(params);
+        self
+    }
+
+    pub fn channel_mode(mut self, mode: ChannelMode) -> Self {
+        self.config.channel_mode = mode;
+        self
+    }
+
+    pub fn trim_head_secs(mut self, secs: f32) -> Self {
+        self.config.trim_head_secs = secs;
+        self
+    }
+
+    pub fn trim_tail_secs(mut self, secs: f32) -> Self {
+        self.config.trim_tail_secs = secs;
+        self
+    }
+
+    pub fn delta_t_bin(mut self, frames: u16) -> Self {
+        self.config.delta_t_bin = frames;
+        self
+    }
+
+    pub fn resample_quality(mut self, quality: ResampleQuality) -> Self {
+        self.config.resample_quality = quality;
+        self
+    }
+
+    pub fn skip_corrupt_packets(mut self, skip: bool) -> Self {
+        self.config.skip_corrupt_packets = skip;
+        self
+    }
+
+    /// The finished config, or why it can't be used.
+    pub fn build(self) -> Result<FingerprintConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(adjust: impl FnOnce(&mut FingerprintConfig)) -> FingerprintConfig {
+        let mut config = FingerprintConfig::default();
+        adjust(&mut config);
+        config
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for config in [
+            FingerprintConfig::music(),
+            FingerprintConfig::speech(),
+            FingerprintConfig::jingle(),
+        ] {
+            assert_eq!(config.validate_for(22_050), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejects_each_invalid_setting() {
+        let cases = [
+            (with(|c| c.max_peaks_per_frame = 0), ConfigError::NoPeaks),
+            (
+                with(|c| c.min_peaks_per_frame = 6),
+                ConfigError::PeakRangeInverted { min: 6, max: 5 },
+            ),
+            (
+                with(|c| c.min_peak_magnitude = Some(-1.0)),
+                ConfigError::InvalidMagnitude(-1.0),
+            ),
+            (
+                with(|c| c.sub_bin_resolution = Some(0)),
+                ConfigError::InvalidSubBinResolution { steps: 0, max: 127 },
+            ),
+            (
+                with(|c| c.sub_bin_resolution = Some(128)),
+                ConfigError::InvalidSubBinResolution {
+                    steps: 128,
+                    max: 127,
+                },
+            ),
+            (
+                with(|c| c.target_lufs = Some(3.0)),
+                ConfigError::InvalidTargetLufs(3.0),
+            ),
+            (
+                with(|c| c.max_pairs_per_track = Some(0)),
+                ConfigError::NoPairs,
+            ),
+            (with(|c| c.delta_t_bin = 0), ConfigError::ZeroDeltaTBin),
+            (
+                with(|c| c.trim_head_secs = -1.0),
+                ConfigError::InvalidTrim {
+                    field: "trim_head_secs",
+                    secs: -1.0,
+                },
+            ),
+            (
+                with(|c| c.trim_tail_secs = f32::INFINITY),
+                ConfigError::InvalidTrim {
+                    field: "trim_tail_secs",
+                    secs: f32::INFINITY,
+                },
+            ),
+            (
+                with(|c| {
+                    c.local_maxima = Some(PeakParams {
+                        max_peaks: 0,
+                        ..PeakParams::default()
+                    })
+                }),
+                ConfigError::NoLocalMaxima,
+            ),
+            (
+                with(|c| {
+                    c.local_maxima = Some(PeakParams {
+                        threshold_ratio: -2.0,
+                        ..PeakParams::default()
+                    })
+                }),
+                ConfigError::InvalidThresholdRatio(-2.0),
+            ),
+        ];
+        for (config, error) in cases {
+            assert_eq!(config.validate(), Err(error.clone()));
+            assert_eq!(config.validate_for(22_050), Err(error));
+        }
+    }
+
+    #[test]
+    fn rejects_rates_the_window_does_not_suit() {
+        let config = FingerprintConfig::default();
+        assert_eq!(config.validate_for(0), Err(ConfigError::ZeroSampleRate));
+        for sample_rate in [4_095, 102_401, 192_000] {
+            assert_eq!(
+                config.validate_for(sample_rate),
+                Err(ConfigError::UnsupportedSampleRate {
+                    sample_rate,
+                    window_size: WINDOW_SIZE,
+                    min: 4_096,
+                    max: 102_400,
+                })
+            );
+        }
+        for sample_rate in [4_096, 8_000, 22_050, 44_100, 48_000, 96_000, 102_400] {
+            assert_eq!(config.validate_for(sample_rate), Ok(()));
+        }
+    }
+
+    #[test]
+    fn builder_validates() {
+        assert_eq!(
+            FingerprintConfig::builder().delta_t_bin(0).build(),
+            Err(ConfigError::ZeroDeltaTBin)
+        );
+        assert_eq!(
+            FingerprintConfig::builder().delta_t_bin(2).build(),
+            Ok(with(|c| c.delta_t_bin = 2))
+        );
+    }
+}
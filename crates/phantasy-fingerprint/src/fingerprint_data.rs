@@ -0,0 +1,231 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FingerprintData {
+    /// Pairs of (f1, f2, deltaTime), mapped to the "anchor time" offset
+    /// We store them in a Vec for demonstration, but you might store differently.
+    pub pairs: Vec<FPHashEntry>,
+    /// Pairs from the side (L−R) channel when fingerprinted in `ChannelMode::MidSide`, in which
+    /// case `pairs` come from the mid channel. Only ever matched against other side pairs.
+    /// Empty in mono mode, for sources without a stereo image, and for older caches
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub side_pairs: Vec<FPHashEntry>,
+    /// The file this fingerprint was built from, absent for snippets and older caches
+    #[serde(default)]
+    pub source: Option<SourceInfo>,
+    /// Channel count of the decoded file, absent for in-memory PCM and older caches
+    #[serde(default)]
+    pub channels: Option<u8>,
+    /// `content_hash` as of when the fingerprint was built, to detect corruption on load.
+    /// Absent for older caches
+    #[serde(default)]
+    pub checksum: Option<u64>,
+    /// `FingerprintConfig::stable_hash` of the config the pairs were built with. Absent for
+    /// snippets cut from other fingerprints and older caches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<u64>,
+}
+
+impl FingerprintData {
+    /// A stable hash of the pairs and the config they were built with, independent of the
+    /// pairs' order.
+    ///
+    /// Equal for byte-identical fingerprints built alike, so exact duplicates can be found
+    /// without comparing every pair. When there are side pairs, the mid and then the side
+    /// pairs are each prefixed with their count, so a pair moved between channels changes the
+    /// hash. Mono pairs are hashed bare, and the config hash last when there is one, so older
+    /// mono caches hash as they always have.
+    pub fn content_hash(&self) -> u64 {
+        let sorted = |pairs: &[FPHashEntry]| {
+            let mut pairs: Vec<(u32, u16, u16, u16)> = pairs
+                .iter()
+                .map(|p| (p.anchor_time, p.f1, p.f2, p.delta_t))
+                .collect();
+            pairs.sort_unstable();
+            pairs
+        };
+        let stereo = !self.side_pairs.is_empty();
+        let count = |pairs: &[FPHashEntry]| {
+            stereo
+                .then_some(pairs.len() as u64)
+                .into_iter()
+                .flat_map(u64::to_le_bytes)
+        };
+
+        let bytes = count(&self.pairs)
+            .chain(sorted(&self.pairs).into_iter().flat_map(pair_bytes))
+            .chain(count(&self.side_pairs))
+            .chain(sorted(&self.side_pairs).into_iter().flat_map(pair_bytes));
+        fnv1a(bytes.chain(self.config_hash.into_iter().flat_map(u64::to_le_bytes)))
+    }
+}
+
+/// The bytes a sorted pair is hashed as, in little-endian field order.
+fn pair_bytes((anchor_time, f1, f2, delta_t): (u32, u16, u16, u16)) -> impl Iterator<Item = u8> {
+    anchor_time
+        .to_le_bytes()
+        .into_iter()
+        .chain(f1.to_le_bytes())
+        .chain(f2.to_le_bytes())
+        .chain(delta_t.to_le_bytes())
+}
+
+/// FNV-1a over `bytes`. Used for every hash that is stored, rather than `std`'s hasher, whose
+/// output may change between Rust releases and would invalidate them all.
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Identifies the exact file contents a cached fingerprint was built from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch
+    pub modified: u64,
+    /// `SourceInfo::quick_hash_of` the file, to spot copies of it under other names. Absent for
+    /// older caches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quick_hash: Option<u64>,
+}
+
+#[cfg(feature = "io")]
+impl SourceInfo {
+    /// Bytes hashed from each end of a file by `quick_hash_of`
+    const QUICK_HASH_SPAN: u64 = 1024 * 1024;
+
+    /// Read the current size, modification time, and quick hash of `path`.
+    pub fn read(path: &std::path::Path) -> Result<SourceInfo, std::io::Error> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(std::io::Error::other)?
+            .as_secs();
+        Ok(SourceInfo {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified,
+            quick_hash: Some(Self::quick_hash_of(path)?),
+        })
+    }
+
+    /// Whether the file still looks as it did when `self` was recorded, going by `current`.
+    /// The quick hash is only compared when `self` has one, so older caches aren't all stale.
+    pub fn is_unchanged(&self, current: &SourceInfo) -> bool {
+        self.path == current.path
+            && self.size == current.size
+            && self.modified == current.modified
+            && self
+                .quick_hash
+                .is_none_or(|hash| current.quick_hash == Some(hash))
+    }
+
+    /// A cheap hash of the contents of `path`: its size plus its first and last MiB, `fnv1a`
+    /// hashed. Copies and renames of a file hash alike, while reading at most 2 MiB of it.
+    pub fn quick_hash_of(path: &std::path::Path) -> Result<u64, std::io::Error> {
+        use std::io::Read;
+        use std::io::Seek;
+        use std::io::SeekFrom;
+
+        let mut file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut bytes = Vec::new();
+        (&mut file)
+            .take(Self::QUICK_HASH_SPAN)
+            .read_to_end(&mut bytes)?;
+        let tail_start = size
+            .saturating_sub(Self::QUICK_HASH_SPAN)
+            .max(Self::QUICK_HASH_SPAN);
+        if tail_start < size {
+            file.seek(SeekFrom::Start(tail_start))?;
+            file.read_to_end(&mut bytes)?;
+        }
+
+        Ok(fnv1a(size.to_le_bytes().into_iter().chain(bytes)))
+    }
+}
+
+// Each "hash" from a peak pair
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FPHashEntry {
+    pub f1: u16,
+    pub f2: u16,
+    pub delta_t: u16,
+    /// The offset (in spectrogram frames) when this pair occurred
+    pub anchor_time: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint_config::FingerprintConfig;
+
+    #[test]
+    fn fnv1a_matches_reference_vectors() {
+        assert_eq!(fnv1a([]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(*b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(*b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn content_hash_covers_pairs_and_config() {
+        let pair = |anchor_time, f1| FPHashEntry {
+            f1,
+            f2: 7,
+            delta_t: 1,
+            anchor_time,
+        };
+        let data = FingerprintData {
+            pairs: vec![pair(0, 3), pair(1, 4)],
+            side_pairs: Vec::new(),
+            source: None,
+            channels: None,
+            checksum: None,
+            config_hash: Some(FingerprintConfig::default().stable_hash()),
+        };
+        let reordered = FingerprintData {
+            pairs: vec![pair(1, 4), pair(0, 3)],
+            ..data.clone()
+        };
+        let other_pairs = FingerprintData {
+            pairs: vec![pair(0, 3), pair(1, 5)],
+            ..data.clone()
+        };
+        let other_config = FingerprintData {
+            config_hash: Some(FingerprintConfig::speech().stable_hash()),
+            ..data.clone()
+        };
+        assert_eq!(data.content_hash(), reordered.content_hash());
+        assert_ne!(data.content_hash(), other_pairs.content_hash());
+        assert_ne!(data.content_hash(), other_config.content_hash());
+
+        // The same pairs split differently between mid and side
+        let stereo = FingerprintData {
+            pairs: vec![pair(0, 3)],
+            side_pairs: vec![pair(1, 4), pair(2, 5)],
+            ..data.clone()
+        };
+        let moved = FingerprintData {
+            pairs: vec![pair(0, 3), pair(1, 4)],
+            side_pairs: vec![pair(2, 5)],
+            ..data.clone()
+        };
+        let all_mid = FingerprintData {
+            pairs: vec![pair(0, 3), pair(1, 4), pair(2, 5)],
+            ..data.clone()
+        };
+        assert_ne!(stereo.content_hash(), moved.content_hash());
+        assert_ne!(moved.content_hash(), all_mid.content_hash());
+    }
+}
@@ -0,0 +1,57 @@
+use crate::fingerprint_config::ConfigError;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Why a file could not be fingerprinted.
+///
+/// A library scan can skip `Decode`, `UnsupportedFormat`, and `TooShort` files and keep going,
+/// while `Io` and `Serialize` usually point at the environment rather than the file.
+#[derive(Debug, thiserror::Error)]
+pub enum FingerprintError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Failed to decode {path:?}")]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[cfg(feature = "io")]
+    #[error("Failed to (de)serialize fingerprint")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Cached fingerprint {path:?} is truncated or does not match its checksum")]
+    Corrupt { path: PathBuf },
+    #[error("Unsupported format in {path:?}: {reason}")]
+    UnsupportedFormat { path: PathBuf, reason: String },
+    #[error("Audio is too short to fingerprint: {samples} samples, need at least {required}")]
+    TooShort { samples: usize, required: usize },
+    #[cfg(feature = "arrow")]
+    #[error("Failed to build Arrow record batch")]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[cfg(feature = "arrow")]
+    #[error("Failed to write Parquet")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("Invalid fingerprint config")]
+    Config(#[from] ConfigError),
+    #[error("Failed to measure loudness")]
+    Loudness(#[from] ebur128::Error),
+}
+
+impl FingerprintError {
+    pub fn decode(
+        path: &Path,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> FingerprintError {
+        FingerprintError::Decode {
+            path: path.to_path_buf(),
+            source: source.into(),
+        }
+    }
+
+    pub fn unsupported_format(path: &Path, reason: impl Into<String>) -> FingerprintError {
+        FingerprintError::UnsupportedFormat {
+            path: path.to_path_buf(),
+            reason: reason.into(),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::compute_fingerprint::compute_fingerprint_mid_side;
+use crate::compute_fingerprint::compute_fingerprint_with_config;
+use crate::decode::OggCodec;
+use crate::decode::decode_to_mid_side_f32;
+use crate::decode::decode_to_mid_side_f32_lenient;
+use crate::decode::decode_to_mono_f32;
+use crate::decode::decode_to_mono_f32_lenient;
+use crate::decode::detect_channel_layout;
+use crate::decode::detect_ogg_codec;
+use crate::decode::detect_sample_rate;
+use crate::decode::is_ogg;
+use crate::fingerprint_config::ChannelMode;
+use crate::fingerprint_config::FingerprintConfig;
+use crate::fingerprint_data::FingerprintData;
+use crate::fingerprint_data::SourceInfo;
+use crate::fingerprint_error::FingerprintError;
+use crate::resample::resample;
+use std::path::Path;
+use std::time::Duration;
+use tracing::warn;
+
+/// What `fingerprint_file` learned about a file while decoding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileAudioInfo {
+    /// Length of the decoded audio
+    pub duration: Duration,
+    /// Rate of the decoded audio, which the fingerprint was computed at. Always 48 kHz for Opus
+    pub sample_rate: u32,
+    /// Channels in the file, before any downmix
+    pub channels: u8,
+    /// Codec inside the OGG container, `None` for MP3, FLAC, and WAV files
+    pub codec: Option<OggCodec>,
+}
+
+/// Decode the file at `path` and fingerprint it with `config`, returning the fingerprint along
+/// with its duration, sample rate, channel count, and codec.
+///
+/// This is the one call most uses need. The fingerprint is computed at the file's own sample
+/// rate and records its source and channels, ready to save with `cache::save_fingerprint`.
+pub fn fingerprint_file(
+    path: &Path,
+    config: &FingerprintConfig,
+) -> Result<(FingerprintData, FileAudioInfo), FingerprintError> {
+    let codec = if is_ogg(path) {
+        Some(detect_ogg_codec(path)?)
+    } else {
+        None
+    };
+    let channels = detect_channel_layout(path)?.channels();
+    let sample_rate = detect_sample_rate(path)?;
+
+    let (data, samples) = decode_and_fingerprint(path, sample_rate as usize, config)?;
+    let info = FileAudioInfo {
+        duration: Duration::from_secs_f64(samples as f64 / sample_rate as f64),
+        sample_rate,
+        channels,
+        codec,
+    };
+    Ok((data, info))
+}
+
+/// Decode `path` as `config.channel_mode` asks and fingerprint it at `sample_rate`, resampling
+/// with `config.resample_quality` if the file decodes at another rate, and record its source
+/// and channels. Also returns how many samples per channel were decoded, before resampling.
+pub(crate) fn decode_and_fingerprint(
+    path: &Path,
+    sample_rate: usize,
+    config: &FingerprintConfig,
+) -> Result<(FingerprintData, usize), FingerprintError> {
+    let (decoded, file_rate) = DecodedChannels::decode(path, config)?;
+    let samples = decoded.len();
+    let mut data = decoded.fingerprint(file_rate, sample_rate, config)?;
+    data.source = Some(SourceInfo::read(path)?);
+    data.channels = Some(detect_channel_layout(path)?.channels());
+    Ok((data, samples))
+}
+
+/// A file's PCM in the channels a `ChannelMode` fingerprints.
+pub(crate) enum DecodedChannels {
+    Mono(Vec<f32>),
+    MidSide { mid: Vec<f32>, side: Vec<f32> },
+}
+
+impl DecodedChannels {
+    /// Decode `path` into the channels `config.channel_mode` fingerprints, along with the rate
+    /// it decoded at, skipping corrupt packets if `config.skip_corrupt_packets` allows.
+    pub(crate) fn decode(
+        path: &Path,
+        config: &FingerprintConfig,
+    ) -> Result<(DecodedChannels, usize), FingerprintError> {
+        let (decoded, file_rate, skipped) = match (config.channel_mode, config.skip_corrupt_packets)
+        {
+            (ChannelMode::Mono, false) => {
+                let (pcm, file_rate) = decode_to_mono_f32(path)?;
+                (DecodedChannels::Mono(pcm), file_rate, 0)
+            }
+            (ChannelMode::Mono, true) => {
+                let (pcm, file_rate, skipped) = decode_to_mono_f32_lenient(path)?;
+                (DecodedChannels::Mono(pcm), file_rate, skipped)
+            }
+            (ChannelMode::MidSide, skip) => {
+                let file_rate = detect_sample_rate(path)?;
+                let (mid, side, skipped) = if skip {
+                    decode_to_mid_side_f32_lenient(path)?
+                } else {
+                    let (mid, side) = decode_to_mid_side_f32(path)?;
+                    (mid, side, 0)
+                };
+                (DecodedChannels::MidSide { mid, side }, file_rate, skipped)
+            }
+        };
+        if skipped > 0 {
+            warn!("Skipped {} corrupt packets of {:?}", skipped, path);
+        }
+        Ok((decoded, file_rate as usize))
+    }
+
+    /// Samples per channel.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            DecodedChannels::Mono(pcm) => pcm.len(),
+            DecodedChannels::MidSide { mid, .. } => mid.len(),
+        }
+    }
+
+    /// Fingerprint at `sample_rate` with `config`, resampling from `file_rate` with
+    /// `config.resample_quality` if they differ.
+    pub(crate) fn fingerprint(
+        self,
+        file_rate: usize,
+        sample_rate: usize,
+        config: &FingerprintConfig,
+    ) -> Result<FingerprintData, FingerprintError> {
+        let to_analysis_rate = |pcm: Vec<f32>| {
+            if file_rate == sample_rate {
+                pcm
+            } else {
+                resample(&pcm, file_rate, sample_rate, config.resample_quality)
+            }
+        };
+        match self {
+            DecodedChannels::Mono(pcm) => {
+                compute_fingerprint_with_config(&to_analysis_rate(pcm), sample_rate, config)
+            }
+            DecodedChannels::MidSide { mid, side } => compute_fingerprint_mid_side(
+                &to_analysis_rate(mid),
+                &to_analysis_rate(side),
+                sample_rate,
+                config,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_matches::find_matches;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+    use crate::test_signal::write_wav;
+
+    #[test]
+    fn mono_recording_identifies_a_mid_side_track() {
+        let dir = std::env::temp_dir().join(format!("phantasy-mono-query-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (mid, side) = (noise(20.0, 0), noise(20.0, 1));
+        let stereo: Vec<f32> = mid
+            .iter()
+            .zip(&side)
+            .flat_map(|(m, s)| [0.5 * m + 0.3 * s, 0.5 * m - 0.3 * s])
+            .collect();
+        // A phone hears the downmix, 5 s of it from 8 s in
+        let recording: Vec<f32> = mid[8 * SAMPLE_RATE..13 * SAMPLE_RATE]
+            .iter()
+            .map(|m| 0.5 * m)
+            .collect();
+        let (track_path, recording_path) = (dir.join("track.wav"), dir.join("recording.wav"));
+        write_wav(&track_path, &stereo, SAMPLE_RATE as u32, 2);
+        write_wav(&recording_path, &recording, SAMPLE_RATE as u32, 1);
+
+        let mid_side = FingerprintConfig {
+            channel_mode: ChannelMode::MidSide,
+            ..FingerprintConfig::default()
+        };
+        let track = fingerprint_file(&track_path, &mid_side);
+        let recording = fingerprint_file(&recording_path, &FingerprintConfig::default());
+        let _ = std::fs::remove_dir_all(&dir);
+        let ((track, _), (recording, _)) = (track.unwrap(), recording.unwrap());
+
+        assert!(!track.side_pairs.is_empty());
+        assert!(recording.side_pairs.is_empty());
+        let found = find_matches(&track, &recording, SAMPLE_RATE, None, false)
+            .expect("mono recording should match the stereo track");
+        assert!(
+            (found.offset_sec - 8.0).abs() < 0.05,
+            "{}",
+            found.offset_sec
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use crate::find_matches::frames_per_sec;
+use crate::fingerprint_data::FPHashEntry;
+use crate::fingerprint_data::FingerprintData;
+use crate::hash_bloom::HashBloom;
+use crate::match_config::MatchConfig;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+/// A pair hash, (f1, f2, delta_t)
+pub type PairHash = (u16, u16, u16);
+
+/// Where a pair hash occurs: the track's position among those inserted, and the anchor frame
+/// within it.
+type Postings = HashMap<PairHash, Vec<(u32, u32)>>;
+
+/// Maps each pair hash of one or more tracks to the anchor times it occurs at, so a snippet is
+/// matched against all of them in a single pass over its pairs instead of one `find_matches`
+/// call per track.
+///
+/// Like `find_matches`, mid (or mono) pairs only collide with mid pairs and side pairs with
+/// side pairs. Tracks and snippets must be fingerprinted at the same sample rate and settings.
+#[derive(Debug, Clone, Default)]
+pub struct FingerprintIndex {
+    track_ids: Vec<String>,
+    mid: Postings,
+    side: Postings,
+    /// A filter per track and the thresholds they reject snippets by, when built `with_bloom`
+    bloom: Option<(MatchConfig, Vec<HashBloom>)>,
+}
+
+/// How evenly a `FingerprintIndex` spreads over the hash space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionStats {
+    /// Number of different hashes, counting those on both channels twice
+    pub distinct_hashes: usize,
+    /// Number of (hash, anchor time) entries across all hashes
+    pub total_postings: usize,
+    /// Postings-list length -> how many hashes have a list that long
+    pub histogram: BTreeMap<usize, usize>,
+    /// The hashes with the longest postings lists and their lengths, longest first
+    pub busiest: Vec<(PairHash, usize)>,
+}
+
+/// A track's best offset for a snippet queried against a `FingerprintIndex`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndexMatch {
+    /// The ID the track was inserted under
+    pub track_id: String,
+    /// Where the snippet begins within the track, in seconds
+    pub offset_sec: f32,
+    /// How many hash collisions agreed on that offset
+    pub count: usize,
+}
+
+impl FingerprintIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An index that keeps a [`HashBloom`] of each track, at the false-positive rate in
+    /// `config`, to skip tracks a snippet can't match when queried.
+    pub fn with_bloom(config: MatchConfig) -> Self {
+        Self {
+            bloom: Some((config, Vec::new())),
+            ..Self::default()
+        }
+    }
+
+    /// An index of `fp` alone, inserted under an empty track ID.
+    pub fn from_fingerprint(fp: &FingerprintData) -> Self {
+        let mut index = Self::new();
+        index.insert("", fp);
+        index
+    }
+
+    /// Add the pairs of `fp` under `track_id`, e.g. its path or Spotify ID. Inserting the same
+    /// ID twice indexes both fingerprints, and matches report them separately.
+    pub fn insert(&mut self, track_id: impl Into<String>, fp: &FingerprintData) {
+        let track = self.track_ids.len() as u32;
+        self.track_ids.push(track_id.into());
+        if let Some((config, blooms)) = &mut self.bloom {
+            blooms.push(HashBloom::from_fingerprint(
+                fp,
+                config.bloom_false_positive_rate,
+            ));
+        }
+        for (postings, pairs) in [(&mut self.mid, &fp.pairs), (&mut self.side, &fp.side_pairs)] {
+            for hash_ent in pairs {
+                postings
+                    .entry((hash_ent.f1, hash_ent.f2, hash_ent.delta_t))
+                    .or_default()
+                    .push((track, hash_ent.anchor_time));
+            }
+        }
+    }
+
+    /// Number of fingerprints inserted.
+    pub fn len(&self) -> usize {
+        self.track_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.track_ids.is_empty()
+    }
+
+    /// The (track, anchor time) pairs `hash` occurs at on the given channel, empty if it never
+    /// does. Tracks are numbered in the order they were inserted, from 0.
+    pub fn get(&self, hash: &PairHash, side: bool) -> &[(u32, u32)] {
+        let postings = if side { &self.side } else { &self.mid };
+        postings.get(hash).map_or(&[], Vec::as_slice)
+    }
+
+    /// The best offset of `snippet` in every track it collides with at all, most collisions
+    /// first, ties broken by track ID for stable output.
+    ///
+    /// Votes are counted for every (track, offset) at once, so this is one histogram however
+    /// many tracks there are. No threshold is applied: a count means little on its own, so pass
+    /// the leading tracks to `find_matches` to judge how significant their best offset is.
+    ///
+    /// In an index built `with_bloom`, tracks whose filter rules out enough collisions for
+    /// `find_match_outcome` to offer them under its config are skipped before their votes are
+    /// counted, and left out.
+    pub fn query(&self, snippet: &FingerprintData, sample_rate: usize) -> Vec<IndexMatch> {
+        let rejected: Vec<bool> = match &self.bloom {
+            Some((config, blooms)) => blooms
+                .iter()
+                .map(|bloom| !bloom.could_match(snippet, config))
+                .collect(),
+            None => vec![false; self.track_ids.len()],
+        };
+        let mut votes: HashMap<(u32, i32), usize> = HashMap::new();
+        count_votes(&self.mid, &snippet.pairs, &rejected, &mut votes);
+        count_votes(&self.side, &snippet.side_pairs, &rejected, &mut votes);
+
+        // Best offset per track, ties broken by the earliest offset as `find_matches` does
+        let mut best: HashMap<u32, (i32, usize)> = HashMap::new();
+        for ((track, offset), count) in votes {
+            let entry = best.entry(track).or_insert((offset, count));
+            if count > entry.1 || (count == entry.1 && offset < entry.0) {
+                *entry = (offset, count);
+            }
+        }
+
+        let frames_per_sec = frames_per_sec(sample_rate);
+        let mut matches: Vec<IndexMatch> = best
+            .into_iter()
+            .map(|(track, (offset, count))| IndexMatch {
+                track_id: self.track_ids[track as usize].clone(),
+                offset_sec: offset as f32 / frames_per_sec,
+                count,
+            })
+            .collect();
+        matches.sort_by(|a, b| b.count.cmp(&a.count).then(a.track_id.cmp(&b.track_id)));
+        matches
+    }
+
+    /// Distribution of postings-list lengths, plus the `top_k` busiest hashes.
+    ///
+    /// Hashes with huge postings lists aren't distinctive: they slow matching and vote for many
+    /// offsets by chance. A heavy tail here suggests the peak or pairing settings are too loose.
+    /// The mid and side channels keep separate lists.
+    pub fn collision_stats(&self, top_k: usize) -> CollisionStats {
+        let lists = || self.mid.iter().chain(&self.side);
+        let mut histogram = BTreeMap::new();
+        for (_, anchors) in lists() {
+            *histogram.entry(anchors.len()).or_insert(0) += 1;
+        }
+
+        let mut busiest: Vec<(PairHash, usize)> = lists()
+            .map(|(&hash, anchors)| (hash, anchors.len()))
+            .collect();
+        // longest first, ties broken by hash for stable output
+        busiest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        busiest.truncate(top_k);
+
+        CollisionStats {
+            distinct_hashes: self.mid.len() + self.side.len(),
+            total_postings: lists().map(|(_, anchors)| anchors.len()).sum(),
+            histogram,
+            busiest,
+        }
+    }
+}
+
+/// Add a vote for every (track, offset) that each of `snippet_pairs` collides at, except in
+/// `rejected` tracks.
+fn count_votes(
+    postings: &Postings,
+    snippet_pairs: &[FPHashEntry],
+    rejected: &[bool],
+    votes: &mut HashMap<(u32, i32), usize>,
+) {
+    for hash_ent in snippet_pairs {
+        let key = (hash_ent.f1, hash_ent.f2, hash_ent.delta_t);
+        for &(track, anchor_time) in postings.get(&key).into_iter().flatten() {
+            if rejected[track as usize] {
+                continue;
+            }
+            let offset = anchor_time as i32 - hash_ent.anchor_time as i32;
+            *votes.entry((track, offset)).or_insert(0) += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_fingerprint::HOP_SIZE;
+    use crate::compute_fingerprint::compute_fingerprint;
+    use crate::find_matches::find_matches;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+
+    #[test]
+    fn query_finds_the_track_and_offset_find_matches_does() {
+        let tracks: Vec<FingerprintData> = (0..3)
+            .map(|seed| compute_fingerprint(&noise(20.0, seed), SAMPLE_RATE).unwrap())
+            .collect();
+        let mut index = FingerprintIndex::new();
+        for (id, fp) in ["a", "b", "c"].iter().zip(&tracks) {
+            index.insert(*id, fp);
+        }
+        let start = 216 * HOP_SIZE;
+        let snippet =
+            compute_fingerprint(&noise(20.0, 1)[start..start + 5 * SAMPLE_RATE], SAMPLE_RATE)
+                .unwrap();
+
+        let matches = index.query(&snippet, SAMPLE_RATE);
+        let expected = find_matches(&tracks[1], &snippet, SAMPLE_RATE, None, false).unwrap();
+        assert_eq!(matches[0].track_id, "b");
+        assert_eq!(matches[0].offset_sec, expected.offset_sec);
+        assert_eq!(matches[0].count, expected.count);
+        assert!(matches[1..].iter().all(|m| m.count * 10 < expected.count));
+    }
+
+    #[test]
+    fn collision_stats_cover_every_track() {
+        let tracks: Vec<FingerprintData> = (0..2)
+            .map(|seed| compute_fingerprint(&noise(5.0, seed), SAMPLE_RATE).unwrap())
+            .collect();
+        let mut index = FingerprintIndex::new();
+        for (id, fp) in ["a", "b"].iter().zip(&tracks) {
+            index.insert(*id, fp);
+        }
+        let stats = index.collision_stats(3);
+        assert_eq!(
+            stats.total_postings,
+            tracks.iter().map(|fp| fp.pairs.len()).sum::<usize>()
+        );
+        assert_eq!(
+            stats.histogram.values().sum::<usize>(),
+            stats.distinct_hashes
+        );
+        assert_eq!(stats.busiest.len(), 3);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::decode::detect_channel_layout;
+use crate::fingerprint_config::FingerprintConfig;
+use crate::fingerprint_data::FingerprintData;
+use crate::fingerprint_data::SourceInfo;
+use crate::fingerprint_error::FingerprintError;
+use crate::fingerprint_file::DecodedChannels;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+
+/// Sizing of `fingerprint_files_pipelined`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// Decoded files allowed to wait for a worker. Caps memory at roughly this many files of PCM
+    /// beyond the ones being fingerprinted.
+    pub channel_depth: usize,
+    /// Threads computing fingerprints from decoded PCM
+    pub workers: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            channel_depth: 2,
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// A decoded file on its way to a worker, tagged with its position in the input.
+struct Decoded {
+    index: usize,
+    path: PathBuf,
+    /// The PCM, its sample rate, and the channels in the file
+    pcm: Result<(DecodedChannels, usize, u8), FingerprintError>,
+}
+
+/// Fingerprint every file in `paths`, decoding the next files while earlier ones are in the FFT.
+///
+/// A decoder thread feeds PCM through a bounded channel to `pipeline.workers` fingerprint
+/// threads, blocking when the channel is full so fast decoding can't pile up memory. Results
+/// come back in the order of `paths`, each with its source info and channel count recorded,
+/// matching what `build_and_save_fingerprint_with_config` would produce with `config` one file
+/// at a time. Files at another rate than `sample_rate` are resampled to it first, as there.
+pub fn fingerprint_files_pipelined(
+    paths: &[PathBuf],
+    sample_rate: usize,
+    config: &FingerprintConfig,
+    pipeline: &PipelineConfig,
+) -> Vec<(PathBuf, Result<FingerprintData, FingerprintError>)> {
+    let (pcm_tx, pcm_rx) = mpsc::sync_channel::<Decoded>(pipeline.channel_depth);
+    let pcm_rx = Arc::new(Mutex::new(pcm_rx));
+    let (result_tx, result_rx) = mpsc::channel();
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for (index, path) in paths.iter().enumerate() {
+                let pcm = decode_with_channels(path, config);
+                let decoded = Decoded {
+                    index,
+                    path: path.clone(),
+                    pcm,
+                };
+                // Every worker hung up, nobody is left to fingerprint
+                if pcm_tx.send(decoded).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..pipeline.workers.max(1) {
+            let pcm_rx = Arc::clone(&pcm_rx);
+            let result_tx = result_tx.clone();
+            scope.spawn(move || {
+                loop {
+                    // Hold the lock only while receiving so workers fingerprint concurrently
+                    let next = pcm_rx.lock().map(|rx| rx.recv());
+                    let Ok(Ok(decoded)) = next else {
+                        break;
+                    };
+                    let result = decoded.pcm.and_then(|(pcm, file_rate, channels)| {
+                        let mut data = pcm.fingerprint(file_rate, sample_rate, config)?;
+                        data.source = Some(SourceInfo::read(&decoded.path)?);
+                        data.channels = Some(channels);
+                        Ok(data)
+                    });
+                    if result_tx
+                        .send((decoded.index, decoded.path, result))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    drop(result_tx);
+
+    let mut results: Vec<_> = result_rx.into_iter().collect();
+    results.sort_by_key(|(index, _, _)| *index);
+    results
+        .into_iter()
+        .map(|(_, path, result)| (path, result))
+        .collect()
+}
+
+fn decode_with_channels(
+    path: &Path,
+    config: &FingerprintConfig,
+) -> Result<(DecodedChannels, usize, u8), FingerprintError> {
+    let channels = detect_channel_layout(path)?.channels();
+    let (pcm, file_rate) = DecodedChannels::decode(path, config)?;
+    Ok((pcm, file_rate, channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint_config::ChannelMode;
+    use crate::fingerprint_file::decode_and_fingerprint;
+    use crate::resample::ResampleQuality;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+    use crate::test_signal::write_wav;
+
+    #[test]
+    fn output_matches_sequential_fingerprinting() {
+        let dir = std::env::temp_dir().join(format!("phantasy-pipeline-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths: Vec<PathBuf> = (0..5)
+            .map(|seed| {
+                let path = dir.join(format!("{seed}.wav"));
+                // Every other file at twice the rate, to be resampled, and the first few stereo
+                let rate = SAMPLE_RATE as u32 * (1 + seed as u32 % 2);
+                let channels = if seed < 3 { 2 } else { 1 };
+                let pcm = noise((2.0 + seed as f32) * channels as f32, seed);
+                write_wav(&path, &pcm, rate, channels);
+                path
+            })
+            .collect();
+        paths.insert(2, dir.join("missing.wav"));
+
+        let config = FingerprintConfig {
+            channel_mode: ChannelMode::MidSide,
+            trim_head_secs: 0.5,
+            resample_quality: ResampleQuality::Fast,
+            ..FingerprintConfig::default()
+        };
+        let pipelined = fingerprint_files_pipelined(
+            &paths,
+            SAMPLE_RATE,
+            &config,
+            &PipelineConfig {
+                channel_depth: 1,
+                workers: 3,
+            },
+        );
+        let sequential: Vec<_> = paths
+            .iter()
+            .map(|path| decode_and_fingerprint(path, SAMPLE_RATE, &config).map(|(data, _)| data))
+            .collect();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(pipelined.len(), paths.len());
+        for ((path, pipelined), (expected_path, sequential)) in
+            pipelined.iter().zip(paths.iter().zip(&sequential))
+        {
+            assert_eq!(path, expected_path);
+            match (pipelined, sequential) {
+                (Ok(pipelined), Ok(sequential)) => assert_eq!(pipelined, sequential, "{path:?}"),
+                (Err(_), Err(_)) => {}
+                _ => panic!("{path:?}: {pipelined:?} but sequentially {sequential:?}"),
+            }
+        }
+        assert!(pipelined[2].1.is_err());
+        // The config was applied, not the default
+        assert!(!pipelined[0].1.as_ref().unwrap().side_pairs.is_empty());
+    }
+}
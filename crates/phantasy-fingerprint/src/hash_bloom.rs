@@ -0,0 +1,201 @@
+use crate::fingerprint_data::FPHashEntry;
+use crate::fingerprint_data::FingerprintData;
+use crate::fingerprint_index::PairHash;
+use crate::match_config::MatchConfig;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// False-positive rate of [`MatchConfig::default`], when there's no reason to pick another.
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// A Bloom filter over the pair hashes of one track, to skip tracks a snippet can't match
+/// without building their hash maps and offset histograms.
+///
+/// Build it once per track alongside its fingerprint, then call [`HashBloom::could_match`] with
+/// each snippet before `find_match_outcome`, as a `FingerprintIndex` built with `with_bloom`
+/// does. It never rejects a track `find_match_outcome` would offer: every vote for an offset
+/// needs a snippet pair whose hash is in the track, and the filter never misses a hash it
+/// holds. A lower false-positive rate rejects more non-matching tracks at the cost of a larger
+/// filter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashBloom {
+    bits: Vec<u64>,
+    n_hashes: u32,
+}
+
+impl HashBloom {
+    /// A filter holding every hash of `track_fp`, sized for `false_positive_rate` (in `(0, 1)`).
+    pub fn from_fingerprint(track_fp: &FingerprintData, false_positive_rate: f64) -> Self {
+        let n_items = (track_fp.pairs.len() + track_fp.side_pairs.len()).max(1) as f64;
+        let p = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        // The usual optimum: m = -n ln p / (ln 2)^2 bits and k = (m / n) ln 2 hash functions
+        let n_bits = (-n_items * p.ln() / std::f64::consts::LN_2.powi(2)).ceil() as usize;
+        let n_words = n_bits.div_ceil(64).max(1);
+        let n_hashes = ((n_words * 64) as f64 / n_items * std::f64::consts::LN_2)
+            .round()
+            .clamp(1.0, 32.0) as u32;
+
+        let mut bloom = Self {
+            bits: vec![0; n_words],
+            n_hashes,
+        };
+        for (pairs, side) in [(&track_fp.pairs, false), (&track_fp.side_pairs, true)] {
+            for hash_ent in pairs {
+                let bits: Vec<usize> = bloom.bit_indices(key_of(hash_ent), side).collect();
+                for bit in bits {
+                    bloom.bits[bit / 64] |= 1 << (bit % 64);
+                }
+            }
+        }
+        bloom
+    }
+
+    /// Whether the track might contain `hash` on the given channel. `false` is certain.
+    pub fn may_contain(&self, hash: &PairHash, side: bool) -> bool {
+        self.bit_indices(*hash, side)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Whether enough of `snippet_fp`'s pairs might occur in the track for `find_match_outcome`
+    /// to offer it under `config`, as a match or a guess. `false` means it would certainly
+    /// return `NoMatch`, `true` that it has to be run to tell.
+    pub fn could_match(&self, snippet_fp: &FingerprintData, config: &MatchConfig) -> bool {
+        let needed = min_collisions(config);
+        let mut shared = 0;
+        for (pairs, side) in [(&snippet_fp.pairs, false), (&snippet_fp.side_pairs, true)] {
+            for hash_ent in pairs {
+                if self.may_contain(&key_of(hash_ent), side) {
+                    shared += 1;
+                    if shared >= needed {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Size of the filter in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// The bits `hash` sets, by double hashing two halves of one 64-bit mix.
+    fn bit_indices(&self, (f1, f2, delta_t): PairHash, side: bool) -> impl Iterator<Item = usize> {
+        let key = (f1 as u64) << 32 | (f2 as u64) << 16 | delta_t as u64 | (side as u64) << 48;
+        let mixed = splitmix64(key);
+        let (h1, h2) = (mixed as u32 as u64, (mixed >> 32) | 1);
+        let n_bits = self.bits.len() as u64 * 64;
+        (0..self.n_hashes as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % n_bits) as usize)
+    }
+}
+
+/// The fewest collisions at one offset `find_match_outcome` offers anything for under `config`.
+fn min_collisions(config: &MatchConfig) -> usize {
+    (config.min_count + 1).min(config.ambiguous_min_count)
+}
+
+fn key_of(hash_ent: &FPHashEntry) -> PairHash {
+    (hash_ent.f1, hash_ent.f2, hash_ent.delta_t)
+}
+
+/// Spreads nearby keys across the whole 64-bit range. Stable across Rust releases, unlike
+/// `std`'s hasher, so serialized filters stay valid.
+pub(crate) fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_fingerprint::compute_fingerprint;
+    use crate::find_matches::MatchOutcome;
+    use crate::find_matches::find_match_outcome;
+    use crate::fingerprint_index::FingerprintIndex;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+
+    #[test]
+    fn never_rejects_a_track_find_match_outcome_offers() {
+        let tracks: Vec<FingerprintData> = (0..4)
+            .map(|seed| compute_fingerprint(&noise(10.0, seed), SAMPLE_RATE).unwrap())
+            .collect();
+        // A loose filter and lowered thresholds, where false negatives would show first
+        let config = MatchConfig {
+            min_count: 1,
+            ambiguous_min_count: 2,
+            max_p_value: 1.0,
+            ambiguous_max_p_value: 1.0,
+            min_coherence: 0.0,
+            bloom_false_positive_rate: 0.3,
+            ..MatchConfig::default()
+        };
+        let blooms: Vec<HashBloom> = tracks
+            .iter()
+            .map(|fp| HashBloom::from_fingerprint(fp, config.bloom_false_positive_rate))
+            .collect();
+
+        let mut offered = 0;
+        for seed in 0..8 {
+            let snippet =
+                compute_fingerprint(&noise(10.0, seed)[..2 * SAMPLE_RATE], SAMPLE_RATE).unwrap();
+            for (track_fp, bloom) in tracks.iter().zip(&blooms) {
+                let outcome =
+                    find_match_outcome(track_fp, &snippet, SAMPLE_RATE, None, false, &config);
+                if !matches!(outcome, MatchOutcome::NoMatch) {
+                    offered += 1;
+                    assert!(bloom.could_match(&snippet, &config));
+                }
+            }
+        }
+        // Chance collisions make guesses of most pairs, so the check above had work to do
+        assert!(offered > tracks.len());
+    }
+
+    #[test]
+    fn rejects_unrelated_snippets() {
+        let track = compute_fingerprint(&noise(10.0, 0), SAMPLE_RATE).unwrap();
+        let bloom = HashBloom::from_fingerprint(&track, DEFAULT_FALSE_POSITIVE_RATE);
+        // Few enough pairs that false positives stay under the `ambiguous_min_count` of 3
+        let unrelated = FingerprintData {
+            pairs: track.pairs[..20]
+                .iter()
+                .map(|p| FPHashEntry {
+                    // Beyond the target zone, so never produced by `compute_fingerprint`
+                    delta_t: p.delta_t + 100,
+                    ..p.clone()
+                })
+                .collect(),
+            ..track.clone()
+        };
+        assert!(bloom.could_match(&track, &MatchConfig::default()));
+        assert!(!bloom.could_match(&unrelated, &MatchConfig::default()));
+    }
+
+    #[test]
+    fn index_with_bloom_keeps_every_match() {
+        let mut plain = FingerprintIndex::new();
+        let mut filtered = FingerprintIndex::with_bloom(MatchConfig::default());
+        for seed in 0..4 {
+            let fp = compute_fingerprint(&noise(10.0, seed), SAMPLE_RATE).unwrap();
+            plain.insert(seed.to_string(), &fp);
+            filtered.insert(seed.to_string(), &fp);
+        }
+        let snippet =
+            compute_fingerprint(&noise(10.0, 2)[SAMPLE_RATE..4 * SAMPLE_RATE], SAMPLE_RATE)
+                .unwrap();
+        let plain = plain.query(&snippet, SAMPLE_RATE);
+        let filtered = filtered.query(&snippet, SAMPLE_RATE);
+        assert_eq!(filtered[0], plain[0]);
+        assert_eq!(filtered[0].track_id, "2");
+        for m in &plain {
+            if m.count > MatchConfig::default().min_count {
+                assert!(filtered.contains(m));
+            }
+        }
+    }
+}
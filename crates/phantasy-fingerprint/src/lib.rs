@@ -0,0 +1,49 @@
+//! Shazam-style audio fingerprinting: spectrogram, peak picking, pair hashing, and matching.
+//!
+//! Everything outside the `io` feature works on in-memory PCM and builds for
+//! `wasm32-unknown-unknown`, leaving decoding to the host (e.g. the browser's Web Audio API).
+//!
+//! With `io`, start from [`fingerprint_file::fingerprint_file`], which decodes a file and
+//! fingerprints it in one call. The modules it builds on stay public for finer control.
+pub mod auto_tune;
+#[cfg(feature = "io")]
+pub mod cache;
+pub mod compute_fingerprint;
+pub mod compute_spectrogram;
+#[cfg(feature = "io")]
+pub mod decode;
+#[cfg(feature = "async")]
+pub mod decode_async;
+pub mod export_histogram;
+#[cfg(feature = "arrow")]
+pub mod export_parquet;
+pub mod extract_snippet;
+pub mod find_aligned_regions;
+pub mod find_matches;
+pub mod find_matches_segmented;
+pub mod find_peaks;
+pub mod fingerprint_config;
+pub mod fingerprint_data;
+pub mod fingerprint_error;
+#[cfg(feature = "io")]
+pub mod fingerprint_file;
+pub mod fingerprint_index;
+#[cfg(feature = "io")]
+pub mod fingerprint_pipeline;
+pub mod hash_bloom;
+pub mod match_config;
+#[cfg(feature = "io")]
+pub mod match_lines;
+pub mod melody_index;
+pub mod normalize_loudness;
+pub mod pitch_contour;
+pub mod resample;
+#[cfg(feature = "async")]
+pub mod scan_library;
+pub mod snap_to_onset;
+pub mod spectrogram_backend;
+pub mod streaming_fingerprint;
+pub mod streaming_spectrogram;
+#[cfg(test)]
+mod test_signal;
+pub mod track_metadata;
@@ -0,0 +1,52 @@
+use crate::find_matches::MAX_P_VALUE;
+use crate::find_matches::MIN_COHERENCE;
+use crate::find_matches::MIN_MATCH_COUNT;
+use crate::hash_bloom::DEFAULT_FALSE_POSITIVE_RATE;
+
+/// Thresholds that sort the best offsets of a snippet against a track into a confident match,
+/// a few plausible guesses, or nothing, for `find_match_outcome`.
+///
+/// The best offset is a [`MatchOutcome::Match`](crate::find_matches::MatchOutcome::Match) when
+/// it has more than `min_count` collisions, a p-value of at most `max_p_value`, and a coherence
+/// of at least `min_coherence`. Otherwise, the `max_candidates` best offsets with at least
+/// `ambiguous_min_count` collisions and a p-value of at most `ambiguous_max_p_value` are
+/// [`MatchOutcome::Ambiguous`](crate::find_matches::MatchOutcome::Ambiguous), and without any
+/// the outcome is `NoMatch`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchConfig {
+    /// A match needs more collisions than this at its offset, however significant
+    pub min_count: usize,
+    /// A match must be at least this unlikely to be chance, see
+    /// [`MatchResult::p_value`](crate::find_matches::MatchResult::p_value)
+    pub max_p_value: f64,
+    /// A match must be supported in at least this fraction of the snippet, see
+    /// [`MatchResult::coherence`](crate::find_matches::MatchResult::coherence)
+    pub min_coherence: f32,
+    /// Offsets short of a match need at least this many collisions to be offered as guesses
+    pub ambiguous_min_count: usize,
+    /// Offsets short of a match need at most this p-value to be offered as guesses. Looser
+    /// than `max_p_value`, as a guess only has to be worth showing
+    pub ambiguous_max_p_value: f64,
+    /// Most guesses offered when no offset is a match
+    pub max_candidates: usize,
+    /// How often the [`HashBloom`](crate::hash_bloom::HashBloom) of a track may let through a
+    /// snippet it can't match, in `(0, 1)`. Lower rejects more such tracks before their votes
+    /// are counted, at the cost of larger filters
+    pub bloom_false_positive_rate: f64,
+}
+
+impl Default for MatchConfig {
+    /// The thresholds `find_matches` has always applied, with guesses down to a p-value of 0.05,
+    /// and Bloom filters letting through 1% of the tracks they could reject.
+    fn default() -> Self {
+        Self {
+            min_count: MIN_MATCH_COUNT,
+            max_p_value: MAX_P_VALUE,
+            min_coherence: MIN_COHERENCE,
+            ambiguous_min_count: 3,
+            ambiguous_max_p_value: 0.05,
+            max_candidates: 3,
+            bloom_false_positive_rate: DEFAULT_FALSE_POSITIVE_RATE,
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::find_matches::MatchResult;
+use crate::fingerprint_error::FingerprintError;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// One line of JSON Lines match output, describing the outcome for a single file.
+#[derive(Debug, Serialize)]
+pub struct MatchLine<'a> {
+    pub path: &'a Path,
+    pub matched: bool,
+    /// The fields of the `MatchResult`, absent when nothing matched
+    #[serde(flatten)]
+    pub result: Option<&'a MatchResult>,
+}
+
+/// Write the outcome for `path` as a single line of JSON and flush it, so a consumer reading
+/// the other end sees each file as soon as it is matched, e.g. to stop early on a strong hit.
+///
+/// Lines look like
+/// `{"path":"a.ogg","matched":true,"offset_sec":12.3,"count":41,"p_value":1e-9,"coherence":0.9,"supporting_pairs":null}`
+/// or `{"path":"b.ogg","matched":false}`.
+pub fn write_match_line(
+    mut writer: impl Write,
+    path: &Path,
+    result: Option<&MatchResult>,
+) -> Result<(), FingerprintError> {
+    let line = MatchLine {
+        path,
+        matched: result.is_some(),
+        result,
+    };
+    serde_json::to_writer(&mut writer, &line)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use crate::pitch_contour::PitchContour;
+use crate::pitch_contour::extract_pitch_contour;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Contours are compared at this many frames per second. Coarse enough to forgive wobbly
+/// humming, fine enough to keep short notes
+pub const MELODY_FRAMES_PER_SEC: f32 = 10.0;
+/// Query tempos tried against each track, as ratios of the track's tempo
+const TEMPO_RATIOS: [f32; 7] = [0.7, 0.8, 0.9, 1.0, 1.1, 1.25, 1.4];
+/// Fewest frames where both query and track must be voiced for an alignment to count
+const MIN_VOICED_OVERLAP: usize = 10;
+
+/// Tracks indexed by their melodic contour, for finding a song from a hummed or whistled
+/// query rather than a recording of it.
+///
+/// Unlike the acoustic fingerprints, which only match the exact recording, contours survive a
+/// different singer, key, and tempo. They are much less precise, so results are ranked
+/// candidates rather than confident matches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MelodyIndex {
+    tracks: Vec<(String, PitchContour)>,
+}
+
+/// A track that might be the hummed tune.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MelodyCandidate {
+    /// The id the track was indexed under
+    pub id: String,
+    /// Average distance in semitones between the query and the track where they best align,
+    /// after transposing the query into the track's key. Lower is closer
+    pub distance: f32,
+    /// Where in the track the query best aligns, in seconds
+    pub offset_sec: f32,
+    /// How much faster the query was than the track, e.g. 1.25 for 25% faster
+    pub tempo_ratio: f32,
+}
+
+impl MelodyIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the melody of `pcm` under `id`, usually its path.
+    pub fn insert_pcm(&mut self, id: impl Into<String>, pcm: &[f32], sample_rate: usize) {
+        let contour = extract_pitch_contour(pcm, sample_rate, MELODY_FRAMES_PER_SEC);
+        self.insert(id, contour);
+    }
+
+    /// Add a contour extracted with `MELODY_FRAMES_PER_SEC` under `id`.
+    pub fn insert(&mut self, id: impl Into<String>, contour: PitchContour) {
+        self.tracks.push((id.into(), contour));
+    }
+
+    /// Decode the file at `path` and add its melody, indexed under its path.
+    #[cfg(feature = "io")]
+    pub fn insert_file(
+        &mut self,
+        path: &std::path::Path,
+    ) -> Result<(), crate::fingerprint_error::FingerprintError> {
+        let (pcm, sample_rate) = crate::decode::decode_to_mono_f32(path)?;
+        self.insert_pcm(path.to_string_lossy(), &pcm, sample_rate as usize);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// The `top_k` tracks whose melody best fits the hummed `pcm`, closest first.
+    pub fn query_pcm(&self, pcm: &[f32], sample_rate: usize, top_k: usize) -> Vec<MelodyCandidate> {
+        let query = extract_pitch_contour(pcm, sample_rate, MELODY_FRAMES_PER_SEC);
+        self.query(&query, top_k)
+    }
+
+    /// The `top_k` tracks whose melody best fits `query`, closest first. Tracks the query
+    /// can't be aligned with at all, e.g. because it is longer than them, are left out.
+    pub fn query(&self, query: &PitchContour, top_k: usize) -> Vec<MelodyCandidate> {
+        let mut candidates: Vec<MelodyCandidate> = self
+            .tracks
+            .iter()
+            .filter_map(|(id, track)| {
+                let (distance, offset, tempo_ratio) =
+                    best_alignment(&query.pitches, &track.pitches)?;
+                Some(MelodyCandidate {
+                    id: id.clone(),
+                    distance,
+                    offset_sec: offset as f32 / track.frames_per_sec,
+                    tempo_ratio,
+                })
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        candidates.truncate(top_k);
+        candidates
+    }
+}
+
+/// The closest (distance, track frame, tempo ratio) over every tempo in `TEMPO_RATIOS` and
+/// every offset of the query within the track.
+fn best_alignment(query: &[Option<f32>], track: &[Option<f32>]) -> Option<(f32, usize, f32)> {
+    let mut best: Option<(f32, usize, f32)> = None;
+    let mut diffs = Vec::with_capacity(query.len() * 2);
+    for tempo_ratio in TEMPO_RATIOS {
+        // A faster query spans more of the track than it has frames
+        let len = (query.len() as f32 * tempo_ratio).round() as usize;
+        if len == 0 || len > track.len() {
+            continue;
+        }
+        let stretched: Vec<Option<f32>> = (0..len)
+            .map(|i| query[((i as f32 / tempo_ratio) as usize).min(query.len() - 1)])
+            .collect();
+        for offset in 0..=track.len() - len {
+            diffs.clear();
+            diffs.extend(
+                stretched
+                    .iter()
+                    .zip(&track[offset..offset + len])
+                    .filter_map(|(q, t)| Some((*t)? - (*q)?)),
+            );
+            if diffs.len() < MIN_VOICED_OVERLAP {
+                continue;
+            }
+            let distance = transposed_distance(&mut diffs);
+            if best.is_none_or(|(d, _, _)| distance < d) {
+                best = Some((distance, offset, tempo_ratio));
+            }
+        }
+    }
+    best
+}
+
+/// Mean distance in semitones of track-minus-query pitch differences from their median, the
+/// transposition that best fits. Octaves are folded away, since people hum an octave off.
+fn transposed_distance(diffs: &mut [f32]) -> f32 {
+    let mid = diffs.len() / 2;
+    let (_, &mut median, _) = diffs.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+    let total: f32 = diffs
+        .iter()
+        .map(|d| (d - median + 6.0).rem_euclid(12.0) - 6.0)
+        .map(f32::abs)
+        .sum();
+    total / diffs.len() as f32
+}
@@ -0,0 +1,103 @@
+use crate::fingerprint_error::FingerprintError;
+use ebur128::EbuR128;
+use ebur128::Mode;
+
+/// Integrated loudness of mono `pcm` in LUFS, per EBU R128.
+///
+/// Returns `None` for silence or audio shorter than a single 400ms gating block.
+pub fn measure_lufs(pcm: &[f32], sample_rate: usize) -> Result<Option<f64>, FingerprintError> {
+    let mut meter = EbuR128::new(1, sample_rate as u32, Mode::I)?;
+    meter.add_frames_f32(pcm)?;
+    let lufs = meter.loudness_global()?;
+    Ok(lufs.is_finite().then_some(lufs))
+}
+
+/// Scale mono `pcm` so its integrated loudness is `target_lufs`.
+///
+/// Perceptual loudness lines up recordings with different dynamics (e.g. a brickwalled and a
+/// dynamic master) better than peak or RMS levels. Audio that can't be measured is returned as-is.
+pub fn normalize_loudness(
+    pcm: &[f32],
+    sample_rate: usize,
+    target_lufs: f64,
+) -> Result<Vec<f32>, FingerprintError> {
+    let Some(lufs) = measure_lufs(pcm, sample_rate)? else {
+        return Ok(pcm.to_vec());
+    };
+    let gain = 10f64.powf((target_lufs - lufs) / 20.0) as f32;
+    Ok(pcm.iter().map(|s| s * gain).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_fingerprint::compute_fingerprint_with_config;
+    use crate::find_matches::find_matches;
+    use crate::fingerprint_config::FingerprintConfig;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+
+    /// Noise whose level swells and falls by 20 dB every couple of seconds, mastered quietly.
+    fn dynamic_master() -> Vec<f32> {
+        noise(20.0, 0)
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let level = 0.1 * 10f32.powf((t * 1.5).sin() - 1.0);
+                s * level
+            })
+            .collect()
+    }
+
+    /// The same track pushed up and soft-clipped until it barely breathes.
+    fn loudness_war_master(dynamic: &[f32]) -> Vec<f32> {
+        dynamic.iter().map(|s| (s * 40.0).tanh()).collect()
+    }
+
+    #[test]
+    fn normalizes_to_the_target() {
+        let dynamic = dynamic_master();
+        let loud = loudness_war_master(&dynamic);
+        for pcm in [&dynamic, &loud] {
+            let normalized = normalize_loudness(pcm, SAMPLE_RATE, -14.0).unwrap();
+            let lufs = measure_lufs(&normalized, SAMPLE_RATE).unwrap().unwrap();
+            assert!((lufs + 14.0).abs() < 0.1, "{lufs}");
+        }
+        assert_eq!(measure_lufs(&[0.0; 22_050], SAMPLE_RATE).unwrap(), None);
+    }
+
+    #[test]
+    fn loudness_war_master_matches_dynamic_master() {
+        let dynamic = dynamic_master();
+        let loud = loudness_war_master(&dynamic);
+        let start = 8 * SAMPLE_RATE;
+        let find = |config: &FingerprintConfig| {
+            let track = compute_fingerprint_with_config(&dynamic, SAMPLE_RATE, config).unwrap();
+            let snippet = compute_fingerprint_with_config(
+                &loud[start..start + 5 * SAMPLE_RATE],
+                SAMPLE_RATE,
+                config,
+            )
+            .unwrap();
+            find_matches(&track, &snippet, SAMPLE_RATE, None, false)
+        };
+
+        // A magnitude gate drops most peaks of the quiet master unless levels are brought in line
+        let gated = FingerprintConfig {
+            min_peak_magnitude: Some(3.0),
+            ..FingerprintConfig::default()
+        };
+        assert!(find(&gated).is_none());
+        let found = find(&FingerprintConfig {
+            target_lufs: Some(-14.0),
+            ..gated
+        })
+        .unwrap();
+        assert!(
+            (found.offset_sec - 8.0).abs() < 0.05,
+            "{}",
+            found.offset_sec
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::resample::ResampleQuality;
+use crate::resample::resample;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Rate the signal is brought to before pitch tracking. Hummed and sung pitch stays well below
+/// its 4 kHz Nyquist frequency, and the lower rate keeps the lag search cheap
+const ANALYSIS_RATE: usize = 8_000;
+/// Samples compared at each lag, 64 ms at the analysis rate
+const YIN_WINDOW: usize = 512;
+/// Lowest pitch tracked, below most men's humming
+const MIN_FREQ: f32 = 70.0;
+/// Highest pitch tracked, covering sung notes and most whistling
+const MAX_FREQ: f32 = 2_000.0;
+/// YIN's absolute threshold: a frame is voiced if its normalized difference dips below this
+const YIN_THRESHOLD: f32 = 0.15;
+/// Frames quieter than this RMS are unvoiced, whatever their periodicity
+const MIN_RMS: f32 = 0.01;
+
+/// The pitch of the dominant monophonic line over time, as MIDI note numbers (69 = A4) with
+/// fractions for pitches between notes. `None` where there was no clear pitch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PitchContour {
+    pub frames_per_sec: f32,
+    pub pitches: Vec<Option<f32>>,
+}
+
+/// Track the pitch of `pcm` at `frames_per_sec` frames per second using YIN
+/// (de Cheveigné & Kawahara, 2002).
+///
+/// Meant for a hummed, sung, or whistled query, where a single voice carries the tune. On a
+/// full mix it follows whichever line dominates, usually the vocal or lead, which is what
+/// makes indexing tracks by their contour worthwhile, if rougher.
+pub fn extract_pitch_contour(pcm: &[f32], sample_rate: usize, frames_per_sec: f32) -> PitchContour {
+    let pcm = resample(pcm, sample_rate, ANALYSIS_RATE, ResampleQuality::Balanced);
+    let min_lag = (ANALYSIS_RATE as f32 / MAX_FREQ).floor() as usize;
+    let max_lag = (ANALYSIS_RATE as f32 / MIN_FREQ).ceil() as usize;
+    let hop = ((ANALYSIS_RATE as f32 / frames_per_sec).round() as usize).max(1);
+
+    let mut pitches = Vec::new();
+    let mut diff = vec![0.0f32; max_lag + 1];
+    let mut start = 0;
+    while start + YIN_WINDOW + max_lag <= pcm.len() {
+        let frame = &pcm[start..start + YIN_WINDOW + max_lag];
+        pitches.push(yin_pitch(frame, min_lag, max_lag, &mut diff));
+        start += hop;
+    }
+    PitchContour {
+        frames_per_sec: ANALYSIS_RATE as f32 / hop as f32,
+        pitches,
+    }
+}
+
+/// The pitch of `frame` as a MIDI note number, `None` if it is too quiet or not periodic.
+/// `frame` holds `YIN_WINDOW + max_lag` samples, `diff` at least `max_lag + 1`.
+fn yin_pitch(frame: &[f32], min_lag: usize, max_lag: usize, diff: &mut [f32]) -> Option<f32> {
+    let window = &frame[..YIN_WINDOW];
+    let rms = (window.iter().map(|s| s * s).sum::<f32>() / YIN_WINDOW as f32).sqrt();
+    if rms < MIN_RMS {
+        return None;
+    }
+
+    // Difference function, then normalized by its running mean so the lag-0 dip disappears
+    diff[0] = 1.0;
+    let mut running_sum = 0.0;
+    for lag in 1..=max_lag {
+        let d: f32 = window
+            .iter()
+            .zip(&frame[lag..])
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum();
+        running_sum += d;
+        diff[lag] = if running_sum > 0.0 {
+            d * lag as f32 / running_sum
+        } else {
+            1.0
+        };
+    }
+
+    // The first dip below the threshold, followed down to its bottom
+    let mut lag = (min_lag.max(2)..max_lag).find(|&lag| diff[lag] < YIN_THRESHOLD)?;
+    while lag + 1 < max_lag && diff[lag + 1] < diff[lag] {
+        lag += 1;
+    }
+
+    // Refine between lags by fitting a parabola through the dip
+    let (a, b, c) = (diff[lag - 1], diff[lag], diff[lag + 1]);
+    let denom = a - 2.0 * b + c;
+    let shift = if denom.abs() > f32::EPSILON {
+        (0.5 * (a - c) / denom).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    let freq = ANALYSIS_RATE as f32 / (lag as f32 + shift);
+    Some(69.0 + 12.0 * (freq / 440.0).log2())
+}
@@ -0,0 +1,126 @@
+use crate::cache::load_or_build_fingerprint_with_config;
+use crate::decode::can_decode;
+use crate::find_matches::MatchResult;
+use crate::find_matches::find_matches;
+use crate::fingerprint_config::FingerprintConfig;
+use crate::fingerprint_data::FingerprintData;
+use crate::fingerprint_error::FingerprintError;
+use futures_core::Stream;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use tokio::sync::mpsc;
+
+/// A scanned file and what matching the snippet against it found.
+pub type ScanItem = (PathBuf, Result<Option<MatchResult>, FingerprintError>);
+
+/// Results are scanned at most this far ahead of the consumer.
+const SCAN_BUFFER: usize = 16;
+
+/// The results of [`find_matches_in_dir_stream`], in directory order as each file finishes.
+///
+/// Dropping it stops the scan after the file in progress.
+#[derive(Debug)]
+pub struct MatchStream {
+    rx: mpsc::Receiver<ScanItem>,
+}
+
+impl MatchStream {
+    /// The next result, or `None` once every file has been scanned. For callers without
+    /// stream combinators at hand.
+    pub async fn next(&mut self) -> Option<ScanItem> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for MatchStream {
+    type Item = ScanItem;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ScanItem>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// Match `snippet_fp` against every decodable file in `music_dir`, loading or building track
+/// fingerprints through `cache_dir` with `config`, and yield each result as it completes.
+///
+/// Decoding and matching are blocking work, so they run on tokio's blocking pool and this must
+/// be called from within a runtime. If `music_dir` can't be listed, the stream yields a single
+/// error for it. The consumer can stop early by dropping the stream.
+pub fn find_matches_in_dir_stream(
+    music_dir: &Path,
+    cache_dir: &Path,
+    snippet_fp: FingerprintData,
+    sample_rate: usize,
+    config: FingerprintConfig,
+    search_window: Option<(f32, f32)>,
+) -> MatchStream {
+    let (tx, rx) = mpsc::channel(SCAN_BUFFER);
+    let music_dir = music_dir.to_path_buf();
+    let cache_dir = cache_dir.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let audio_files = match audio_files_in(&music_dir) {
+            Ok(audio_files) => audio_files,
+            Err(e) => {
+                let _ = tx.blocking_send((music_dir, Err(e)));
+                return;
+            }
+        };
+        for track_path in audio_files {
+            let result = load_or_build_fingerprint_with_config(
+                &track_path,
+                &cache_dir,
+                sample_rate,
+                &config,
+            )
+            .map(|track_fp| {
+                find_matches(&track_fp, &snippet_fp, sample_rate, search_window, false)
+            });
+            if tx.blocking_send((track_path, result)).is_err() {
+                // The stream was dropped, nobody wants the rest
+                return;
+            }
+        }
+    });
+    MatchStream { rx }
+}
+
+/// Like [`find_matches_in_dir_stream`], collecting every result once the scan is done.
+pub async fn find_matches_in_dir(
+    music_dir: &Path,
+    cache_dir: &Path,
+    snippet_fp: FingerprintData,
+    sample_rate: usize,
+    config: FingerprintConfig,
+    search_window: Option<(f32, f32)>,
+) -> Vec<ScanItem> {
+    let mut stream = find_matches_in_dir_stream(
+        music_dir,
+        cache_dir,
+        snippet_fp,
+        sample_rate,
+        config,
+        search_window,
+    );
+    let mut results = Vec::new();
+    while let Some(item) = stream.next().await {
+        results.push(item);
+    }
+    results
+}
+
+/// The files in `dir` that look decodable, sorted by path so scans run in a stable order.
+fn audio_files_in(dir: &Path) -> Result<Vec<PathBuf>, FingerprintError> {
+    let mut audio_files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if can_decode(&path, false) {
+            audio_files.push(path);
+        }
+    }
+    audio_files.sort();
+    Ok(audio_files)
+}
@@ -0,0 +1,81 @@
+/// A match offset alongside the same offset moved onto the nearest onset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnappedOffset {
+    /// The offset as detected, in seconds
+    pub raw_offset_sec: f32,
+    /// The nearest onset within tolerance, or the raw offset when there is none, in seconds
+    pub snapped_offset_sec: f32,
+}
+
+/// Spectral flux of each frame: how much energy rose in every bin since the previous frame.
+///
+/// Spikes in flux mark note and drum onsets. The first frame has nothing to compare against
+/// and gets zero.
+pub fn spectral_flux(spectrogram: &[Vec<f32>]) -> Vec<f32> {
+    // spectrogram[freq_bin][time]
+    let n_hops = spectrogram.first().map_or(0, Vec::len);
+    (0..n_hops)
+        .map(|t| {
+            if t == 0 {
+                return 0.0;
+            }
+            spectrogram
+                .iter()
+                .map(|row| (row[t] - row[t - 1]).max(0.0))
+                .filter(|rise| !rise.is_nan())
+                .sum()
+        })
+        .collect()
+}
+
+/// Move `offset_sec` onto the nearest onset of the track within `tolerance_sec`, so a sample
+/// lands on a beat instead of mid-beat.
+///
+/// Onsets are spectral flux peaks that beat their neighbours and the mean flux around the
+/// offset. `spectrogram` is the track's, built with `hop_size` like the fingerprint.
+pub fn snap_to_onset(
+    spectrogram: &[Vec<f32>],
+    sample_rate: usize,
+    hop_size: usize,
+    offset_sec: f32,
+    tolerance_sec: f32,
+) -> SnappedOffset {
+    let frames_per_sec = sample_rate as f32 / hop_size as f32;
+    let flux = spectral_flux(spectrogram);
+    let unsnapped = SnappedOffset {
+        raw_offset_sec: offset_sec,
+        snapped_offset_sec: offset_sec,
+    };
+    if flux.is_empty() {
+        return unsnapped;
+    }
+
+    let center = offset_sec * frames_per_sec;
+    let tolerance = tolerance_sec.max(0.0) * frames_per_sec;
+    let last = flux.len() - 1;
+    let begin = ((center - tolerance).floor().max(0.0) as usize).min(last);
+    let end = ((center + tolerance).ceil().max(0.0) as usize).min(last);
+    let window = &flux[begin..=end];
+    let mean = window.iter().sum::<f32>() / window.len() as f32;
+
+    let nearest_onset = (begin..=end)
+        .filter(|&t| {
+            let before = if t > 0 { flux[t - 1] } else { 0.0 };
+            let after = flux.get(t + 1).copied().unwrap_or(0.0);
+            flux[t] > mean && flux[t] >= before && flux[t] > after
+        })
+        .filter(|&t| (t as f32 - center).abs() <= tolerance)
+        .min_by(|&a, &b| {
+            (a as f32 - center)
+                .abs()
+                .total_cmp(&(b as f32 - center).abs())
+        });
+
+    match nearest_onset {
+        Some(t) => SnappedOffset {
+            raw_offset_sec: offset_sec,
+            snapped_offset_sec: t as f32 / frames_per_sec,
+        },
+        None => unsnapped,
+    }
+}
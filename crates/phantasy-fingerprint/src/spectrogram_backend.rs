@@ -0,0 +1,130 @@
+use crate::compute_spectrogram::compute_spectrogram;
+use crate::fingerprint_error::FingerprintError;
+
+/// Magnitudes of shape (n_freq, n_frames), as returned by `compute_spectrogram`.
+pub type SpectrogramMatrix = Vec<Vec<f32>>;
+
+/// How PCM is cut into frames before the FFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpectrogramConfig {
+    pub sample_rate: usize,
+    /// Samples per window, which must be Hann-weighted to match the default backend
+    pub window_size: usize,
+    /// Samples between the starts of consecutive windows
+    pub hop_size: usize,
+}
+
+/// Turns PCM into a magnitude spectrogram, so the FFT can be swapped for an accelerated one
+/// (GPU, SIMD) without changing the fingerprinting algorithm.
+///
+/// Implementations should produce `window_size / 2` frequency rows and
+/// `(pcm.len() - window_size) / hop_size + 1` frames, and error with `TooShort` when `pcm`
+/// does not fill a single window, so peaks land on the same bins as with [`RustFftSpectrogram`].
+pub trait Spectrogram {
+    fn compute(
+        &self,
+        pcm: &[f32],
+        config: &SpectrogramConfig,
+    ) -> Result<SpectrogramMatrix, FingerprintError>;
+}
+
+/// The default backend, using `rustfft` on the CPU.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RustFftSpectrogram;
+
+impl Spectrogram for RustFftSpectrogram {
+    fn compute(
+        &self,
+        pcm: &[f32],
+        config: &SpectrogramConfig,
+    ) -> Result<SpectrogramMatrix, FingerprintError> {
+        compute_spectrogram(pcm, config.sample_rate, config.window_size, config.hop_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_fingerprint::HOP_SIZE;
+    use crate::compute_fingerprint::WINDOW_SIZE;
+    use crate::compute_fingerprint::compute_fingerprint_with_backend;
+    use crate::compute_spectrogram::hann_window;
+    use crate::fingerprint_config::FingerprintConfig;
+    use crate::fingerprint_data::FingerprintData;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+    use std::f64::consts::TAU;
+
+    /// A direct DFT, far slower than an FFT but sharing no code with `rustfft`.
+    struct NaiveDft;
+
+    impl Spectrogram for NaiveDft {
+        fn compute(
+            &self,
+            pcm: &[f32],
+            config: &SpectrogramConfig,
+        ) -> Result<SpectrogramMatrix, FingerprintError> {
+            let n = config.window_size;
+            if pcm.len() < n {
+                return Err(FingerprintError::TooShort {
+                    samples: pcm.len(),
+                    required: n,
+                });
+            }
+            let window = hann_window(n);
+            let n_frames = (pcm.len() - n) / config.hop_size + 1;
+            let mut matrix = vec![vec![0.0; n_frames]; n / 2];
+            for (t, frame) in pcm
+                .windows(n)
+                .step_by(config.hop_size)
+                .take(n_frames)
+                .enumerate()
+            {
+                for (k, row) in matrix.iter_mut().enumerate() {
+                    let (mut re, mut im) = (0.0f64, 0.0f64);
+                    for (i, (&sample, &weight)) in frame.iter().zip(&window).enumerate() {
+                        let x = (sample * weight) as f64;
+                        let phase = TAU * (k * i % n) as f64 / n as f64;
+                        re += x * phase.cos();
+                        im -= x * phase.sin();
+                    }
+                    row[t] = re.hypot(im) as f32;
+                }
+            }
+            Ok(matrix)
+        }
+    }
+
+    #[test]
+    fn alternative_backend_gives_matching_results() {
+        let pcm = noise(1.0, 0);
+        let config = SpectrogramConfig {
+            sample_rate: SAMPLE_RATE,
+            window_size: WINDOW_SIZE,
+            hop_size: HOP_SIZE,
+        };
+        let fft = RustFftSpectrogram.compute(&pcm, &config).unwrap();
+        let dft = NaiveDft.compute(&pcm, &config).unwrap();
+        assert_eq!(dft.len(), fft.len());
+        for (dft_row, fft_row) in dft.iter().zip(&fft) {
+            assert_eq!(dft_row.len(), fft_row.len());
+            for (a, b) in dft_row.iter().zip(fft_row) {
+                assert!((a - b).abs() <= 1e-3 * b.max(1.0), "{a} vs {b}");
+            }
+        }
+
+        // Magnitudes agree to rounding, so the same peaks are picked and paired
+        fn fingerprint(pcm: &[f32], backend: &impl Spectrogram) -> FingerprintData {
+            compute_fingerprint_with_backend(
+                pcm,
+                SAMPLE_RATE,
+                &FingerprintConfig::default(),
+                backend,
+            )
+            .unwrap()
+        }
+        let fft_fp = fingerprint(&pcm, &RustFftSpectrogram);
+        let dft_fp = fingerprint(&pcm, &NaiveDft);
+        assert_eq!(dft_fp.pairs, fft_fp.pairs);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::compute_fingerprint::HOP_SIZE;
+use crate::compute_fingerprint::TARGET_ZONE_FRAMES;
+use crate::compute_fingerprint::WINDOW_SIZE;
+use crate::compute_fingerprint::bin_delta_t;
+use crate::compute_fingerprint::pair_peaks;
+use crate::find_peaks::frame_energy;
+use crate::find_peaks::peak_count;
+use crate::find_peaks::top_peaks;
+use crate::fingerprint_config::ConfigError;
+use crate::fingerprint_config::FingerprintConfig;
+use crate::fingerprint_data::FPHashEntry;
+use crate::streaming_spectrogram::StreamingSpectrogram;
+use std::collections::VecDeque;
+
+/// Fingerprint a signal as it arrives, holding only one window of samples and one target zone
+/// of peaks at a time.
+///
+/// Pairs are emitted once their anchor frame ages out of the target zone. Peak counts scale
+/// against the loudest frame seen so far rather than the loudest of the whole signal, so early
+/// frames may get more peaks than `compute_fingerprint` would give them.
+pub struct StreamingFingerprinter {
+    spectrogram: StreamingSpectrogram,
+    config: FingerprintConfig,
+    loudest: f32,
+    /// Peaks of the frames not yet used as anchors, oldest first
+    pending: VecDeque<Vec<u16>>,
+    /// Frame index of the front of `pending`
+    next_anchor: u32,
+}
+
+impl StreamingFingerprinter {
+    pub fn new(config: FingerprintConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Self {
+            spectrogram: StreamingSpectrogram::new(WINDOW_SIZE, HOP_SIZE),
+            config,
+            loudest: 0.0,
+            pending: VecDeque::with_capacity(TARGET_ZONE_FRAMES + 1),
+            next_anchor: 0,
+        })
+    }
+
+    /// Push `samples`, returning the pairs whose target zone is now complete.
+    pub fn push(&mut self, samples: &[f32]) -> Vec<FPHashEntry> {
+        let mut pairs = Vec::new();
+        for column in self.spectrogram.push(samples) {
+            let energy = frame_energy(&column);
+            self.loudest = self.loudest.max(energy);
+            let top_n = peak_count(
+                energy,
+                self.loudest,
+                self.config.min_peaks_per_frame,
+                self.config.max_peaks_per_frame,
+            );
+            self.pending
+                .push_back(top_peaks(&column, top_n, &self.config));
+
+            if self.pending.len() > TARGET_ZONE_FRAMES {
+                self.emit_front(&mut pairs);
+            }
+        }
+        pairs
+    }
+
+    /// Emit the pairs of the frames still waiting on a full target zone, ending the stream.
+    pub fn finish(mut self) -> Vec<FPHashEntry> {
+        let mut pairs = Vec::new();
+        while !self.pending.is_empty() {
+            self.emit_front(&mut pairs);
+        }
+        pairs
+    }
+
+    /// Pair the oldest pending frame with the frames after it and drop it.
+    fn emit_front(&mut self, pairs: &mut Vec<FPHashEntry>) {
+        let Some(anchor) = self.pending.pop_front() else {
+            return;
+        };
+        let future: Vec<Vec<u16>> = self.pending.iter().cloned().collect();
+        let start = pairs.len();
+        pair_peaks(self.next_anchor, &anchor, &future, pairs);
+        bin_delta_t(&mut pairs[start..], self.config.delta_t_bin);
+        self.next_anchor += 1;
+    }
+}
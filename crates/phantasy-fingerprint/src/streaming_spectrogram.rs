@@ -0,0 +1,107 @@
+use crate::compute_spectrogram::hann_window;
+use rustfft::Fft;
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+use rustfft::num_traits::Zero;
+use std::sync::Arc;
+
+/// A spectrogram computed incrementally over a ring buffer of exactly one window of samples.
+///
+/// Emits the same columns as `compute_spectrogram` without ever holding the whole signal, which
+/// keeps memory constant for mic or streaming input.
+pub struct StreamingSpectrogram {
+    window_size: usize,
+    hop_size: usize,
+    fft: Arc<dyn Fft<f32>>,
+    window_func: Vec<f32>,
+    /// The latest `window_size` samples, oldest at `write_pos`
+    ring: Vec<f32>,
+    write_pos: usize,
+    /// Samples pushed so far
+    pushed: usize,
+    buffer: Vec<Complex<f32>>,
+}
+
+impl StreamingSpectrogram {
+    pub fn new(window_size: usize, hop_size: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        Self {
+            window_size,
+            hop_size,
+            fft: planner.plan_fft_forward(window_size),
+            window_func: hann_window(window_size),
+            ring: vec![0.0; window_size],
+            write_pos: 0,
+            pushed: 0,
+            buffer: vec![Complex::zero(); window_size],
+        }
+    }
+
+    /// Push `samples`, returning a magnitude column (one value per freq bin) for every
+    /// `hop_size` samples once the first window has filled.
+    pub fn push(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        let mut columns = Vec::new();
+        for &sample in samples {
+            self.ring[self.write_pos] = sample;
+            self.write_pos = (self.write_pos + 1) % self.window_size;
+            self.pushed += 1;
+
+            if self.pushed >= self.window_size
+                && (self.pushed - self.window_size).is_multiple_of(self.hop_size)
+            {
+                columns.push(self.column());
+            }
+        }
+        columns
+    }
+
+    /// Magnitudes of the window currently held in the ring.
+    fn column(&mut self) -> Vec<f32> {
+        // the oldest sample sits at write_pos, so read from there around to write_pos - 1
+        let (newer, older) = self.ring.split_at(self.write_pos);
+        for ((slot, &sample), &weight) in self
+            .buffer
+            .iter_mut()
+            .zip(older.iter().chain(newer))
+            .zip(&self.window_func)
+        {
+            slot.re = sample * weight;
+            slot.im = 0.0;
+        }
+        self.fft.process(&mut self.buffer);
+
+        self.buffer[..self.window_size / 2]
+            .iter()
+            .map(|bin| (bin.re * bin.re + bin.im * bin.im).sqrt())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_fingerprint::HOP_SIZE;
+    use crate::compute_fingerprint::WINDOW_SIZE;
+    use crate::compute_spectrogram::compute_spectrogram;
+    use crate::test_signal::SAMPLE_RATE;
+    use crate::test_signal::noise;
+
+    #[test]
+    fn columns_match_the_batch_spectrogram() {
+        let pcm = noise(3.0, 0);
+        let batch = compute_spectrogram(&pcm, SAMPLE_RATE, WINDOW_SIZE, HOP_SIZE).unwrap();
+
+        let mut streaming = StreamingSpectrogram::new(WINDOW_SIZE, HOP_SIZE);
+        // Chunks unaligned with the hop, so columns complete mid-push
+        let columns: Vec<Vec<f32>> = pcm
+            .chunks(777)
+            .flat_map(|chunk| streaming.push(chunk))
+            .collect();
+
+        assert_eq!(columns.len(), batch[0].len());
+        for (t, column) in columns.iter().enumerate() {
+            let batch_column: Vec<f32> = batch.iter().map(|row| row[t]).collect();
+            assert_eq!(column, &batch_column, "frame {t}");
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Deterministic signals for the unit tests.
+
+/// Rate the test signals are generated at
+pub(crate) const SAMPLE_RATE: usize = 22_050;
+
+/// `secs` seconds of white noise, the same for the same `seed` on every run.
+pub(crate) fn noise(secs: f32, seed: u64) -> Vec<f32> {
+    let mut state = seed;
+    (0..(secs * SAMPLE_RATE as f32) as usize)
+        .map(|_| {
+            // splitmix64, whose streams for different seeds are uncorrelated
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut x = state;
+            x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            x ^= x >> 31;
+            (x >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+        })
+        .collect()
+}
+
+/// Write interleaved `pcm` of `channels` channels at `sample_rate` to `path` as 16-bit WAV.
+#[cfg(feature = "io")]
+pub(crate) fn write_wav(path: &std::path::Path, pcm: &[f32], sample_rate: u32, channels: u16) {
+    let data_len = (pcm.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&channels.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+    bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in pcm {
+        bytes
+            .extend_from_slice(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    std::fs::write(path, bytes).unwrap();
+}
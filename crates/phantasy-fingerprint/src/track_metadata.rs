@@ -0,0 +1,148 @@
+use crate::find_matches::MatchResult;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The sidecar in a cache directory that `MetadataTable::load` and `save` use. Its extension
+/// keeps it out of scans for cached fingerprints
+pub const METADATA_FILE: &str = "metadata.jsonl";
+
+/// Who and what a fingerprinted file is, so a match can name the song rather than the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackMetadata {
+    pub path: PathBuf,
+    /// The Spotify track id, for files fingerprinted from a Spotify preview
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_track_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+impl TrackMetadata {
+    /// Metadata for `path` with nothing known about it yet.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            spotify_track_id: None,
+            artist: None,
+            title: None,
+        }
+    }
+
+    /// Metadata for `path`, a file fingerprinted from the preview of `track`. Multiple artists
+    /// are joined by commas, as Spotify shows them.
+    #[cfg(feature = "spotify")]
+    pub fn from_spotify_track(
+        path: impl Into<PathBuf>,
+        track: &phantasy_spotify_api::track::Track,
+    ) -> Self {
+        let artists: Vec<&str> = track.artists.iter().map(|a| a.name.as_str()).collect();
+        let non_empty = |s: String| (!s.is_empty()).then_some(s);
+        Self {
+            path: path.into(),
+            spotify_track_id: non_empty(track.id.clone()),
+            artist: non_empty(artists.join(", ")),
+            title: non_empty(track.name.clone()),
+        }
+    }
+
+    /// "Artist - Title" when both are known, else whichever is, else the file name.
+    pub fn label(&self) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (Some(name), None) | (None, Some(name)) => name.clone(),
+            (None, None) => self
+                .path
+                .file_name()
+                .unwrap_or(self.path.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+}
+
+/// A match enriched with what is known about the track it was found in.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackMatch {
+    #[serde(flatten)]
+    pub track: TrackMetadata,
+    #[serde(flatten)]
+    pub result: MatchResult,
+}
+
+/// Metadata for the files of a library, kept beside their cached fingerprints.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataTable {
+    tracks: BTreeMap<PathBuf, TrackMetadata>,
+}
+
+impl MetadataTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `metadata`, replacing anything known about the same path.
+    pub fn insert(&mut self, metadata: TrackMetadata) {
+        self.tracks.insert(metadata.path.clone(), metadata);
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&TrackMetadata> {
+        self.tracks.get(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// What is known about `path`, or just its path when nothing is.
+    pub fn metadata_for(&self, path: &Path) -> TrackMetadata {
+        self.get(path)
+            .cloned()
+            .unwrap_or_else(|| TrackMetadata::new(path))
+    }
+
+    /// Attach what is known about `path` to a match found in it.
+    pub fn enrich(&self, path: &Path, result: MatchResult) -> TrackMatch {
+        TrackMatch {
+            track: self.metadata_for(path),
+            result,
+        }
+    }
+
+    /// Read the table kept in `cache_dir`, empty if there is none yet. When a path appears on
+    /// more than one line, the last wins.
+    #[cfg(feature = "io")]
+    pub fn load(cache_dir: &Path) -> Result<Self, crate::fingerprint_error::FingerprintError> {
+        let body = match std::fs::read_to_string(cache_dir.join(METADATA_FILE)) {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut table = Self::default();
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            table.insert(serde_json::from_str(line)?);
+        }
+        Ok(table)
+    }
+
+    /// Write the table to `cache_dir`, one track per line, replacing what was there.
+    #[cfg(feature = "io")]
+    pub fn save(&self, cache_dir: &Path) -> Result<(), crate::fingerprint_error::FingerprintError> {
+        let mut body = Vec::new();
+        for metadata in self.tracks.values() {
+            serde_json::to_writer(&mut body, metadata)?;
+            body.push(b'\n');
+        }
+        std::fs::create_dir_all(cache_dir)?;
+        std::fs::write(cache_dir.join(METADATA_FILE), body)?;
+        Ok(())
+    }
+}
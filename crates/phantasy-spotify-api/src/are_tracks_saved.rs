@@ -0,0 +1,37 @@
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::track_id::TrackId;
+use eyre::bail;
+
+/// The most IDs the check-saved-tracks endpoint accepts per request
+const SAVED_TRACKS_BATCH_SIZE: usize = 50;
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/check-users-saved-tracks
+    ///
+    /// Needs the `user-library-read` scope. Batches `track_ids` into requests of 50. The result
+    /// lines up with `track_ids`, holding whether each is in the user's library.
+    pub async fn are_tracks_saved(&self, track_ids: &[TrackId]) -> eyre::Result<Vec<bool>> {
+        let mut saved = Vec::with_capacity(track_ids.len());
+        for batch in track_ids.chunks(SAVED_TRACKS_BATCH_SIZE) {
+            let ids = batch.iter().map(|id| &**id).collect::<Vec<_>>().join(",");
+            let url = format!("https://api.spotify.com/v1/me/tracks/contains?ids={}", ids);
+            let page: Vec<bool> = self.fetch(&url).await?;
+            // A short answer would shift every later flag onto the wrong track
+            if page.len() != batch.len() {
+                bail!(
+                    "Asked whether {} tracks are saved but got {} answers",
+                    batch.len(),
+                    page.len()
+                );
+            }
+            saved.extend(page);
+        }
+        Ok(saved)
+    }
+}
+
+/// https://developer.spotify.com/documentation/web-api/reference/check-users-saved-tracks
+pub async fn are_tracks_saved(ids: &[TrackId], bearer: BearerToken) -> eyre::Result<Vec<bool>> {
+    SpotifyClient::new(bearer).are_tracks_saved(ids).await
+}
@@ -0,0 +1,20 @@
+use std::ops::Deref;
+
+#[derive(Debug, Clone)]
+pub struct ArtistId(pub String);
+impl std::fmt::Display for ArtistId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl Deref for ArtistId {
+    type Target = str;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl AsRef<str> for ArtistId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
@@ -0,0 +1,55 @@
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::track_id::TrackId;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The track-level summary of an audio analysis. The bars, beats, sections, and segments that
+/// make up the rest of the analysis aren't modelled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioAnalysis {
+    pub track: AudioAnalysisTrack,
+}
+
+/// Whole-track estimates from the audio analysis, each with how confident Spotify is in it.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioAnalysisTrack {
+    /// Length in seconds
+    pub duration: f64,
+    /// Average loudness in dB
+    pub loudness: f64,
+    /// Estimated tempo in BPM
+    pub tempo: f64,
+    pub tempo_confidence: f64,
+    pub time_signature: i64,
+    pub time_signature_confidence: f64,
+    /// Pitch class in Spotify's key order, -1 when none was detected
+    pub key: i64,
+    pub key_confidence: f64,
+    /// 1 for major, 0 for minor
+    pub mode: i64,
+    pub mode_confidence: f64,
+}
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/get-audio-analysis
+    pub async fn get_track_audio_analysis(
+        &self,
+        track_id: impl AsRef<str>,
+    ) -> eyre::Result<AudioAnalysis> {
+        let track_id = TrackId::parse(track_id)?;
+        let url = format!("https://api.spotify.com/v1/audio-analysis/{}", track_id);
+        Ok(self.fetch(&url).await?)
+    }
+}
+
+/// https://developer.spotify.com/documentation/web-api/reference/get-audio-analysis
+pub async fn get_track_audio_analysis(
+    track_id: impl AsRef<str>,
+    bearer: BearerToken,
+) -> eyre::Result<AudioAnalysis> {
+    SpotifyClient::new(bearer)
+        .get_track_audio_analysis(track_id)
+        .await
+}
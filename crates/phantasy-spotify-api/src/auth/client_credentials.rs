@@ -0,0 +1,113 @@
+use crate::auth::pkce::TokenResponse;
+use crate::auth::pkce::request_token;
+use crate::bearer_token::BearerToken;
+use crate::bearer_token::REFRESH_WINDOW;
+use eyre::Result;
+use eyre::eyre;
+use tracing::debug;
+use tracing::info;
+
+/// Where `get_bearer_token_via_client_credentials` caches the token of the app `client_id`,
+/// apart from the PKCE flow's `bearer_token.json` since it can't act for a user. Each app gets
+/// its own file, so switching credentials never picks up another app's token.
+fn client_credentials_token_file(client_id: &str) -> String {
+    // Spotify client IDs are hex, but keep anything else out of the path all the same
+    let client_id: String = client_id
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect();
+    format!("client_credentials_token_{client_id}.json")
+}
+
+/// An app's ID and secret, for the client credentials flow.
+///
+/// https://developer.spotify.com/documentation/web-api/tutorials/client-credentials-flow
+#[derive(Clone)]
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl ClientCredentials {
+    pub fn new(client_id: impl Into<String>, client_secret: impl Into<String>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+        }
+    }
+
+    /// Read `SPOTIFY_CLIENT_ID` and `SPOTIFY_CLIENT_SECRET`.
+    pub fn from_env() -> Result<ClientCredentials> {
+        let var = |name: &str| std::env::var(name).map_err(|_| eyre!("Missing env var: {}", name));
+        Ok(ClientCredentials::new(
+            var("SPOTIFY_CLIENT_ID")?,
+            var("SPOTIFY_CLIENT_SECRET")?,
+        ))
+    }
+
+    /// Ask Spotify for a new app token. Neither reads nor saves the token file.
+    pub async fn request_token(&self) -> Result<TokenResponse> {
+        request_token(
+            &[("grant_type", "client_credentials")],
+            Some((&self.client_id, &self.client_secret)),
+        )
+        .await
+    }
+}
+
+// Keep the secret out of logs
+impl std::fmt::Debug for ClientCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientCredentials")
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A token for the app itself, for servers and CI where nobody can sign in through a browser.
+///
+/// It has no user scopes, so it only works for public data such as `get_track` and
+/// `get_album`, not endpoints like `are_tracks_saved`. It comes without a refresh token; once
+/// the cached one is within [`REFRESH_WINDOW`] of expiry a new one is requested and cached in
+/// its place. Pass the credentials to `SpotifyClient::with_client_credentials` to keep renewing
+/// it for a long-running client.
+pub async fn get_bearer_token_via_client_credentials(
+    client_id: &str,
+    client_secret: &str,
+) -> Result<BearerToken> {
+    let token_file = client_credentials_token_file(client_id);
+    if let Ok(token) = tokio::fs::read(&token_file).await {
+        let token: BearerToken = serde_json::from_slice(&token)?;
+        if token.expires_at.is_some() && !token.expires_within(REFRESH_WINDOW) {
+            debug!("Using cached client credentials token");
+            return Ok(token);
+        }
+        info!("Cached client credentials token has expired, requesting a new one");
+    }
+
+    let credentials = ClientCredentials::new(client_id, client_secret);
+    let token = BearerToken::from_token_response(&credentials.request_token().await?);
+    tokio::fs::write(&token_file, serde_json::to_string_pretty(&token)?).await?;
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_file_is_per_client() {
+        assert_eq!(
+            client_credentials_token_file("abc123"),
+            "client_credentials_token_abc123.json"
+        );
+        assert_ne!(
+            client_credentials_token_file("abc123"),
+            client_credentials_token_file("def456")
+        );
+        assert_eq!(
+            client_credentials_token_file("../x/y"),
+            "client_credentials_token_xy.json"
+        );
+    }
+}
@@ -1,4 +1,8 @@
+use crate::auth::scope::DEFAULT_SCOPES;
+use crate::auth::scope::Scope;
 use crate::bearer_token::BearerToken;
+use crate::bearer_token::REFRESH_WINDOW;
+use crate::client::shared_http_client;
 use base64::Engine;
 use eyre::OptionExt;
 use eyre::Result;
@@ -10,11 +14,17 @@ use serde::Deserialize;
 use serde::Serialize;
 use sha2::Digest;
 use sha2::Sha256;
+use std::time::Duration;
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
-use tokio::net::TcpListener;
+use tokio::net::TcpSocket;
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tracing::Span;
 use tracing::debug;
+use tracing::field;
 use tracing::info;
+use tracing::warn;
 use url::Url;
 
 /// Read the required environment variable or error
@@ -23,13 +33,57 @@ fn var(name: &str) -> Result<String> {
 }
 
 const BEARER_TOKEN_FILE: &'static str = "bearer_token.json";
+/// Tries of the token exchange before giving up on transient failures
+const TOKEN_EXCHANGE_ATTEMPTS: u32 = 3;
+/// Wait before the first retry of the token exchange, doubling after each failure
+const TOKEN_EXCHANGE_BACKOFF: Duration = Duration::from_millis(500);
+/// How long `get_bearer_token_via_pkce` waits for the user to finish in the browser
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Connections the redirect listener queues before refusing more, by default
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 16;
+
+/// Nobody completed the authorization in the browser in time, so the redirect never arrived.
+#[derive(Debug)]
+pub struct AuthTimedOut {
+    pub waited: Duration,
+}
+
+impl std::fmt::Display for AuthTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "No authorization callback arrived within {:?}, sign in again",
+            self.waited
+        )
+    }
+}
+
+impl std::error::Error for AuthTimedOut {}
+
+/// The token saved by the last sign-in, if any.
+///
+/// A token within [`REFRESH_WINDOW`] of expiry is first renewed with its refresh token and
+/// `SPOTIFY_CLIENT_ID`, and saved again. When that fails the token is no use, so this returns
+/// `None` and the caller signs in again.
 pub async fn get_saved_token() -> Result<Option<BearerToken>> {
-    if let Ok(token) = tokio::fs::read(BEARER_TOKEN_FILE).await {
-        let token = serde_json::from_slice(&token)?;
-        Ok(Some(token))
-    } else {
-        Ok(None)
+    let Ok(token) = tokio::fs::read(BEARER_TOKEN_FILE).await else {
+        return Ok(None);
+    };
+    let mut token: BearerToken = serde_json::from_slice(&token)?;
+    if token.refresh_token.is_some() && token.expires_within(REFRESH_WINDOW) {
+        let refreshed = match var("SPOTIFY_CLIENT_ID") {
+            Ok(client_id) => token.refresh_if_expired(&client_id).await,
+            Err(e) => Err(e),
+        };
+        match refreshed {
+            Ok(_) => save_token(&token).await?,
+            Err(e) => {
+                warn!("Couldn't refresh the saved token: {}", e);
+                return Ok(None);
+            }
+        }
     }
+    Ok(Some(token))
 }
 
 pub async fn save_token(token: &BearerToken) -> Result<()> {
@@ -37,61 +91,285 @@ pub async fn save_token(token: &BearerToken) -> Result<()> {
     Ok(())
 }
 
+/// The app registration used for the PKCE flow.
+#[derive(Debug, Clone)]
+pub struct PkceConfig {
+    pub client_id: String,
+    pub redirect_uri: String,
+    /// Pending connections the local redirect listener accepts, see [`DEFAULT_LISTEN_BACKLOG`]
+    pub listen_backlog: u32,
+    /// What the user is asked to grant, see [`DEFAULT_SCOPES`]
+    pub scopes: Vec<Scope>,
+}
+
+impl PkceConfig {
+    /// Read `SPOTIFY_CLIENT_ID` and `SPOTIFY_REDIRECT_URI`, asking for [`DEFAULT_SCOPES`].
+    pub fn from_env() -> Result<PkceConfig> {
+        Ok(PkceConfig {
+            client_id: var("SPOTIFY_CLIENT_ID")?,
+            redirect_uri: var("SPOTIFY_REDIRECT_URI")?,
+            listen_backlog: DEFAULT_LISTEN_BACKLOG,
+            scopes: DEFAULT_SCOPES.to_vec(),
+        })
+    }
+
+    /// Ask for `scopes` instead of [`DEFAULT_SCOPES`].
+    pub fn with_scopes(mut self, scopes: &[Scope]) -> Self {
+        self.scopes = scopes.to_vec();
+        self
+    }
+}
+
+/// Run the whole PKCE flow: open the browser, catch the redirect on a local listener, and
+/// exchange the code. Returns the saved token instead when there is one, refreshed if it is
+/// about to expire, and saves new tokens along with their refresh token and expiry.
+///
+/// When no browser can be opened, as over SSH or in a container, the link is printed to open
+/// by hand instead. Fails with [`AuthTimedOut`] if the redirect doesn't arrive within
+/// [`AUTH_TIMEOUT`]. Asks for [`DEFAULT_SCOPES`]; see `get_bearer_token_via_pkce_with_scopes`
+/// for others.
 pub async fn get_bearer_token_via_pkce() -> Result<BearerToken> {
+    get_bearer_token_via_pkce_with_scopes(DEFAULT_SCOPES).await
+}
+
+/// Like `get_bearer_token_via_pkce`, asking the user to grant `scopes`.
+///
+/// A saved token is only returned when it was granted all of `scopes`. Otherwise the user signs
+/// in again, and the new token replaces it.
+pub async fn get_bearer_token_via_pkce_with_scopes(scopes: &[Scope]) -> Result<BearerToken> {
     debug!("Getting bearer token");
     if let Some(x) = get_saved_token().await? {
-        return Ok(x);
+        if x.grants(scopes) {
+            return Ok(x);
+        }
+        info!(
+            "Saved token wasn't granted all of {:?}, signing in again",
+            Scope::join(scopes)
+        );
     }
 
-    let client_id = var("SPOTIFY_CLIENT_ID")?;
-    let redirect_uri = var("SPOTIFY_REDIRECT_URI")?;
+    let config = PkceConfig::from_env()?.with_scopes(scopes);
+    let rtn = BearerToken::from_token_response(&authorize_in_browser(&config).await?);
+    save_token(&rtn).await?;
+
+    Ok(rtn)
+}
+
+/// The interactive part of `get_bearer_token_via_pkce`: open the browser, catch the redirect,
+/// and exchange the code, keeping the expiry and refresh token. Neither reads nor saves the
+/// token file.
+pub async fn authorize_in_browser(config: &PkceConfig) -> Result<TokenResponse> {
+    let authorization = start_authorization(config)?;
+
+    info!("Opening browser for auth");
+    if let Err(e) = open_browser(authorization.url.as_str()) {
+        warn!("Couldn't open a browser: {}", e);
+        println!(
+            "Open this link in a browser to authorize Phantasy:\n{}",
+            authorization.url
+        );
+    }
+
+    let code = tokio::time::timeout(
+        AUTH_TIMEOUT,
+        listen_for_code(&config.redirect_uri, config.listen_backlog),
+    )
+    .await
+    .map_err(|_| AuthTimedOut {
+        waited: AUTH_TIMEOUT,
+    })??;
+
+    exchange_code(&code, &authorization.verifier, config).await
+}
+
+/// A fresh authorization attempt: where to send the user, and the secret to redeem the code.
+#[derive(Debug, Clone)]
+pub struct Authorization {
+    /// The Spotify page the user visits to grant access
+    pub url: Url,
+    /// Pass to `exchange_code_for_token` along with the code from the redirect
+    pub verifier: String,
+}
+
+/// Begin the PKCE flow without opening a browser.
+///
+/// For servers and TUIs that show the link themselves (or as a QR code) and catch the redirect
+/// on their own, then finish with `exchange_code_for_token`.
+pub fn start_authorization(config: &PkceConfig) -> Result<Authorization> {
     let verifier = generate_code_verifier();
     let challenge = code_challenge(&verifier);
+    Ok(Authorization {
+        url: authorize_url(config, &challenge)?,
+        verifier,
+    })
+}
 
-    let auth_url = Url::parse_with_params(
+/// The Spotify page the user visits to grant access, redirecting back with a `code`.
+pub fn authorize_url(config: &PkceConfig, challenge: &str) -> Result<Url> {
+    Ok(Url::parse_with_params(
         "https://accounts.spotify.com/authorize",
         &[
-            ("client_id", &client_id),
-            ("response_type", &"code".to_string()),
-            ("redirect_uri", &redirect_uri),
-            ("code_challenge_method", &"S256".to_string()),
-            ("code_challenge", &challenge),
-            ("scope", &"user-library-read".to_string()), // adjust as needed
+            ("client_id", config.client_id.as_str()),
+            ("response_type", "code"),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("code_challenge_method", "S256"),
+            ("code_challenge", challenge),
+            ("scope", &Scope::join(&config.scopes)),
         ],
-    )?;
-
-    info!("Opening browser for auth");
-    open_browser(auth_url.as_str())?;
+    )?)
+}
 
-    let code = listen_for_code().await?;
+/// Trade an authorization `code` for a bearer token.
+///
+/// Use this when the redirect is handled elsewhere, e.g. by an external web server or a test,
+/// instead of the listener in `get_bearer_token_via_pkce`. `verifier` must be the one whose
+/// challenge was sent to `authorize_url`. The token is not saved.
+pub async fn exchange_code_for_token(
+    code: &str,
+    verifier: &str,
+    config: &PkceConfig,
+) -> Result<BearerToken> {
+    let resp = exchange_code(code, verifier, config).await?;
+    Ok(BearerToken::from_token_response(&resp))
+}
 
-    let client = reqwest::Client::new();
-    let resp = client
-        .post("https://accounts.spotify.com/api/token")
-        .form(&[
+/// Like `exchange_code_for_token`, keeping the expiry and refresh token for
+/// `SpotifyClient::with_token_refresh`.
+pub async fn exchange_code(
+    code: &str,
+    verifier: &str,
+    config: &PkceConfig,
+) -> Result<TokenResponse> {
+    request_token(
+        &[
             ("grant_type", "authorization_code"),
-            ("code", &code),
-            ("redirect_uri", &redirect_uri),
-            ("client_id", &client_id),
-            ("code_verifier", &verifier),
-        ])
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<TokenResponse>()
-        .await?;
+            ("code", code),
+            ("redirect_uri", &config.redirect_uri),
+            ("client_id", &config.client_id),
+            ("code_verifier", verifier),
+        ],
+        None,
+    )
+    .await
+}
+
+/// Trade a refresh token for a new access token, without involving the user.
+///
+/// Spotify may rotate the refresh token; use the returned one when present.
+pub async fn refresh_access_token(
+    refresh_token: &str,
+    config: &PkceConfig,
+) -> Result<TokenResponse> {
+    refresh_with_client_id(refresh_token, &config.client_id).await
+}
+
+/// `refresh_access_token` for when only the client id is at hand, as with a saved token.
+pub(crate) async fn refresh_with_client_id(
+    refresh_token: &str,
+    client_id: &str,
+) -> Result<TokenResponse> {
+    request_token(
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", client_id),
+        ],
+        None,
+    )
+    .await
+}
+
+/// POST `form` to the token endpoint, authenticating as `(client_id, client_secret)` with
+/// HTTP Basic auth when given, as confidential clients do.
+///
+/// Runs in a `token_request` span recording the current `attempt` and the last HTTP `status`.
+/// The form holds the code or refresh token, so it is never recorded.
+#[tracing::instrument(
+    name = "token_request",
+    skip_all,
+    fields(attempt = field::Empty, status = field::Empty)
+)]
+pub(crate) async fn request_token(
+    form: &[(&str, &str)],
+    basic_auth: Option<(&str, &str)>,
+) -> Result<TokenResponse> {
+    let client = shared_http_client();
+    let span = Span::current();
+
+    // Retry transient failures so a network blip doesn't cost the user another consent screen
+    let mut attempt = 1;
+    let resp = loop {
+        span.record("attempt", attempt);
+        let mut req = client
+            .post("https://accounts.spotify.com/api/token")
+            .form(form);
+        if let Some((client_id, client_secret)) = basic_auth {
+            req = req.basic_auth(client_id, Some(client_secret));
+        }
+        let res = req.send().await;
+        if let Ok(res) = &res {
+            span.record("status", res.status().as_u16());
+        }
+        let transient = match res {
+            Ok(res) if res.status().is_success() => break res.json::<TokenResponse>().await?,
+            Ok(res)
+                if res.status().is_server_error()
+                    || res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS =>
+            {
+                eyre!("Token endpoint returned {}", res.status())
+            }
+            Ok(res) => {
+                let status = res.status();
+                let body = res.text().await.unwrap_or_default();
+                return Err(token_error(status, &body));
+            }
+            Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => eyre::Error::new(e),
+            Err(e) => return Err(e.into()),
+        };
+        if attempt >= TOKEN_EXCHANGE_ATTEMPTS {
+            return Err(
+                transient.wrap_err(format!("Token exchange failed after {} attempts", attempt))
+            );
+        }
+        let backoff = TOKEN_EXCHANGE_BACKOFF * 2u32.pow(attempt - 1);
+        warn!(
+            "Token exchange attempt {} failed, retrying in {:?}: {}",
+            attempt, backoff, transient
+        );
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+    };
 
     debug!("Access Token: len={}", resp.access_token.len());
     debug!("Scope: {}", resp.scope);
     debug!("Expires in: {}s", resp.expires_in);
 
-    let rtn = BearerToken(resp.access_token);
-    save_token(&rtn).await?;
+    Ok(resp)
+}
 
-    Ok(rtn)
+/// Describe a rejected token request, calling out an expired, reused, or revoked grant.
+fn token_error(status: reqwest::StatusCode, body: &str) -> eyre::Error {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        error: String,
+        error_description: Option<String>,
+    }
+
+    match serde_json::from_str::<ErrorBody>(body) {
+        Ok(e) if e.error == "invalid_grant" => eyre!(
+            "Spotify rejected the grant (invalid_grant: {}). Authorization codes are single-use \
+             and expire quickly, and refresh tokens can be revoked, so sign in again",
+            e.error_description.unwrap_or_default()
+        ),
+        Ok(e) if e.error == "invalid_client" => eyre!(
+            "Spotify rejected the client (invalid_client: {}). Check the client ID and secret",
+            e.error_description.unwrap_or_default()
+        ),
+        _ => eyre!("Token exchange failed with {}: {}", status, body),
+    }
 }
 
-fn generate_code_verifier() -> String {
+pub fn generate_code_verifier() -> String {
     rand::rng()
         .sample_iter(&Alphanumeric)
         .take(128)
@@ -99,64 +377,203 @@ fn generate_code_verifier() -> String {
         .collect()
 }
 
-fn code_challenge(verifier: &str) -> String {
+pub fn code_challenge(verifier: &str) -> String {
     let hash = Sha256::digest(verifier.as_bytes());
     base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hash)
 }
 
-async fn listen_for_code() -> Result<String> {
-    let redirect_uri = var("SPOTIFY_REDIRECT_URI")?;
+/// Wait for the redirect carrying the authorization code, returning the code or Spotify's error.
+///
+/// Browsers often connect to the redirect port before the real callback, to preconnect or to
+/// fetch a favicon, so connections are served concurrently and any request without a `code` or
+/// `error` parameter is answered with 204 and otherwise ignored.
+async fn listen_for_code(redirect_uri: &str, backlog: u32) -> Result<String> {
     debug!("Listening for code on {}", redirect_uri);
     let addr = redirect_uri
         .strip_prefix("http://")
         .or_else(|| redirect_uri.strip_prefix("https://"))
         .ok_or_eyre("Invalid redirect URI")?;
-    let listener = TcpListener::bind(addr).await?;
-    let (mut socket, _) = listener.accept().await?;
+    let addr = addr.split('/').next().unwrap_or_default();
+    let addr = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| eyre!("Redirect URI host {} didn't resolve", addr))?;
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.bind(addr)?;
+    let listener = socket.listen(backlog)?;
 
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (socket, peer) = accepted?;
+                debug!("Auth listener accepted a connection from {}", peer);
+                connections.spawn(serve_callback(socket));
+            }
+            Some(served) = connections.join_next() => match served? {
+                Ok(Some(outcome)) => return outcome,
+                Ok(None) => {}
+                // A broken connection can't have been the callback the user is waiting on
+                Err(e) => debug!("Auth listener connection failed: {}", e),
+            },
+        }
+    }
+}
+
+/// Answer one connection to the redirect port. `None` when it wasn't the callback, else the
+/// code or Spotify's error.
+async fn serve_callback(mut socket: TcpStream) -> Result<Option<Result<String>>> {
+    // The request line may arrive over several TCP segments, so read until it's complete
     let mut buffer = [0; 1024];
-    socket.read(&mut buffer).await?;
-    let request = String::from_utf8_lossy(&buffer[..]);
+    let mut len = 0;
+    while len < buffer.len() && !buffer[..len].windows(2).any(|w| w == b"\r\n") {
+        let n = socket.read(&mut buffer[len..]).await?;
+        if n == 0 {
+            break;
+        }
+        len += n;
+    }
+    let request = String::from_utf8_lossy(&buffer[..len]);
+    let request_line = request.split("\r\n").next().unwrap_or_default();
 
-    let code = request
+    let url = request_line
         .split_whitespace()
         .nth(1)
-        .and_then(|url| Url::parse(&format!("http://localhost{}", url)).ok())
-        .and_then(|url| {
+        .and_then(|url| Url::parse(&format!("http://localhost{}", url)).ok());
+    let param = |name: &str| {
+        url.as_ref().and_then(|url| {
             url.query_pairs()
-                .find(|(k, _)| k == "code")
+                .find(|(k, _)| k == name)
                 .map(|(_, v)| v.to_string())
         })
-        .ok_or_else(|| eyre!("Failed to extract code from request"))?;
+    };
 
-    let body = r#"
+    let (outcome, message) = match (param("code"), param("error")) {
+        (Some(code), _) => (
+            Ok(code),
+            "✅ <strong>Spotify auth complete.</strong><br/>You may close this window.".to_string(),
+        ),
+        (None, Some(error)) => (
+            Err(eyre!("Spotify authorization failed: {}", error)),
+            format!(
+                "❌ <strong>Spotify auth failed:</strong> {}<br/>You may close this window.",
+                html_escape(&error)
+            ),
+        ),
+        (None, None) => {
+            debug!("Ignoring request without a code: {}", request_line);
+            socket
+                .write_all(
+                    b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    let body = format!(
+        r#"
         <!DOCTYPE html>
         <html lang="en">
           <head><meta charset="UTF-8"><title>Spotify Auth</title></head>
           <body style="font-family:sans-serif;text-align:center;padding-top:3em">
             <h1>Phantasy</h1>
-            ✅ <strong>Spotify auth complete.</strong><br/>You may close this window.
+            {}
           </body>
         </html>
-        "#;
+        "#,
+        message
+    );
 
     let response = format!(
-        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
         body.len(),
         body
     );
 
     socket.write_all(response.as_bytes()).await?;
-    socket.write_all(response.as_bytes()).await?;
 
-    Ok(code)
+    Ok(Some(outcome))
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
+/// https://developer.spotify.com/documentation/web-api/tutorials/code-pkce-flow#response-1
+///
+/// Also what the client credentials flow answers with, less the scope and refresh token.
 #[derive(Debug, Deserialize, Serialize)]
-struct TokenResponse {
-    access_token: String,
-    token_type: String,
-    scope: String,
-    expires_in: u64,
-    refresh_token: Option<String>,
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    /// Empty for client credentials tokens, which carry no user scopes
+    #[serde(default)]
+    pub scope: String,
+    /// Seconds until `access_token` expires
+    pub expires_in: u64,
+    pub refresh_token: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Send `segments` to `serve_callback` over loopback one write at a time, returning what it
+    /// made of them and the response it wrote.
+    async fn serve(segments: &[&str]) -> (Result<Option<Result<String>>>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let served = tokio::spawn(serve_callback(socket));
+        for segment in segments {
+            client.write_all(segment.as_bytes()).await.unwrap();
+            client.flush().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        (served.await.unwrap(), response)
+    }
+
+    #[tokio::test]
+    async fn request_line_split_across_segments() {
+        let (served, response) = serve(&[
+            "GET /callback?code=ab",
+            "c123&state=s HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+        ])
+        .await;
+        assert_eq!(served.unwrap().unwrap().unwrap(), "abc123");
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn favicon_is_ignored() {
+        let (served, response) =
+            serve(&["GET /favicon.ico HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n"]).await;
+        assert!(served.unwrap().is_none());
+        assert!(
+            response.starts_with("HTTP/1.1 204 No Content"),
+            "{response}"
+        );
+    }
+
+    #[tokio::test]
+    async fn error_callback_fails() {
+        let (served, response) =
+            serve(&["GET /callback?error=access_denied HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n"]).await;
+        let error = served.unwrap().unwrap().unwrap_err();
+        assert!(error.to_string().contains("access_denied"), "{error}");
+        assert!(response.contains("access_denied"), "{response}");
+    }
 }
@@ -0,0 +1,71 @@
+/// A permission the PKCE flow asks the user for.
+///
+/// https://developer.spotify.com/documentation/web-api/concepts/scopes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    UgcImageUpload,
+    UserReadPlaybackState,
+    UserModifyPlaybackState,
+    UserReadCurrentlyPlaying,
+    AppRemoteControl,
+    Streaming,
+    PlaylistReadPrivate,
+    PlaylistReadCollaborative,
+    PlaylistModifyPrivate,
+    PlaylistModifyPublic,
+    UserFollowModify,
+    UserFollowRead,
+    UserReadPlaybackPosition,
+    UserTopRead,
+    UserReadRecentlyPlayed,
+    UserLibraryModify,
+    UserLibraryRead,
+    UserReadEmail,
+    UserReadPrivate,
+}
+
+/// The scopes asked for when the caller names none, enough for `are_tracks_saved`
+pub const DEFAULT_SCOPES: &[Scope] = &[Scope::UserLibraryRead];
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::UgcImageUpload => "ugc-image-upload",
+            Scope::UserReadPlaybackState => "user-read-playback-state",
+            Scope::UserModifyPlaybackState => "user-modify-playback-state",
+            Scope::UserReadCurrentlyPlaying => "user-read-currently-playing",
+            Scope::AppRemoteControl => "app-remote-control",
+            Scope::Streaming => "streaming",
+            Scope::PlaylistReadPrivate => "playlist-read-private",
+            Scope::PlaylistReadCollaborative => "playlist-read-collaborative",
+            Scope::PlaylistModifyPrivate => "playlist-modify-private",
+            Scope::PlaylistModifyPublic => "playlist-modify-public",
+            Scope::UserFollowModify => "user-follow-modify",
+            Scope::UserFollowRead => "user-follow-read",
+            Scope::UserReadPlaybackPosition => "user-read-playback-position",
+            Scope::UserTopRead => "user-top-read",
+            Scope::UserReadRecentlyPlayed => "user-read-recently-played",
+            Scope::UserLibraryModify => "user-library-modify",
+            Scope::UserLibraryRead => "user-library-read",
+            Scope::UserReadEmail => "user-read-email",
+            Scope::UserReadPrivate => "user-read-private",
+        }
+    }
+
+    /// `scopes` as the space-separated list the `scope` parameter takes, each once.
+    pub fn join(scopes: &[Scope]) -> String {
+        let mut joined: Vec<&str> = Vec::with_capacity(scopes.len());
+        for scope in scopes {
+            if !joined.contains(&scope.as_str()) {
+                joined.push(scope.as_str());
+            }
+        }
+        joined.join(" ")
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,149 @@
+use crate::auth::client_credentials::ClientCredentials;
+use crate::auth::pkce::PkceConfig;
+use crate::auth::pkce::refresh_access_token;
+use crate::auth::pkce::save_token;
+use crate::bearer_token::BearerToken;
+use eyre::Result;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tracing::info;
+use tracing::warn;
+
+/// Keeps an access token fresh, shared by every clone of a client.
+pub(crate) struct TokenRefresh {
+    state: Mutex<TokenState>,
+}
+
+/// How a new access token is obtained once the current one nears expiry.
+pub(crate) enum Renewal {
+    /// Trade the refresh token from the PKCE flow, which Spotify may rotate. With `save`, each
+    /// renewed token is also saved as `save_token` does, so a rotated refresh token outlives
+    /// the session
+    RefreshToken {
+        refresh_token: String,
+        config: PkceConfig,
+        save: bool,
+    },
+    /// Request a new app token, as there is no refresh token to trade
+    ClientCredentials(ClientCredentials),
+    /// Issue this access token, valid for an hour, without asking Spotify
+    #[cfg(test)]
+    Fixed(String),
+}
+
+struct TokenState {
+    bearer: BearerToken,
+    renewal: Renewal,
+    expires_at: Instant,
+}
+
+impl TokenRefresh {
+    pub(crate) fn new(bearer: BearerToken, expires_in: Duration, renewal: Renewal) -> Self {
+        Self {
+            state: Mutex::new(TokenState {
+                bearer,
+                renewal,
+                expires_at: Instant::now() + expires_in,
+            }),
+        }
+    }
+
+    /// The current token as is, even when it is about to expire.
+    pub(crate) async fn current(&self) -> BearerToken {
+        self.state.lock().await.bearer.clone()
+    }
+
+    /// The current token, refreshed first when it expires within `skew`.
+    ///
+    /// Holding the lock across the refresh makes concurrent callers wait for one refresh
+    /// rather than each starting their own.
+    pub(crate) async fn bearer(&self, skew: Duration) -> Result<BearerToken> {
+        let mut state = self.state.lock().await;
+        if Instant::now() + skew >= state.expires_at {
+            info!("Access token expires within {:?}, refreshing", skew);
+            let state = &mut *state;
+            let resp = match &mut state.renewal {
+                Renewal::RefreshToken {
+                    refresh_token,
+                    config,
+                    save,
+                } => {
+                    let resp = refresh_access_token(refresh_token, config).await?;
+                    if let Some(rotated) = &resp.refresh_token {
+                        *refresh_token = rotated.clone();
+                    }
+                    state.bearer = BearerToken {
+                        refresh_token: Some(refresh_token.clone()),
+                        ..state.bearer.renewed(&resp)
+                    };
+                    // The token in hand still works, so a failed save shouldn't fail the call
+                    if *save && let Err(e) = save_token(&state.bearer).await {
+                        warn!("Couldn't save the refreshed token: {}", e);
+                    }
+                    resp
+                }
+                Renewal::ClientCredentials(credentials) => {
+                    let resp = credentials.request_token().await?;
+                    state.bearer = BearerToken::from_token_response(&resp);
+                    resp
+                }
+                #[cfg(test)]
+                Renewal::Fixed(access_token) => {
+                    let resp = crate::auth::pkce::TokenResponse {
+                        access_token: access_token.clone(),
+                        token_type: "Bearer".to_string(),
+                        scope: String::new(),
+                        expires_in: 3600,
+                        refresh_token: None,
+                    };
+                    state.bearer = BearerToken::from_token_response(&resp);
+                    resp
+                }
+            };
+            state.expires_at = Instant::now() + Duration::from_secs(resp.expires_in);
+        }
+        Ok(state.bearer.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expiring_in(secs: u64) -> TokenRefresh {
+        TokenRefresh::new(
+            BearerToken::new("old"),
+            Duration::from_secs(secs),
+            Renewal::Fixed("new".to_string()),
+        )
+    }
+
+    #[tokio::test]
+    async fn refreshes_within_skew() {
+        let refresh = expiring_in(30);
+        let bearer = refresh.bearer(Duration::from_secs(60)).await.unwrap();
+        assert_eq!(bearer.access_token, "new");
+        assert_eq!(refresh.current().await.access_token, "new");
+    }
+
+    #[tokio::test]
+    async fn keeps_token_outside_skew() {
+        let refresh = expiring_in(30);
+        let bearer = refresh.bearer(Duration::from_secs(10)).await.unwrap();
+        assert_eq!(bearer.access_token, "old");
+    }
+
+    #[tokio::test]
+    async fn refreshed_token_is_kept_until_it_nears_expiry() {
+        let refresh = expiring_in(0);
+        let skew = Duration::from_secs(60);
+        assert_eq!(refresh.bearer(skew).await.unwrap().access_token, "new");
+        // An hour left now, so a second call doesn't refresh again
+        {
+            let mut state = refresh.state.lock().await;
+            state.renewal = Renewal::Fixed("newer".to_string());
+        }
+        assert_eq!(refresh.bearer(skew).await.unwrap().access_token, "new");
+    }
+}
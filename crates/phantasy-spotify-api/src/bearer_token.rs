@@ -1,4 +1,229 @@
-use serde::{Deserialize, Serialize};
+use crate::auth::pkce::TokenResponse;
+use crate::auth::pkce::refresh_with_client_id;
+use crate::auth::scope::DEFAULT_SCOPES;
+use crate::auth::scope::Scope;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tracing::info;
 
+/// How close to expiry `refresh_if_expired` renews a token
+pub const REFRESH_WINDOW: Duration = Duration::from_secs(60);
+
+/// An access token, with what is needed to renew it when it came from the PKCE flow.
+///
+/// Saved as an object in `bearer_token.json`. Files from before the refresh token and expiry
+/// were kept hold just the access token as a string, and still load, with neither.
+///
+/// This was once a tuple struct around the access token: read `access_token` where code used
+/// `.0`, and build one with [`BearerToken::new`] where it used `BearerToken(token)`.
 #[derive(Serialize, Deserialize, Clone)]
-pub struct BearerToken(pub String);
+#[serde(from = "SavedBearerToken")]
+pub struct BearerToken {
+    pub access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// When `access_token` expires, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// The scopes the user granted, space-separated as Spotify lists them. `None` for files
+    /// from before they were kept, which were all granted [`DEFAULT_SCOPES`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+impl BearerToken {
+    /// A bare access token, which can't be refreshed.
+    pub fn new(access_token: impl Into<String>) -> Self {
+        Self {
+            access_token: access_token.into(),
+            refresh_token: None,
+            expires_at: None,
+            scope: None,
+        }
+    }
+
+    /// The token Spotify just issued, expiring `expires_in` from now.
+    pub fn from_token_response(resp: &TokenResponse) -> Self {
+        Self {
+            access_token: resp.access_token.clone(),
+            refresh_token: resp.refresh_token.clone(),
+            expires_at: Some(unix_now() + resp.expires_in),
+            scope: Some(resp.scope.clone()),
+        }
+    }
+
+    /// Whether the user granted every one of `scopes` to this token.
+    pub fn grants(&self, scopes: &[Scope]) -> bool {
+        scopes.iter().all(|scope| match &self.scope {
+            Some(granted) => granted.split_whitespace().any(|g| g == scope.as_str()),
+            None => DEFAULT_SCOPES.contains(scope),
+        })
+    }
+
+    /// Time left before the access token expires, zero once it has. `None` when unknown.
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expires_at
+            .map(|at| Duration::from_secs(at.saturating_sub(unix_now())))
+    }
+
+    /// Whether the access token expires within `window`. A token with no known expiry never does.
+    pub fn expires_within(&self, window: Duration) -> bool {
+        self.expires_in().is_some_and(|left| left <= window)
+    }
+
+    /// Renew the access token with the refresh token when it expires within [`REFRESH_WINDOW`].
+    /// Returns whether it was renewed; tokens without a refresh token or expiry are left alone.
+    pub async fn refresh_if_expired(&mut self, client_id: &str) -> eyre::Result<bool> {
+        let Some(refresh_token) = &self.refresh_token else {
+            return Ok(false);
+        };
+        if !self.expires_within(REFRESH_WINDOW) {
+            return Ok(false);
+        }
+        info!(
+            "Saved access token expires within {:?}, refreshing",
+            REFRESH_WINDOW
+        );
+        let resp = refresh_with_client_id(refresh_token, client_id).await?;
+        *self = self.renewed(&resp);
+        Ok(true)
+    }
+
+    /// This token renewed by `resp`, keeping the refresh token and granted scopes when Spotify
+    /// leaves them out.
+    pub(crate) fn renewed(&self, resp: &TokenResponse) -> BearerToken {
+        let renewed = BearerToken::from_token_response(resp);
+        BearerToken {
+            refresh_token: renewed.refresh_token.or(self.refresh_token.clone()),
+            scope: renewed
+                .scope
+                .filter(|scope| !scope.is_empty())
+                .or(self.scope.clone()),
+            ..renewed
+        }
+    }
+}
+
+/// Either shape of `bearer_token.json`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedBearerToken {
+    AccessTokenOnly(String),
+    Full {
+        access_token: String,
+        #[serde(default)]
+        refresh_token: Option<String>,
+        #[serde(default)]
+        expires_at: Option<u64>,
+        #[serde(default)]
+        scope: Option<String>,
+    },
+}
+
+impl From<SavedBearerToken> for BearerToken {
+    fn from(saved: SavedBearerToken) -> Self {
+        match saved {
+            SavedBearerToken::AccessTokenOnly(access_token) => BearerToken::new(access_token),
+            SavedBearerToken::Full {
+                access_token,
+                refresh_token,
+                expires_at,
+                scope,
+            } => BearerToken {
+                access_token,
+                refresh_token,
+                expires_at,
+                scope,
+            },
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_only_recorded_scopes() {
+        let token = BearerToken {
+            scope: Some("user-library-read playlist-read-private".to_string()),
+            ..BearerToken::new("token")
+        };
+        assert!(token.grants(&[Scope::PlaylistReadPrivate, Scope::UserLibraryRead]));
+        assert!(token.grants(&[]));
+        assert!(!token.grants(&[Scope::PlaylistReadPrivate, Scope::UserTopRead]));
+    }
+
+    #[test]
+    fn unrecorded_scopes_are_the_defaults() {
+        let token = BearerToken::new("token");
+        assert!(token.grants(DEFAULT_SCOPES));
+        assert!(!token.grants(&[Scope::PlaylistReadPrivate]));
+    }
+
+    /// The fields of `token`, to compare without `BearerToken` being `PartialEq`.
+    fn fields(token: &BearerToken) -> (&str, Option<&str>, Option<u64>, Option<&str>) {
+        (
+            &token.access_token,
+            token.refresh_token.as_deref(),
+            token.expires_at,
+            token.scope.as_deref(),
+        )
+    }
+
+    #[test]
+    fn loads_a_bare_access_token() {
+        let token: BearerToken = serde_json::from_str(r#""token""#).unwrap();
+        assert_eq!(fields(&token), ("token", None, None, None));
+    }
+
+    #[test]
+    fn loads_a_full_token_with_and_without_renewal() {
+        let full: BearerToken = serde_json::from_str(
+            r#"{"access_token":"token","refresh_token":"refresh","expires_at":1700000000,"scope":"user-top-read"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            fields(&full),
+            (
+                "token",
+                Some("refresh"),
+                Some(1_700_000_000),
+                Some("user-top-read")
+            )
+        );
+
+        let bare: BearerToken = serde_json::from_str(r#"{"access_token":"token"}"#).unwrap();
+        assert_eq!(fields(&bare), ("token", None, None, None));
+    }
+
+    #[test]
+    fn saves_and_loads_the_same_token() {
+        let token = BearerToken {
+            access_token: "token".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: Some(1_700_000_000),
+            scope: Some("user-library-read playlist-read-private".to_string()),
+        };
+        let loaded: BearerToken =
+            serde_json::from_str(&serde_json::to_string(&token).unwrap()).unwrap();
+        assert_eq!(fields(&loaded), fields(&token));
+
+        // A bare token saves without the missing fields, and loads the same
+        let bare = BearerToken::new("token");
+        let json = serde_json::to_string(&bare).unwrap();
+        assert_eq!(json, r#"{"access_token":"token"}"#);
+        let loaded: BearerToken = serde_json::from_str(&json).unwrap();
+        assert_eq!(fields(&loaded), fields(&bare));
+    }
+}
@@ -0,0 +1,398 @@
+use crate::auth::client_credentials::ClientCredentials;
+use crate::auth::pkce::PkceConfig;
+use crate::auth::token_refresh::Renewal;
+use crate::auth::token_refresh::TokenRefresh;
+use crate::bearer_token::BearerToken;
+use crate::fetch::FetchOptions;
+use crate::fetch::fetch_with_options;
+use crate::spotify_api_error::DEFAULT_ERROR_BODY_LIMIT;
+use crate::spotify_error::SpotifyError;
+use crate::track::Track;
+use crate::track_audio_features::TrackAudioFeatures;
+use crate::track_id::TrackId;
+use reqwest::header::HeaderMap;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::debug;
+use tracing::warn;
+
+/// How long before expiry a refreshable token is renewed, unless configured otherwise
+pub const DEFAULT_REFRESH_SKEW: Duration = Duration::from_secs(60);
+
+/// The `reqwest::Client` behind `SpotifyClient::new`, the free endpoint functions, and the token
+/// exchange, built on first use. Sharing it pools connections and reuses TLS sessions across
+/// them, which matters when matching a library makes hundreds of calls.
+static SHARED_HTTP: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// The process-wide `reqwest::Client`. Cheap to clone, and clones share its connection pool.
+pub fn shared_http_client() -> &'static reqwest::Client {
+    &SHARED_HTTP
+}
+
+/// A Spotify Web API client that reuses a single `reqwest::Client` for every request.
+#[derive(Clone)]
+pub struct SpotifyClient {
+    http: reqwest::Client,
+    bearer: BearerToken,
+    default_headers: HeaderMap,
+    market_fallback: bool,
+    audio_analysis_fallback: bool,
+    /// Caps requests in flight, shared by every clone of this client
+    request_slots: Option<Arc<Semaphore>>,
+    /// Renews `bearer` before it expires, shared by every clone of this client
+    token_refresh: Option<Arc<TokenRefresh>>,
+    refresh_skew: Duration,
+    error_body_limit: usize,
+    fetch_options: FetchOptions,
+}
+
+impl SpotifyClient {
+    /// Create a client on the [`shared_http_client`], pooling connections with every other
+    /// client created this way.
+    pub fn new(bearer: BearerToken) -> Self {
+        Self::with_http_client(shared_http_client().clone(), bearer)
+    }
+
+    /// Create a client around an externally-built `reqwest::Client`.
+    ///
+    /// Use this when the application already configures proxies, TLS roots, or timeouts centrally.
+    pub fn with_http_client(http: reqwest::Client, bearer: BearerToken) -> Self {
+        Self {
+            http,
+            bearer,
+            default_headers: HeaderMap::new(),
+            market_fallback: false,
+            audio_analysis_fallback: false,
+            request_slots: None,
+            token_refresh: None,
+            refresh_skew: DEFAULT_REFRESH_SKEW,
+            error_body_limit: DEFAULT_ERROR_BODY_LIMIT,
+            fetch_options: FetchOptions::default(),
+        }
+    }
+
+    /// Send `headers` with every request made through this client, e.g. a request id for tracing.
+    pub fn with_default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    /// Retry `get_track_in_market` once without the market when the market answers 404.
+    ///
+    /// Spotify may 404 a track that exists globally but is fully unavailable in the market, which
+    /// would otherwise fail a whole playlist enrichment pass. Off by default.
+    pub fn with_market_fallback(mut self, enabled: bool) -> Self {
+        self.market_fallback = enabled;
+        self
+    }
+
+    /// Fill `get_track_audio_features` from the audio analysis when the features endpoint
+    /// answers 403.
+    ///
+    /// Spotify has withdrawn audio features from some apps. The fallback keeps tempo and key
+    /// based analytics working, flagging what it returns with `FeaturesOrigin::AudioAnalysis`.
+    /// Off by default.
+    pub fn with_audio_analysis_fallback(mut self, enabled: bool) -> Self {
+        self.audio_analysis_fallback = enabled;
+        self
+    }
+
+    /// Allow at most `max` requests in flight at once across this client and its clones.
+    ///
+    /// Protects a flaky connection from bursts of parallel calls. Independent of any rate limit.
+    ///
+    /// Panics when `max` is 0, which would leave every request waiting forever.
+    pub fn with_max_concurrent_requests(mut self, max: usize) -> Self {
+        assert!(max > 0, "max_concurrent_requests must be at least 1");
+        self.request_slots = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// Refresh the bearer token with `refresh_token` before it expires, `expires_in` from now.
+    ///
+    /// Pass the `expires_in` and `refresh_token` of the `TokenResponse` the bearer came from.
+    pub fn with_token_refresh(
+        self,
+        refresh_token: String,
+        expires_in: Duration,
+        config: PkceConfig,
+    ) -> Self {
+        self.with_refresh_token(refresh_token, expires_in, config, false)
+    }
+
+    /// Like `with_token_refresh`, also saving each renewed token to `bearer_token.json` as
+    /// `save_token` does. Spotify may rotate the refresh token when it is used, leaving the
+    /// saved one dead for the next session otherwise.
+    pub fn with_saved_token_refresh(
+        self,
+        refresh_token: String,
+        expires_in: Duration,
+        config: PkceConfig,
+    ) -> Self {
+        self.with_refresh_token(refresh_token, expires_in, config, true)
+    }
+
+    fn with_refresh_token(
+        mut self,
+        refresh_token: String,
+        expires_in: Duration,
+        config: PkceConfig,
+        save: bool,
+    ) -> Self {
+        self.token_refresh = Some(Arc::new(TokenRefresh::new(
+            self.bearer.clone(),
+            expires_in,
+            Renewal::RefreshToken {
+                refresh_token,
+                config,
+                save,
+            },
+        )));
+        self
+    }
+
+    /// Request a new app token with `credentials` before the bearer token expires, for a client
+    /// whose token came from `get_bearer_token_via_client_credentials`.
+    ///
+    /// A bearer with no known expiry is renewed before the first request.
+    pub fn with_client_credentials(mut self, credentials: ClientCredentials) -> Self {
+        self.token_refresh = Some(Arc::new(TokenRefresh::new(
+            self.bearer.clone(),
+            self.bearer.expires_in().unwrap_or_default(),
+            Renewal::ClientCredentials(credentials),
+        )));
+        self
+    }
+
+    /// Refresh the token once it is within `skew` of expiring, rather than at expiry, so a
+    /// request sent just before expiry doesn't arrive just after it. Defaults to 60 seconds.
+    pub fn with_refresh_skew(mut self, skew: Duration) -> Self {
+        self.refresh_skew = skew;
+        self
+    }
+
+    /// Keep at most `limit` bytes of an error response for the `SpotifyError` it becomes, so
+    /// a misbehaving proxy can't flood logs. Defaults to 4 KiB.
+    pub fn with_error_body_limit(mut self, limit: usize) -> Self {
+        self.error_body_limit = limit;
+        self
+    }
+
+    /// Retry rate-limited and failing requests as `options` sets out, see `fetch_with_options`.
+    /// Defaults to 3 retries, waiting as long as `Retry-After` asks up to a minute.
+    pub fn with_fetch_options(mut self, options: FetchOptions) -> Self {
+        self.fetch_options = options;
+        self
+    }
+
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// The token requests are currently sent with: the latest refreshed one when the client
+    /// refreshes its token, and otherwise the one it was created with. Doesn't refresh it.
+    pub async fn bearer(&self) -> BearerToken {
+        match &self.token_refresh {
+            Some(token_refresh) => token_refresh.current().await,
+            None => self.bearer.clone(),
+        }
+    }
+
+    pub fn refresh_skew(&self) -> Duration {
+        self.refresh_skew
+    }
+
+    pub fn error_body_limit(&self) -> usize {
+        self.error_body_limit
+    }
+
+    pub fn fetch_options(&self) -> &FetchOptions {
+        &self.fetch_options
+    }
+
+    pub fn default_headers(&self) -> &HeaderMap {
+        &self.default_headers
+    }
+
+    pub fn market_fallback(&self) -> bool {
+        self.market_fallback
+    }
+
+    pub fn audio_analysis_fallback(&self) -> bool {
+        self.audio_analysis_fallback
+    }
+
+    /// Open a pooled connection to the API ahead of the first real call, paying DNS and the TLS
+    /// handshake up front so interactive tools feel quicker.
+    ///
+    /// Best effort: failures are logged and otherwise ignored.
+    pub async fn warm_up(&self) {
+        let res = self
+            .http
+            .head("https://api.spotify.com/v1/")
+            .headers(self.default_headers.clone())
+            .send()
+            .await;
+        match res {
+            Ok(res) => debug!("Warmed up Spotify connection ({})", res.status()),
+            Err(e) => debug!("Failed to warm up Spotify connection: {}", e),
+        }
+    }
+
+    /// GET a Spotify endpoint and deserialize the JSON response.
+    pub async fn fetch<T>(&self, url: &str) -> Result<T, SpotifyError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.fetch_with_headers(url, &HeaderMap::new()).await
+    }
+
+    /// Like `fetch`, but with `headers` replacing any same-named default headers for this call.
+    pub async fn fetch_with_headers<T>(
+        &self,
+        url: &str,
+        headers: &HeaderMap,
+    ) -> Result<T, SpotifyError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut merged = self.default_headers.clone();
+        for name in headers.keys() {
+            merged.remove(name);
+        }
+        for (name, value) in headers {
+            merged.append(name, value.clone());
+        }
+        let _permit = match &self.request_slots {
+            Some(slots) => Some(slots.acquire().await.map_err(eyre::Report::from)?),
+            None => None,
+        };
+        let bearer = match &self.token_refresh {
+            Some(token_refresh) => token_refresh.bearer(self.refresh_skew).await?,
+            None => self.bearer.clone(),
+        };
+        fetch_with_options(
+            &self.http,
+            url,
+            &bearer,
+            &merged,
+            self.error_body_limit,
+            &self.fetch_options,
+        )
+        .await
+    }
+
+    /// https://developer.spotify.com/documentation/web-api/reference/get-track
+    ///
+    /// Accepts a `TrackId` or a raw `&str`/`String`, erroring early when it isn't a valid ID.
+    pub async fn get_track(&self, track_id: impl AsRef<str>) -> Result<Track, SpotifyError> {
+        let track_id = TrackId::parse(track_id)?;
+        let url = format!("https://api.spotify.com/v1/tracks/{}", track_id);
+        self.fetch(&url).await
+    }
+
+    /// https://developer.spotify.com/documentation/web-api/reference/get-audio-features
+    ///
+    /// `None` when Spotify answers with no content, as it does for some tracks it has analysed
+    /// but won't serve features for. With `with_audio_analysis_fallback` enabled, a 403 is
+    /// answered from the audio analysis instead, with `origin` set to
+    /// `FeaturesOrigin::AudioAnalysis`.
+    pub async fn get_track_audio_features(
+        &self,
+        track_id: impl AsRef<str>,
+    ) -> Result<Option<TrackAudioFeatures>, SpotifyError> {
+        let track_id = TrackId::parse(track_id)?;
+        let url = format!("https://api.spotify.com/v1/audio-features/{}", track_id);
+        match self.fetch(&url).await {
+            Err(SpotifyError::Forbidden { .. }) if self.audio_analysis_fallback => {
+                warn!(
+                    "Audio features for {} are forbidden, falling back to the audio analysis",
+                    track_id
+                );
+                let analysis = self.get_track_audio_analysis(&track_id).await?;
+                Ok(Some(TrackAudioFeatures::from_analysis(
+                    &track_id,
+                    &analysis.track,
+                )?))
+            }
+            res => res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use tokio::task::JoinSet;
+
+    /// Serve `{}` to every request after a short delay, recording the most answered at once.
+    async fn serve_slowly(peak: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (in_flight, peak) = (in_flight.clone(), peak.clone());
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+                        )
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+        format!("http://{}/v1/me", addr)
+    }
+
+    #[tokio::test]
+    async fn concurrency_never_exceeds_cap() {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let url = serve_slowly(peak.clone()).await;
+        let client = SpotifyClient::with_http_client(reqwest::Client::new(), BearerToken::new("t"))
+            .with_max_concurrent_requests(3);
+
+        let mut requests = JoinSet::new();
+        for _ in 0..12 {
+            let (client, url) = (client.clone(), url.clone());
+            requests.spawn(async move { client.fetch::<serde_json::Value>(&url).await });
+        }
+        while let Some(res) = requests.join_next().await {
+            res.unwrap().unwrap();
+        }
+        let peak = peak.load(Ordering::SeqCst);
+        assert!(
+            (1..=3).contains(&peak),
+            "{} requests were in flight at once",
+            peak
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1")]
+    fn zero_concurrent_requests_is_rejected() {
+        SpotifyClient::new(BearerToken::new("t")).with_max_concurrent_requests(0);
+    }
+}
@@ -0,0 +1,31 @@
+use crate::track::Track;
+use std::collections::HashMap;
+
+/// Collapse tracks sharing an ISRC into one representative, e.g. after merging playlists.
+///
+/// The representative is the most popular track, then the one available in the most markets,
+/// then the earliest. It takes the place of the first track with that ISRC. Tracks without an
+/// ISRC are kept as-is.
+pub fn dedup_by_isrc(tracks: Vec<Track>) -> Vec<Track> {
+    let mut kept: Vec<Track> = Vec::with_capacity(tracks.len());
+    let mut index_by_isrc: HashMap<String, usize> = HashMap::new();
+    for track in tracks {
+        let Some(isrc) = track.external_ids.isrc.clone() else {
+            kept.push(track);
+            continue;
+        };
+        match index_by_isrc.get(&isrc) {
+            Some(&i) => {
+                let rank = |t: &Track| (t.popularity, t.available_markets.len());
+                if rank(&track) > rank(&kept[i]) {
+                    kept[i] = track;
+                }
+            }
+            None => {
+                index_by_isrc.insert(isrc, kept.len());
+                kept.push(track);
+            }
+        }
+    }
+    kept
+}
@@ -0,0 +1,60 @@
+use crate::artist_id::ArtistId;
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::track::Track;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// A track tagged with the genres of its artists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaggedTrack {
+    pub track: Track,
+    /// Union of the genres of every artist on the track, in first-seen order
+    pub genres: Vec<String>,
+}
+
+impl SpotifyClient {
+    /// Tag each track with its artists' genres, since Spotify only records genres on artists.
+    ///
+    /// Each artist is fetched once however many tracks it appears on.
+    pub async fn enrich_with_genres(&self, tracks: Vec<Track>) -> eyre::Result<Vec<TaggedTrack>> {
+        let mut seen = HashSet::new();
+        let artist_ids: Vec<ArtistId> = tracks
+            .iter()
+            .flat_map(|track| &track.artists)
+            .filter(|artist| !artist.id.is_empty() && seen.insert(artist.id.clone()))
+            .map(|artist| ArtistId(artist.id.clone()))
+            .collect();
+        let genres_by_artist: HashMap<String, Vec<String>> = self
+            .get_several_artists(&artist_ids)
+            .await?
+            .into_iter()
+            .flatten()
+            .map(|artist| (artist.id, artist.genres))
+            .collect();
+
+        Ok(tracks
+            .into_iter()
+            .map(|track| {
+                let mut seen = HashSet::new();
+                let genres = track
+                    .artists
+                    .iter()
+                    .filter_map(|artist| genres_by_artist.get(&artist.id))
+                    .flatten()
+                    .filter(|genre| seen.insert(*genre))
+                    .cloned()
+                    .collect();
+                TaggedTrack { track, genres }
+            })
+            .collect())
+    }
+}
+
+/// Tag each track with its artists' genres.
+pub async fn enrich_with_genres(
+    tracks: Vec<Track>,
+    bearer: BearerToken,
+) -> eyre::Result<Vec<TaggedTrack>> {
+    SpotifyClient::new(bearer).enrich_with_genres(tracks).await
+}
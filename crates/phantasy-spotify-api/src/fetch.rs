@@ -1,21 +1,397 @@
 use crate::bearer_token::BearerToken;
+use crate::client::shared_http_client;
+use crate::spotify_api_error::DEFAULT_ERROR_BODY_LIMIT;
+use crate::spotify_error::SpotifyError;
+use reqwest::header::HeaderMap;
+use serde_path_to_error::Segment;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::Span;
+use tracing::debug;
+use tracing::field;
+use tracing::warn;
 
-pub async fn fetch<T>(url: &str, bearer: BearerToken) -> eyre::Result<T>
+/// Retries of a rate-limited or failing request before giving up, unless configured otherwise
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+/// Wait before the first retry when Spotify doesn't say how long, doubling after each failure
+pub const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Longest `Retry-After` waited out before retrying, unless configured otherwise
+pub const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How `fetch_with_options` retries 429 and 5xx responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchOptions {
+    /// Retries after the first attempt; 0 returns the first failure as is
+    pub max_retries: u32,
+    /// Wait as long as a 429's `Retry-After` asks rather than the usual backoff
+    pub respect_retry_after: bool,
+    /// A 429 asking to wait longer than this is returned rather than retried, so a long ban
+    /// doesn't stall the caller for hours
+    pub max_retry_after: Duration,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            respect_retry_after: true,
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+        }
+    }
+}
+
+pub async fn fetch<T>(url: &str, bearer: BearerToken) -> Result<T, SpotifyError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    fetch_with_client(shared_http_client(), url, &bearer).await
+}
+
+pub async fn fetch_with_client<T>(
+    client: &reqwest::Client,
+    url: &str,
+    bearer: &BearerToken,
+) -> Result<T, SpotifyError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    fetch_with_headers(client, url, bearer, &HeaderMap::new()).await
+}
+
+/// Like `fetch_with_client`, but sends `headers` along with the request.
+///
+/// Useful for tracing headers such as `traceparent`; Spotify ignores headers it doesn't know.
+/// A header here replaces any same-named default configured on `client`.
+pub async fn fetch_with_headers<T>(
+    client: &reqwest::Client,
+    url: &str,
+    bearer: &BearerToken,
+    headers: &HeaderMap,
+) -> Result<T, SpotifyError>
 where
     T: serde::de::DeserializeOwned,
 {
-    let client = reqwest::Client::new();
-    let res = client
+    fetch_with_error_body_limit(client, url, bearer, headers, DEFAULT_ERROR_BODY_LIMIT).await
+}
+
+/// Like `fetch_with_headers`, keeping at most `error_body_limit` bytes of an error response.
+///
+/// A non-success response fails with the [`SpotifyError`] for its status, carrying Spotify's
+/// reason for it, so a 401 or 403 says which token or scope was wrong and a 429 how long to wait.
+/// Rate limiting and server errors are retried as [`FetchOptions::default`] sets out.
+pub async fn fetch_with_error_body_limit<T>(
+    client: &reqwest::Client,
+    url: &str,
+    bearer: &BearerToken,
+    headers: &HeaderMap,
+    error_body_limit: usize,
+) -> Result<T, SpotifyError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let options = FetchOptions::default();
+    fetch_with_options(client, url, bearer, headers, error_body_limit, &options).await
+}
+
+/// Like `fetch_with_error_body_limit`, retrying as `options` sets out.
+///
+/// A 429 or 5xx response is retried up to `options.max_retries` times. Before each retry it
+/// waits for the response's `Retry-After` when there is one and `options.respect_retry_after`
+/// is set, and otherwise for [`RETRY_BACKOFF`], doubling with each retry. A 429 whose
+/// `Retry-After` exceeds `options.max_retry_after` is returned rather than waited out. Other
+/// failures, including the remaining 4xx responses, are returned at once.
+///
+/// Runs in a `spotify_request` span carrying the `endpoint` (the URL path with IDs replaced by
+/// `{id}`, to keep it low-cardinality), the `id` it replaced, the current `attempt`, and once
+/// answered the HTTP `status` and the `elapsed_ms` over every attempt. The bearer token and
+/// headers are never recorded.
+#[tracing::instrument(
+    name = "spotify_request",
+    skip_all,
+    fields(
+        endpoint = %endpoint_of(url),
+        id = field::Empty,
+        attempt = field::Empty,
+        status = field::Empty,
+        elapsed_ms = field::Empty,
+    )
+)]
+pub async fn fetch_with_options<T>(
+    client: &reqwest::Client,
+    url: &str,
+    bearer: &BearerToken,
+    headers: &HeaderMap,
+    error_body_limit: usize,
+    options: &FetchOptions,
+) -> Result<T, SpotifyError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let span = Span::current();
+    if let Some(id) = id_of(url) {
+        span.record("id", id);
+    }
+
+    let started = Instant::now();
+    let mut attempt = 1;
+    let res = loop {
+        span.record("attempt", attempt);
+        let res = send_once(client, url, bearer, headers, error_body_limit, &span).await;
+        let e = match &res {
+            Err(e) if attempt <= options.max_retries && is_retryable(e) => e,
+            _ => break res,
+        };
+        let wait = match e {
+            SpotifyError::RateLimited {
+                retry_after: Some(retry_after),
+            } if options.respect_retry_after => {
+                if *retry_after > options.max_retry_after {
+                    debug!(
+                        "Attempt {} asked to wait {:?}, longer than {:?}, giving up",
+                        attempt, retry_after, options.max_retry_after
+                    );
+                    break res;
+                }
+                *retry_after
+            }
+            _ => RETRY_BACKOFF * 2u32.saturating_pow(attempt - 1),
+        };
+        debug!("Attempt {} failed, retrying in {:?}: {}", attempt, wait, e);
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    };
+    span.record("elapsed_ms", started.elapsed().as_millis() as u64);
+    let res = match res {
+        Ok(res) => {
+            debug!("Request completed");
+            res
+        }
+        Err(e) => {
+            debug!("Request failed: {}", e);
+            return Err(e);
+        }
+    };
+
+    // A 204 or otherwise empty body reads as `null`, so `Option` responses come back as `None`
+    let res = if res.trim().is_empty() {
+        "null".to_string()
+    } else {
+        res
+    };
+    match serde_json::from_str(&res) {
+        Ok(x) => Ok(x),
+        Err(source) => {
+            let divergence = find_divergent_field::<T>(&res);
+            if let Some(divergence) = &divergence {
+                warn!("Unexpected response from {}: {}", url, divergence);
+            }
+            Err(SpotifyError::Deserialize {
+                body: res,
+                divergence,
+                source,
+            })
+        }
+    }
+}
+
+/// Send the request once, returning the body of a success and the error for anything else.
+async fn send_once(
+    client: &reqwest::Client,
+    url: &str,
+    bearer: &BearerToken,
+    headers: &HeaderMap,
+    error_body_limit: usize,
+    span: &Span,
+) -> Result<String, SpotifyError> {
+    let mut res = client
         .get(url)
-        .bearer_auth(bearer.0)
+        .bearer_auth(&bearer.access_token)
+        .headers(headers.clone())
         .send()
-        .await?
-        .error_for_status()?
-        .text()
         .await?;
+    let status = res.status();
+    span.record("status", status.as_u16());
+    if !status.is_success() {
+        let headers = res.headers().clone();
+        let (body, truncated) = read_capped(&mut res, error_body_limit).await?;
+        return Err(SpotifyError::from_response(
+            status, &headers, &body, truncated,
+        ));
+    }
+    Ok(res.text().await?)
+}
 
-    match serde_json::from_str(&res) {
-        Ok(x) => Ok(x),
-        Err(e) => Err(eyre::Error::new(e).wrap_err(format!("Failed to deserialize:\n{}", res))),
+/// Whether sending the request again may succeed: it was rate limited or hit a server error.
+fn is_retryable(e: &SpotifyError) -> bool {
+    match e {
+        SpotifyError::RateLimited { .. } => true,
+        SpotifyError::Status(e) => e.status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// Read at most `limit` bytes of the body, and whether there was more.
+async fn read_capped(
+    res: &mut reqwest::Response,
+    limit: usize,
+) -> reqwest::Result<(Vec<u8>, bool)> {
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        let room = limit - body.len();
+        if chunk.len() > room {
+            body.extend_from_slice(&chunk[..room]);
+            return Ok((body, true));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((body, false))
+}
+
+/// The path of `url` with every ID segment replaced by `{id}`, e.g. `/v1/tracks/{id}`.
+fn endpoint_of(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(url) => url
+            .path()
+            .split('/')
+            .map(|segment| if is_id(segment) { "{id}" } else { segment })
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => "<invalid url>".to_string(),
+    }
+}
+
+/// The first ID segment in the path of `url`, if any. IDs passed in the query, as the batch
+/// endpoints do, are left out since there can be hundreds.
+fn id_of(url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok()?;
+    url.path()
+        .split('/')
+        .find(|segment| is_id(segment))
+        .map(str::to_string)
+}
+
+/// Spotify IDs are 22 base-62 characters.
+fn is_id(segment: &str) -> bool {
+    segment.len() == 22 && segment.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Retry deserializing `body` through a `serde_json::Value` to pinpoint the field that broke,
+/// e.g. "field `/available_markets` was null: invalid type: null, expected a sequence".
+///
+/// Returns `None` when `body` isn't JSON at all.
+fn find_divergent_field<T>(body: &str) -> Option<String>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let err = serde_path_to_error::deserialize::<_, T>(&value).err()?;
+    let pointer: String = err
+        .path()
+        .iter()
+        .map(|segment| match segment {
+            Segment::Seq { index } => format!("/{}", index),
+            Segment::Map { key } => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+            Segment::Enum { variant } => format!("/{}", variant),
+            Segment::Unknown => "/?".to_string(),
+        })
+        .collect();
+    let found = match value.pointer(&pointer) {
+        Some(serde_json::Value::Null) => "was null",
+        Some(serde_json::Value::Bool(_)) => "was a boolean",
+        Some(serde_json::Value::Number(_)) => "was a number",
+        Some(serde_json::Value::String(_)) => "was a string",
+        Some(serde_json::Value::Array(_)) => "was an array",
+        Some(serde_json::Value::Object(_)) => "was an object",
+        None => "was missing",
+    };
+    Some(format!("field `{}` {}: {}", pointer, found, err.inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::Track;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    /// Answer every request with a 429 asking to wait an hour, counting the requests.
+    async fn rate_limit_for_an_hour(requests: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let requests = requests.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    socket
+                        .write_all(
+                            b"HTTP/1.1 429 Too Many Requests\r\nRetry-After: 3600\r\n\
+                              Content-Length: 0\r\nConnection: close\r\n\r\n",
+                        )
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+        format!("http://{}/v1/me", addr)
+    }
+
+    #[tokio::test]
+    async fn retry_after_beyond_the_cap_is_returned() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = rate_limit_for_an_hour(requests.clone()).await;
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            fetch_with_options::<serde_json::Value>(
+                &reqwest::Client::new(),
+                &url,
+                &BearerToken::new("t"),
+                &HeaderMap::new(),
+                DEFAULT_ERROR_BODY_LIMIT,
+                &FetchOptions::default(),
+            ),
+        )
+        .await
+        .expect("waited out the Retry-After");
+        assert!(matches!(
+            res,
+            Err(SpotifyError::RateLimited {
+                retry_after: Some(retry_after),
+            }) if retry_after == Duration::from_secs(3600)
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn divergent_field_is_pinpointed() {
+        let mut track = serde_json::to_value(Track::default()).unwrap();
+        track["available_markets"] = serde_json::Value::Null;
+        let found = find_divergent_field::<Track>(&track.to_string()).unwrap();
+        assert!(
+            found.starts_with("field `/available_markets` was null"),
+            "{found}"
+        );
+    }
+
+    #[test]
+    fn divergent_field_of_non_json_is_none() {
+        assert_eq!(
+            find_divergent_field::<Track>("<html>Bad gateway</html>"),
+            None
+        );
     }
 }
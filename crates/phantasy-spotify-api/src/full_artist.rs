@@ -0,0 +1,31 @@
+use crate::track::ExternalUrls;
+use crate::track::Image;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The full artist object, unlike the simplified `Artist` embedded in tracks and albums.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct FullArtist {
+    #[serde(rename = "external_urls")]
+    pub external_urls: ExternalUrls,
+    pub followers: Followers,
+    pub genres: Vec<String>,
+    pub href: String,
+    pub id: String,
+    pub images: Vec<Image>,
+    pub name: String,
+    pub popularity: i64,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub uri: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Followers {
+    pub href: Option<String>,
+    pub total: i64,
+}
@@ -0,0 +1,21 @@
+use crate::album_id::AlbumId;
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::track::Album;
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/get-an-album
+    ///
+    /// Unlike the album nested in a `Track`, this one carries `tracks`, `copyrights`, `label`,
+    /// `popularity`, and `external_ids`. `tracks` holds only the first page of up to 50 tracks;
+    /// follow its `next` for the rest of a longer album.
+    pub async fn get_album(&self, album_id: &AlbumId) -> eyre::Result<Album> {
+        let url = format!("https://api.spotify.com/v1/albums/{}", album_id);
+        Ok(self.fetch(&url).await?)
+    }
+}
+
+/// https://developer.spotify.com/documentation/web-api/reference/get-an-album
+pub async fn get_album(album_id: AlbumId, bearer: BearerToken) -> eyre::Result<Album> {
+    SpotifyClient::new(bearer).get_album(&album_id).await
+}
@@ -0,0 +1,84 @@
+use crate::artist_id::ArtistId;
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::track::Album;
+use std::collections::HashSet;
+use url::Url;
+
+/// The relationship between an artist and an album, used to filter `get_artist_albums`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlbumGroup {
+    Album,
+    Single,
+    Compilation,
+    AppearsOn,
+}
+
+impl AlbumGroup {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlbumGroup::Album => "album",
+            AlbumGroup::Single => "single",
+            AlbumGroup::Compilation => "compilation",
+            AlbumGroup::AppearsOn => "appears_on",
+        }
+    }
+}
+
+impl std::fmt::Display for AlbumGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/get-an-artists-albums
+    ///
+    /// Follows every page and drops albums repeated across markets, keeping the first by `id`.
+    /// An empty `include_groups` leaves the filter to Spotify's default of all groups.
+    pub async fn get_artist_albums(
+        &self,
+        artist_id: &ArtistId,
+        include_groups: &[AlbumGroup],
+        market: Option<&str>,
+    ) -> eyre::Result<Vec<Album>> {
+        let mut url = Url::parse(&format!(
+            "https://api.spotify.com/v1/artists/{}/albums",
+            artist_id
+        ))?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("limit", "50");
+            if !include_groups.is_empty() {
+                let groups = include_groups
+                    .iter()
+                    .map(AlbumGroup::as_str)
+                    .collect::<Vec<_>>()
+                    .join(",");
+                query.append_pair("include_groups", &groups);
+            }
+            if let Some(market) = market {
+                query.append_pair("market", market);
+            }
+        }
+
+        let albums: Vec<Album> = self.fetch_all_pages(url.as_str()).await?;
+        let mut seen = HashSet::new();
+        Ok(albums
+            .into_iter()
+            .filter(|album| seen.insert(album.id.clone()))
+            .collect())
+    }
+}
+
+/// https://developer.spotify.com/documentation/web-api/reference/get-an-artists-albums
+pub async fn get_artist_albums(
+    artist_id: ArtistId,
+    include_groups: &[AlbumGroup],
+    market: Option<&str>,
+    bearer: BearerToken,
+) -> eyre::Result<Vec<Album>> {
+    SpotifyClient::new(bearer)
+        .get_artist_albums(&artist_id, include_groups, market)
+        .await
+}
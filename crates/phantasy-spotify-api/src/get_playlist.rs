@@ -0,0 +1,68 @@
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::playlist::Playlist;
+use crate::playlist_id::PlaylistId;
+use eyre::eyre;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+/// The most playlists `get_playlists` requests at once, on top of any client-wide limit
+const PLAYLISTS_IN_FLIGHT: usize = 8;
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/get-playlist
+    ///
+    /// Only the metadata; fetch the items with `get_playlist_items`.
+    pub async fn get_playlist(&self, playlist_id: &PlaylistId) -> eyre::Result<Playlist> {
+        let url = format!(
+            "https://api.spotify.com/v1/playlists/{}?fields={}",
+            playlist_id,
+            "collaborative,description,external_urls,href,id,images,name,owner,public,\
+             snapshot_id,tracks(href,total),type,uri"
+        );
+        Ok(self.fetch(&url).await?)
+    }
+
+    /// Fetch several playlists, as Spotify has no batch endpoint for them.
+    ///
+    /// Requests run concurrently, at most 8 at a time and within any limit set by
+    /// `with_max_concurrent_requests`. The result lines up with `playlist_ids`, and one failed
+    /// playlist doesn't fail the others.
+    pub async fn get_playlists(&self, playlist_ids: &[PlaylistId]) -> Vec<eyre::Result<Playlist>> {
+        let mut results: Vec<Option<eyre::Result<Playlist>>> =
+            (0..playlist_ids.len()).map(|_| None).collect();
+        let mut pending = playlist_ids.iter().cloned().enumerate();
+        let mut in_flight = JoinSet::new();
+        loop {
+            while in_flight.len() < PLAYLISTS_IN_FLIGHT
+                && let Some((index, playlist_id)) = pending.next()
+            {
+                let client = self.clone();
+                in_flight.spawn(async move { (index, client.get_playlist(&playlist_id).await) });
+            }
+            match in_flight.join_next().await {
+                Some(Ok((index, result))) => results[index] = Some(result),
+                // The task panicked or was cancelled, losing its index; its slot reports it below
+                Some(Err(e)) => warn!("Playlist request task failed: {}", e),
+                None => break,
+            }
+        }
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(eyre!("Playlist request task failed"))))
+            .collect()
+    }
+}
+
+/// https://developer.spotify.com/documentation/web-api/reference/get-playlist
+pub async fn get_playlist(playlist_id: PlaylistId, bearer: BearerToken) -> eyre::Result<Playlist> {
+    SpotifyClient::new(bearer).get_playlist(&playlist_id).await
+}
+
+/// Fetch several playlists concurrently, see `SpotifyClient::get_playlists`.
+pub async fn get_playlists(
+    playlist_ids: &[PlaylistId],
+    bearer: BearerToken,
+) -> Vec<eyre::Result<Playlist>> {
+    SpotifyClient::new(bearer).get_playlists(playlist_ids).await
+}
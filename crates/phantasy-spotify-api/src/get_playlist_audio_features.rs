@@ -0,0 +1,44 @@
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::playlist_id::PlaylistId;
+use crate::track::Track;
+use crate::track_audio_features::TrackAudioFeatures;
+use crate::track_id::TrackId;
+use std::collections::HashMap;
+
+impl SpotifyClient {
+    /// Every track of a playlist alongside its audio features, in playlist order.
+    ///
+    /// Features are `None` for tracks Spotify has no analysis for.
+    pub async fn get_playlist_audio_features(
+        &self,
+        playlist_id: &PlaylistId,
+    ) -> eyre::Result<Vec<(Track, Option<TrackAudioFeatures>)>> {
+        let tracks = self.get_playlist_tracks(playlist_id).await?;
+        let track_ids: Vec<TrackId> = tracks.iter().map(|t| TrackId(t.id.clone())).collect();
+        let features_by_id: HashMap<String, TrackAudioFeatures> = self
+            .get_several_tracks_audio_features(&track_ids)
+            .await?
+            .into_iter()
+            .flatten()
+            .map(|features| (features.id.clone(), features))
+            .collect();
+        Ok(tracks
+            .into_iter()
+            .map(|track| {
+                let features = features_by_id.get(&track.id).cloned();
+                (track, features)
+            })
+            .collect())
+    }
+}
+
+/// Every track of a playlist alongside its audio features, in playlist order.
+pub async fn get_playlist_audio_features(
+    playlist_id: PlaylistId,
+    bearer: BearerToken,
+) -> eyre::Result<Vec<(Track, Option<TrackAudioFeatures>)>> {
+    SpotifyClient::new(bearer)
+        .get_playlist_audio_features(&playlist_id)
+        .await
+}
@@ -0,0 +1,47 @@
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::playlist::PlaylistItem;
+use crate::playlist::tracks;
+use crate::playlist_id::PlaylistId;
+use crate::track::Track;
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/get-playlists-tracks
+    ///
+    /// Follows every page, keeping when each item was added and whether it is a local file.
+    pub async fn get_playlist_items(
+        &self,
+        playlist_id: &PlaylistId,
+    ) -> eyre::Result<Vec<PlaylistItem>> {
+        let url = format!(
+            "https://api.spotify.com/v1/playlists/{}/tracks?limit=100",
+            playlist_id
+        );
+        self.fetch_all_pages(&url).await
+    }
+
+    /// Like `get_playlist_items`, keeping only the playable tracks.
+    pub async fn get_playlist_tracks(&self, playlist_id: &PlaylistId) -> eyre::Result<Vec<Track>> {
+        Ok(tracks(self.get_playlist_items(playlist_id).await?))
+    }
+}
+
+/// https://developer.spotify.com/documentation/web-api/reference/get-playlists-tracks
+pub async fn get_playlist_items(
+    playlist_id: PlaylistId,
+    bearer: BearerToken,
+) -> eyre::Result<Vec<PlaylistItem>> {
+    SpotifyClient::new(bearer)
+        .get_playlist_items(&playlist_id)
+        .await
+}
+
+/// https://developer.spotify.com/documentation/web-api/reference/get-playlists-tracks
+pub async fn get_playlist_tracks(
+    playlist_id: PlaylistId,
+    bearer: BearerToken,
+) -> eyre::Result<Vec<Track>> {
+    SpotifyClient::new(bearer)
+        .get_playlist_tracks(&playlist_id)
+        .await
+}
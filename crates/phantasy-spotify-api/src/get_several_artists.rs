@@ -0,0 +1,32 @@
+use crate::artist_id::ArtistId;
+use crate::client::SpotifyClient;
+use crate::full_artist::FullArtist;
+use serde::Deserialize;
+
+/// The most IDs the several-artists endpoint accepts per request
+const ARTISTS_BATCH_SIZE: usize = 50;
+
+#[derive(Deserialize)]
+struct SeveralArtists {
+    artists: Vec<Option<FullArtist>>,
+}
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/get-multiple-artists
+    ///
+    /// Batches `artist_ids` into requests of 50. The result lines up with `artist_ids`, holding
+    /// `None` for unknown artists.
+    pub async fn get_several_artists(
+        &self,
+        artist_ids: &[ArtistId],
+    ) -> eyre::Result<Vec<Option<FullArtist>>> {
+        let mut artists = Vec::with_capacity(artist_ids.len());
+        for batch in artist_ids.chunks(ARTISTS_BATCH_SIZE) {
+            let ids = batch.iter().map(|id| &**id).collect::<Vec<_>>().join(",");
+            let url = format!("https://api.spotify.com/v1/artists?ids={}", ids);
+            let page: SeveralArtists = self.fetch(&url).await?;
+            artists.extend(page.artists);
+        }
+        Ok(artists)
+    }
+}
@@ -1,10 +1,83 @@
 use crate::bearer_token::BearerToken;
-use crate::fetch::fetch;
+use crate::client::SpotifyClient;
+use crate::spotify_error::SpotifyError;
 use crate::track::Track;
 use crate::track_id::TrackId;
+use serde::Deserialize;
+use tracing::warn;
+use url::Url;
+
+/// The most IDs the several-tracks endpoint accepts per request
+const TRACKS_BATCH_SIZE: usize = 50;
+
+#[derive(Deserialize)]
+struct SeveralTracks {
+    tracks: Vec<Option<Track>>,
+}
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/get-several-tracks
+    ///
+    /// Batches `track_ids` into requests of 50. The result lines up with `track_ids`, holding
+    /// `None` for unknown tracks.
+    pub async fn get_several_tracks(
+        &self,
+        track_ids: &[TrackId],
+    ) -> eyre::Result<Vec<Option<Track>>> {
+        let mut tracks = Vec::with_capacity(track_ids.len());
+        for batch in track_ids.chunks(TRACKS_BATCH_SIZE) {
+            let ids = batch.iter().map(|id| &**id).collect::<Vec<_>>().join(",");
+            let url = format!("https://api.spotify.com/v1/tracks?ids={}", ids);
+            let page: SeveralTracks = self.fetch(&url).await?;
+            tracks.extend(page.tracks);
+        }
+        Ok(tracks)
+    }
+
+    /// https://developer.spotify.com/documentation/web-api/reference/get-track
+    ///
+    /// Relinks the track for `market`. With `with_market_fallback` enabled, a 404 is retried once
+    /// without the market, returning the global metadata with `is_playable: None`.
+    pub async fn get_track_in_market(
+        &self,
+        track_id: impl AsRef<str>,
+        market: &str,
+    ) -> eyre::Result<Track> {
+        let track_id = TrackId::parse(track_id)?;
+        let mut url = Url::parse(&format!("https://api.spotify.com/v1/tracks/{}", track_id))?;
+        url.query_pairs_mut().append_pair("market", market);
+
+        match self.fetch(url.as_str()).await {
+            Err(SpotifyError::NotFound { .. }) if self.market_fallback() => {
+                warn!(
+                    "Track {} not found in market {}, retrying without market",
+                    track_id, market
+                );
+                let mut track = self.get_track(&track_id).await?;
+                track.is_playable = None;
+                Ok(track)
+            }
+            res => Ok(res?),
+        }
+    }
+}
 
 /// https://developer.spotify.com/documentation/web-api/reference/get-track
-pub async fn get_track(track_id: TrackId, bearer: BearerToken) -> eyre::Result<Track> {
-    let url = format!("https://api.spotify.com/v1/tracks/{}", track_id);
-    fetch(&url, bearer).await
+pub async fn get_track(
+    track_id: impl AsRef<str>,
+    bearer: BearerToken,
+) -> Result<Track, SpotifyError> {
+    SpotifyClient::new(bearer).get_track(track_id).await
+}
+
+/// https://developer.spotify.com/documentation/web-api/reference/get-several-tracks
+///
+/// One request per 50 IDs instead of one per track; see `SpotifyClient::get_several_tracks`.
+pub async fn get_tracks(
+    track_ids: &[TrackId],
+    bearer: BearerToken,
+) -> eyre::Result<Vec<Option<Track>>> {
+    SpotifyClient::new(bearer)
+        .get_several_tracks(track_ids)
+        .await
 }
@@ -1,13 +1,45 @@
 use crate::bearer_token::BearerToken;
-use crate::fetch::fetch;
+use crate::client::SpotifyClient;
+use crate::spotify_error::SpotifyError;
 use crate::track_audio_features::TrackAudioFeatures;
 use crate::track_id::TrackId;
+use serde::Deserialize;
+
+/// The most IDs the several-tracks audio features endpoint accepts per request
+const AUDIO_FEATURES_BATCH_SIZE: usize = 100;
+
+#[derive(Deserialize)]
+struct SeveralAudioFeatures {
+    #[serde(rename = "audio_features")]
+    audio_features: Vec<Option<TrackAudioFeatures>>,
+}
+
+impl SpotifyClient {
+    /// https://developer.spotify.com/documentation/web-api/reference/get-several-audio-features
+    ///
+    /// Batches `track_ids` into requests of 100. The result lines up with `track_ids`, holding
+    /// `None` for tracks without features.
+    pub async fn get_several_tracks_audio_features(
+        &self,
+        track_ids: &[TrackId],
+    ) -> eyre::Result<Vec<Option<TrackAudioFeatures>>> {
+        let mut features = Vec::with_capacity(track_ids.len());
+        for batch in track_ids.chunks(AUDIO_FEATURES_BATCH_SIZE) {
+            let ids = batch.iter().map(|id| &**id).collect::<Vec<_>>().join(",");
+            let url = format!("https://api.spotify.com/v1/audio-features?ids={}", ids);
+            let page: SeveralAudioFeatures = self.fetch(&url).await?;
+            features.extend(page.audio_features);
+        }
+        Ok(features)
+    }
+}
 
 /// https://developer.spotify.com/documentation/web-api/reference/get-audio-features
 pub async fn get_track_audio_features(
-    track_id: TrackId,
+    track_id: impl AsRef<str>,
     bearer: BearerToken,
-) -> eyre::Result<TrackAudioFeatures> {
-    let url = format!("https://api.spotify.com/v1/audio-features/{}", track_id);
-    fetch(&url, bearer).await
+) -> Result<Option<TrackAudioFeatures>, SpotifyError> {
+    SpotifyClient::new(bearer)
+        .get_track_audio_features(track_id)
+        .await
 }
@@ -1,12 +1,50 @@
 #![feature(async_fn_track_caller)]
+#[cfg(not(any(
+    feature = "native-tls",
+    feature = "rustls-tls",
+    feature = "rustls-tls-native-roots"
+)))]
+compile_error!(
+    "phantasy-spotify-api needs a TLS backend to reach the Spotify API: enable one of the \
+     `native-tls`, `rustls-tls`, or `rustls-tls-native-roots` features"
+);
 pub mod bearer_token;
 pub mod get_track_audio_features;
 pub mod track_audio_features;
+pub mod audio_analysis;
+pub mod nearest_by_features;
+pub mod musical_key;
 pub mod track_id;
 pub mod uri;
 pub mod get_track;
 pub mod track;
+pub mod release_date;
 pub mod fetch;
+pub mod client;
+pub mod artist_id;
+pub mod album_id;
+pub mod get_album;
+pub mod paging;
+pub mod get_artist_albums;
+pub mod dedup_by_isrc;
+pub mod playlist_id;
+pub mod playlist;
+pub mod get_playlist;
+pub mod get_playlist_tracks;
+pub mod get_playlist_audio_features;
+pub mod full_artist;
+pub mod get_several_artists;
+pub mod enrich_with_genres;
+pub mod are_tracks_saved;
+pub mod phantasy;
+pub mod retry_after;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod spotify_api_error;
+pub mod spotify_error;
 pub mod auth {
+    pub mod client_credentials;
     pub mod pkce;
+    pub mod scope;
+    pub(crate) mod token_refresh;
 }
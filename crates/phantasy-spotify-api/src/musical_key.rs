@@ -0,0 +1,89 @@
+/// One of the twelve pitch classes, in Spotify's key order (0 = C, 1 = C♯/D♭, ...).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PitchClass {
+    C,
+    Cs,
+    D,
+    Ds,
+    E,
+    F,
+    Fs,
+    G,
+    Gs,
+    A,
+    As,
+    B,
+}
+
+impl PitchClass {
+    pub const ALL: [PitchClass; 12] = [
+        PitchClass::C,
+        PitchClass::Cs,
+        PitchClass::D,
+        PitchClass::Ds,
+        PitchClass::E,
+        PitchClass::F,
+        PitchClass::Fs,
+        PitchClass::G,
+        PitchClass::Gs,
+        PitchClass::A,
+        PitchClass::As,
+        PitchClass::B,
+    ];
+
+    /// Map Spotify's 0-11 key to a pitch class, `None` for -1 (no key detected) or anything else.
+    pub fn from_key(key: i64) -> Option<PitchClass> {
+        usize::try_from(key)
+            .ok()
+            .and_then(|key| PitchClass::ALL.get(key).copied())
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PitchClass::C => "C",
+            PitchClass::Cs => "C#",
+            PitchClass::D => "D",
+            PitchClass::Ds => "D#",
+            PitchClass::E => "E",
+            PitchClass::F => "F",
+            PitchClass::Fs => "F#",
+            PitchClass::G => "G",
+            PitchClass::Gs => "G#",
+            PitchClass::A => "A",
+            PitchClass::As => "A#",
+            PitchClass::B => "B",
+        }
+    }
+}
+
+impl std::fmt::Display for PitchClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The modality of a track, Spotify's `mode` of 1 for major and 0 for minor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+impl Mode {
+    pub fn from_mode(mode: i64) -> Mode {
+        if mode == 1 { Mode::Major } else { Mode::Minor }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        }
+    }
+}
+
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,160 @@
+use crate::track_audio_features::TrackAudioFeatures;
+use crate::track_id::TrackId;
+
+/// A numeric audio feature that can take part in a similarity distance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioFeature {
+    Acousticness,
+    Danceability,
+    Energy,
+    Instrumentalness,
+    Liveness,
+    Loudness,
+    Speechiness,
+    Tempo,
+    Valence,
+}
+
+impl AudioFeature {
+    /// The features `nearest_by_features` compares: how a track feels rather than how it was
+    /// recorded.
+    pub const DEFAULT: [AudioFeature; 4] = [
+        AudioFeature::Energy,
+        AudioFeature::Danceability,
+        AudioFeature::Valence,
+        AudioFeature::Tempo,
+    ];
+
+    pub fn value(self, features: &TrackAudioFeatures) -> f64 {
+        match self {
+            AudioFeature::Acousticness => features.acousticness,
+            AudioFeature::Danceability => features.danceability,
+            AudioFeature::Energy => features.energy,
+            AudioFeature::Instrumentalness => features.instrumentalness,
+            AudioFeature::Liveness => features.liveness,
+            AudioFeature::Loudness => features.loudness,
+            AudioFeature::Speechiness => features.speechiness,
+            AudioFeature::Tempo => features.tempo,
+            AudioFeature::Valence => features.valence,
+        }
+    }
+
+    /// The span of values Spotify reports, which differences are divided by so every feature
+    /// weighs the same: 0–250 BPM for tempo, -60–0 dB for loudness, and 0–1 for the rest.
+    pub fn range(self) -> f64 {
+        match self {
+            AudioFeature::Tempo => 250.0,
+            AudioFeature::Loudness => 60.0,
+            _ => 1.0,
+        }
+    }
+}
+
+/// The `k` candidates most like `target` by [`AudioFeature::DEFAULT`], closest first, with
+/// their distances.
+pub fn nearest_by_features(
+    target: &TrackAudioFeatures,
+    candidates: &[(TrackId, TrackAudioFeatures)],
+    k: usize,
+) -> Vec<(TrackId, f64)> {
+    nearest_by_chosen_features(target, candidates, k, &AudioFeature::DEFAULT)
+}
+
+/// The `k` candidates most like `target` by the Euclidean distance over `features`, each
+/// divided by its [`AudioFeature::range`], closest first.
+///
+/// Candidates Spotify never analysed are skipped, since their zeroed features would read as
+/// close to any quiet, slow track.
+pub fn nearest_by_chosen_features(
+    target: &TrackAudioFeatures,
+    candidates: &[(TrackId, TrackAudioFeatures)],
+    k: usize,
+    features: &[AudioFeature],
+) -> Vec<(TrackId, f64)> {
+    let mut nearest: Vec<(TrackId, f64)> = candidates
+        .iter()
+        .filter(|(_, candidate)| candidate.is_analyzed())
+        .map(|(id, candidate)| {
+            let distance = features
+                .iter()
+                .map(|feature| (feature.value(target) - feature.value(candidate)) / feature.range())
+                .map(|d| d * d)
+                .sum::<f64>()
+                .sqrt();
+            (id.clone(), distance)
+        })
+        .collect();
+    nearest.sort_by(|a, b| a.1.total_cmp(&b.1));
+    nearest.truncate(k);
+    nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(energy: f64, danceability: f64, valence: f64, tempo: f64) -> TrackAudioFeatures {
+        TrackAudioFeatures {
+            energy,
+            danceability,
+            valence,
+            tempo,
+            loudness: -10.0,
+            ..TrackAudioFeatures::default()
+        }
+    }
+
+    fn candidates() -> Vec<(TrackId, TrackAudioFeatures)> {
+        vec![
+            // sqrt(0.3² + 0.4²) = 0.5
+            (TrackId("a".to_string()), features(0.8, 0.5, 0.1, 120.0)),
+            // 25 / 250 = 0.1
+            (TrackId("b".to_string()), features(0.5, 0.5, 0.5, 145.0)),
+            // sqrt(0.1² + 0.2² + 0.2² + (50 / 250)²) = sqrt(0.13)
+            (TrackId("c".to_string()), features(0.6, 0.7, 0.7, 170.0)),
+            // Never analysed, so skipped however close its zeros are
+            (TrackId("d".to_string()), TrackAudioFeatures::default()),
+        ]
+    }
+
+    fn assert_nearest(actual: Vec<(TrackId, f64)>, expected: &[(&str, f64)]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?}");
+        for ((id, distance), (expected_id, expected_distance)) in actual.iter().zip(expected) {
+            assert_eq!(id.0, *expected_id);
+            assert!(
+                (distance - expected_distance).abs() < 1e-9,
+                "{id}: {distance}"
+            );
+        }
+    }
+
+    #[test]
+    fn default_features_by_hand() {
+        let target = features(0.5, 0.5, 0.5, 120.0);
+        assert_nearest(
+            nearest_by_features(&target, &candidates(), 2),
+            &[("b", 0.1), ("c", 0.13f64.sqrt())],
+        );
+        assert_nearest(
+            nearest_by_features(&target, &candidates(), 10),
+            &[("b", 0.1), ("c", 0.13f64.sqrt()), ("a", 0.5)],
+        );
+    }
+
+    #[test]
+    fn chosen_features_by_hand() {
+        let target = TrackAudioFeatures {
+            loudness: -40.0,
+            ..features(0.5, 0.5, 0.5, 120.0)
+        };
+        // Only loudness differs: 30 dB of a 60 dB range, the same for every candidate
+        let nearest =
+            nearest_by_chosen_features(&target, &candidates(), 3, &[AudioFeature::Loudness]);
+        assert!(nearest.iter().all(|(_, distance)| *distance == 0.5));
+        // Energy alone: 0.0, 0.1, 0.3
+        assert_nearest(
+            nearest_by_chosen_features(&target, &candidates(), 3, &[AudioFeature::Energy]),
+            &[("b", 0.0), ("c", 0.1), ("a", 0.3)],
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::client::SpotifyClient;
+use futures_core::Stream;
+use serde::Deserialize;
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+
+/// https://developer.spotify.com/documentation/web-api/concepts/api-calls#pagination
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Paging<T> {
+    pub href: String,
+    pub items: Vec<T>,
+    pub limit: i64,
+    pub next: Option<String>,
+    pub offset: i64,
+    pub previous: Option<String>,
+    pub total: i64,
+}
+
+type PageFuture<T> = Pin<Box<dyn Future<Output = eyre::Result<Paging<T>>> + Send>>;
+
+/// The pages of a paginated endpoint, each fetched only once the previous one is consumed.
+///
+/// Requests go through the client, so they count against its concurrency limit. The stream
+/// ends after the last page or the first error. Dropping it stops following `next`.
+pub struct PageStream<T> {
+    client: SpotifyClient,
+    next: Option<String>,
+    in_flight: Option<PageFuture<T>>,
+}
+
+impl<T> PageStream<T>
+where
+    T: serde::de::DeserializeOwned + Send + 'static,
+{
+    /// The next page, or `None` once every page has been fetched. For callers without stream
+    /// combinators at hand.
+    pub async fn next(&mut self) -> Option<eyre::Result<Paging<T>>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<T> Stream for PageStream<T>
+where
+    T: serde::de::DeserializeOwned + Send + 'static,
+{
+    type Item = eyre::Result<Paging<T>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let in_flight = match &mut this.in_flight {
+            Some(in_flight) => in_flight,
+            None => {
+                let Some(url) = this.next.take() else {
+                    return Poll::Ready(None);
+                };
+                let client = this.client.clone();
+                this.in_flight
+                    .insert(Box::pin(async move { Ok(client.fetch(&url).await?) }))
+            }
+        };
+        let Poll::Ready(page) = in_flight.as_mut().poll(cx) else {
+            return Poll::Pending;
+        };
+        this.in_flight = None;
+        if let Ok(page) = &page {
+            this.next = page.next.clone();
+        }
+        Poll::Ready(Some(page))
+    }
+}
+
+/// Stream the pages of `first_url`, following `next` links lazily, so a long playlist can be
+/// processed a page at a time or abandoned early.
+pub fn paginate_stream<T>(client: &SpotifyClient, first_url: &str) -> PageStream<T>
+where
+    T: serde::de::DeserializeOwned + Send + 'static,
+{
+    PageStream {
+        client: client.clone(),
+        next: Some(first_url.to_string()),
+        in_flight: None,
+    }
+}
+
+impl SpotifyClient {
+    /// Fetch `first_url` and follow `next` links until exhausted, concatenating the items.
+    pub async fn fetch_all_pages<T>(&self, first_url: &str) -> eyre::Result<Vec<T>>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        let mut pages = paginate_stream::<T>(self, first_url);
+        let mut items = Vec::new();
+        while let Some(page) = pages.next().await {
+            items.extend(page?.items);
+        }
+        Ok(items)
+    }
+}
@@ -0,0 +1,71 @@
+use crate::auth::pkce::PkceConfig;
+use crate::auth::pkce::authorize_in_browser;
+use crate::auth::pkce::get_saved_token;
+use crate::auth::pkce::save_token;
+use crate::auth::scope::DEFAULT_SCOPES;
+use crate::bearer_token::BearerToken;
+use crate::client::SpotifyClient;
+use crate::spotify_error::SpotifyError;
+use serde::de::IgnoredAny;
+use std::time::Duration;
+use tracing::info;
+
+/// The front door for quick scripts, which just want a working client:
+/// `let client = Phantasy::connect().await?;` then `client.get_track(id).await?`.
+pub struct Phantasy;
+
+impl Phantasy {
+    /// Load `.env` if there is one, then return a client with a working token.
+    ///
+    /// The saved token is used while Spotify still accepts it. Otherwise the user signs in
+    /// through the browser as in `get_bearer_token_via_pkce`, the new token is saved, and the
+    /// client refreshes it by itself before it expires, saving each renewed token too. Needs
+    /// `SPOTIFY_CLIENT_ID` and `SPOTIFY_REDIRECT_URI` whenever it has to sign in.
+    pub async fn connect() -> eyre::Result<SpotifyClient> {
+        if let Err(e) = dotenvy::dotenv()
+            && !e.not_found()
+        {
+            return Err(e.into());
+        }
+
+        // A token signed in for other scopes may lack the ones a new sign-in asks for
+        let saved = get_saved_token().await?;
+        if let Some(bearer) = saved.filter(|bearer| bearer.grants(DEFAULT_SCOPES)) {
+            let mut client = SpotifyClient::new(bearer.clone());
+            // A saved token from the PKCE flow can keep being refreshed for this session
+            if let (Some(refresh_token), Some(expires_in), Ok(config)) = (
+                bearer.refresh_token.clone(),
+                bearer.expires_in(),
+                PkceConfig::from_env(),
+            ) {
+                client = client.with_saved_token_refresh(refresh_token, expires_in, config);
+            }
+            // Older token files carry no expiry, and tokens can be revoked, so ask Spotify
+            // whether this one still works
+            match client
+                .fetch::<IgnoredAny>("https://api.spotify.com/v1/me")
+                .await
+            {
+                Ok(_) => return Ok(client),
+                Err(SpotifyError::Unauthorized { .. }) => {
+                    info!("Saved token has expired, signing in again")
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let config = PkceConfig::from_env()?;
+        let token = authorize_in_browser(&config).await?;
+        let bearer = BearerToken::from_token_response(&token);
+        save_token(&bearer).await?;
+        let client = SpotifyClient::new(bearer);
+        Ok(match token.refresh_token {
+            Some(refresh_token) => client.with_saved_token_refresh(
+                refresh_token,
+                Duration::from_secs(token.expires_in),
+                config,
+            ),
+            None => client,
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use crate::track::ExternalUrls;
+use crate::track::Track;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+
+/// A playlist's metadata, without its items.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    pub collaborative: bool,
+    pub description: Option<String>,
+    #[serde(rename = "external_urls")]
+    pub external_urls: ExternalUrls,
+    pub href: String,
+    pub id: String,
+    /// Null rather than empty for some playlists without artwork
+    #[serde(default)]
+    pub images: Option<Vec<PlaylistImage>>,
+    pub name: String,
+    pub owner: PlaylistOwner,
+    /// `None` when the playlist's status isn't relevant, e.g. for other users' playlists
+    pub public: Option<bool>,
+    #[serde(rename = "snapshot_id")]
+    pub snapshot_id: String,
+    pub tracks: PlaylistTracksRef,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub uri: String,
+}
+
+/// Playlist artwork, which unlike album art may not know its size.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistImage {
+    pub url: String,
+    pub height: Option<i64>,
+    pub width: Option<i64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistOwner {
+    #[serde(rename = "display_name")]
+    pub display_name: Option<String>,
+    #[serde(rename = "external_urls")]
+    pub external_urls: ExternalUrls,
+    pub href: String,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub uri: String,
+}
+
+/// Where to fetch a playlist's items, and how many there are.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistTracksRef {
+    pub href: String,
+    pub total: i64,
+}
+
+/// One entry of a playlist's track listing.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistItem {
+    #[serde(rename = "added_at")]
+    pub added_at: Option<String>,
+    #[serde(rename = "is_local")]
+    pub is_local: bool,
+    /// `None` when the track was removed, and for local files and podcast episodes, which
+    /// don't fit the `Track` model
+    #[serde(deserialize_with = "track_or_none")]
+    pub track: Option<Track>,
+}
+
+/// The tracks of `items`, skipping entries without one.
+pub fn tracks(items: impl IntoIterator<Item = PlaylistItem>) -> Vec<Track> {
+    items.into_iter().filter_map(|item| item.track).collect()
+}
+
+/// Deserialize an item's `track`, as `None` if it was removed, is a local file, or is something
+/// other than a track, such as a podcast episode. A track that doesn't fit the model is an
+/// error, so a change in Spotify's responses surfaces instead of silently dropping tracks.
+fn track_or_none<'de, D>(deserializer: D) -> Result<Option<Track>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(value) = Option::<serde_json::Value>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let is_local = value.get("is_local").and_then(|v| v.as_bool()) == Some(true);
+    let is_track = value.get("type").and_then(|v| v.as_str()) == Some("track");
+    if is_local || !is_track {
+        return Ok(None);
+    }
+    serde_json::from_value(value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(track: serde_json::Value) -> serde_json::Value {
+        json!({ "added_at": "2024-01-01T00:00:00Z", "is_local": false, "track": track })
+    }
+
+    fn track() -> serde_json::Value {
+        serde_json::to_value(Track {
+            id: "track".to_string(),
+            type_field: "track".to_string(),
+            ..Track::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn removed_episode_and_local_items_have_no_track() {
+        let mut local = track();
+        local["is_local"] = json!(true);
+        local["id"] = json!(null);
+        let items: Vec<PlaylistItem> = serde_json::from_value(json!([
+            item(track()),
+            item(json!(null)),
+            item(json!({ "type": "episode", "id": "episode", "name": "Episode" })),
+            { "added_at": null, "is_local": true, "track": local },
+        ]))
+        .unwrap();
+        assert_eq!(items.len(), 4);
+        let tracks = tracks(items);
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].id, "track");
+    }
+
+    #[test]
+    fn malformed_track_is_an_error() {
+        let mut malformed = track();
+        malformed["duration_ms"] = json!("three minutes");
+        let error = serde_json::from_value::<PlaylistItem>(item(malformed)).unwrap_err();
+        assert!(error.to_string().contains("three minutes"), "{error}");
+    }
+}
@@ -0,0 +1,20 @@
+use std::ops::Deref;
+
+#[derive(Debug, Clone)]
+pub struct PlaylistId(pub String);
+impl std::fmt::Display for PlaylistId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl Deref for PlaylistId {
+    type Target = str;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl AsRef<str> for PlaylistId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
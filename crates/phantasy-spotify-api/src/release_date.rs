@@ -0,0 +1,123 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// How much of a release date Spotify knows, from `release_date_precision`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseDatePrecision {
+    Year,
+    Month,
+    Day,
+}
+
+impl ReleaseDatePrecision {
+    /// Map Spotify's `"year"`, `"month"`, or `"day"` to a precision, `None` for anything else.
+    pub fn from_spotify(precision: &str) -> Option<ReleaseDatePrecision> {
+        match precision {
+            "year" => Some(ReleaseDatePrecision::Year),
+            "month" => Some(ReleaseDatePrecision::Month),
+            "day" => Some(ReleaseDatePrecision::Day),
+            _ => None,
+        }
+    }
+}
+
+/// An album's release date, parsed from Spotify's `"1981"`, `"1981-12"`, or `"1981-12-15"`.
+///
+/// Parts finer than `precision` aren't known and are set to 1, so a date known only to the year
+/// compares as January 1 of it. Dates order chronologically, and a less precise date sorts
+/// before a more precise one on the same day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ReleaseDate {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+    pub precision: ReleaseDatePrecision,
+}
+
+impl ReleaseDate {
+    /// A date known only to `year`.
+    pub fn from_year(year: i32) -> ReleaseDate {
+        ReleaseDate {
+            year,
+            month: 1,
+            day: 1,
+            precision: ReleaseDatePrecision::Year,
+        }
+    }
+
+    /// Parse `date` as Spotify gives it alongside `precision`. When `precision` is missing or
+    /// unrecognized it is taken from how many parts `date` has. Returns `None` for an empty or
+    /// malformed date, an out of range month or day, and the year 0 that Spotify uses for
+    /// unknown dates.
+    pub fn parse(date: &str, precision: &str) -> Option<ReleaseDate> {
+        let mut parts = date.trim().split('-');
+        let year: i32 = parts.next()?.parse().ok()?;
+        let month: Option<u8> = parts.next().map(str::parse).transpose().ok()?;
+        let day: Option<u8> = parts.next().map(str::parse).transpose().ok()?;
+        if parts.next().is_some() || year == 0 {
+            return None;
+        }
+        let present = match (month, day) {
+            (None, _) => ReleaseDatePrecision::Year,
+            (Some(_), None) => ReleaseDatePrecision::Month,
+            (Some(_), Some(_)) => ReleaseDatePrecision::Day,
+        };
+        // Trust the parts over a precision claiming more than the date has
+        let precision = ReleaseDatePrecision::from_spotify(precision)
+            .unwrap_or(present)
+            .min(present);
+        let month = match precision {
+            ReleaseDatePrecision::Year => 1,
+            _ => month?,
+        };
+        let day = match precision {
+            ReleaseDatePrecision::Day => day?,
+            _ => 1,
+        };
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+        Some(ReleaseDate {
+            year,
+            month,
+            day,
+            precision,
+        })
+    }
+
+    /// Whether this date is earlier than the start of `other`, e.g. any time before 1990 for
+    /// `other` known only to the year 1990.
+    pub fn is_before(&self, other: &ReleaseDate) -> bool {
+        (self.year, self.month, self.day) < (other.year, other.month, other.day)
+    }
+
+    /// Whether this date is later than the whole of `other`, e.g. 1991 or later for `other`
+    /// known only to the year 1990.
+    pub fn is_after(&self, other: &ReleaseDate) -> bool {
+        let truncate = |date: &ReleaseDate| match other.precision {
+            ReleaseDatePrecision::Year => (date.year, 0, 0),
+            ReleaseDatePrecision::Month => (date.year, date.month, 0),
+            ReleaseDatePrecision::Day => (date.year, date.month, date.day),
+        };
+        truncate(self) > truncate(other)
+    }
+}
+
+impl From<i32> for ReleaseDate {
+    fn from(year: i32) -> Self {
+        ReleaseDate::from_year(year)
+    }
+}
+
+impl std::fmt::Display for ReleaseDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.precision {
+            ReleaseDatePrecision::Year => write!(f, "{:04}", self.year),
+            ReleaseDatePrecision::Month => write!(f, "{:04}-{:02}", self.year, self.month),
+            ReleaseDatePrecision::Day => {
+                write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use reqwest::header::HeaderMap;
+use reqwest::header::RETRY_AFTER;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// How long a 429 response asks us to wait before retrying, as of `now`.
+///
+/// `Retry-After` is either delta-seconds (`120`) or an HTTP-date
+/// (`Sun, 06 Nov 1994 08:49:37 GMT`), the latter usually from a CDN in front of the API. A date
+/// in the past means no wait. `None` when the header is missing or unparseable, in which case
+/// `fetch` falls back to its usual backoff.
+pub fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, now)
+}
+
+/// Parse a `Retry-After` value in either form, `None` if it is neither.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::time::UNIX_EPOCH;
+
+    /// Sun, 06 Nov 1994 08:49:37 GMT
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(784_111_777)
+    }
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn delta_seconds() {
+        assert_eq!(
+            retry_after(&headers("120"), now()),
+            Some(Duration::from_secs(120))
+        );
+    }
+
+    #[test]
+    fn http_date() {
+        assert_eq!(
+            retry_after(&headers("Sun, 06 Nov 1994 08:50:07 GMT"), now()),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_after(&headers("Sun, 06 Nov 1994 08:00:00 GMT"), now()),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn missing_header() {
+        assert_eq!(retry_after(&HeaderMap::new(), now()), None);
+    }
+
+    #[test]
+    fn malformed_header() {
+        assert_eq!(retry_after(&headers("soon"), now()), None);
+        assert_eq!(retry_after(&headers("-5"), now()), None);
+        assert_eq!(
+            retry_after(&headers("Sun, 32 Nov 1994 08:49:37 GMT"), now()),
+            None
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use crate::full_artist::FullArtist;
+use crate::track::Album;
+use crate::track::Artist;
+use crate::track::Track;
+use crate::track_audio_features::TrackAudioFeatures;
+use schemars::JsonSchema;
+use std::fs;
+use std::path::Path;
+
+/// Write the JSON Schema of `T` to `path`, pretty-printed.
+pub fn write_schema<T: JsonSchema>(path: &Path) -> eyre::Result<()> {
+    let schema = schemars::schema_for!(T);
+    fs::write(path, serde_json::to_string_pretty(&schema)?)?;
+    Ok(())
+}
+
+/// Write the schema of every response type consumers usually need into `dir`, one
+/// `<Type>.schema.json` file each. Types they refer to, such as `Image`, are inlined as
+/// definitions.
+pub fn write_schemas(dir: &Path) -> eyre::Result<()> {
+    fs::create_dir_all(dir)?;
+    write_schema::<Track>(&dir.join("Track.schema.json"))?;
+    write_schema::<Album>(&dir.join("Album.schema.json"))?;
+    write_schema::<Artist>(&dir.join("Artist.schema.json"))?;
+    write_schema::<FullArtist>(&dir.join("FullArtist.schema.json"))?;
+    write_schema::<TrackAudioFeatures>(&dir.join("TrackAudioFeatures.schema.json"))?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::fmt;
+
+/// How much of an error response body is kept, unless configured otherwise
+pub const DEFAULT_ERROR_BODY_LIMIT: usize = 4096;
+
+/// A non-success response from the API, with the reason Spotify gave for it.
+///
+/// Spotify answers errors with `{"error": {"status": 401, "message": "The access token expired"}}`.
+/// When the body isn't that envelope, as with errors from a proxy in front of the API, the
+/// message is the body itself, cut short at the configured limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotifyApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorObject,
+}
+
+#[derive(Deserialize)]
+struct ErrorObject {
+    message: String,
+}
+
+impl SpotifyApiError {
+    /// Read the reason for `status` out of `body`, of which at most `limit` bytes were captured
+    /// and `truncated` says whether there was more.
+    pub fn from_body(status: StatusCode, body: &[u8], truncated: bool) -> Self {
+        let message = match serde_json::from_slice::<ErrorEnvelope>(body) {
+            Ok(envelope) if !truncated => envelope.error.message,
+            _ => {
+                let body = String::from_utf8_lossy(body);
+                let body = body.trim();
+                if truncated {
+                    format!("{}…", body)
+                } else {
+                    body.to_string()
+                }
+            }
+        };
+        Self { status, message }
+    }
+}
+
+impl fmt::Display for SpotifyApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "Spotify API returned {}", self.status)
+        } else {
+            write!(f, "Spotify API returned {}: {}", self.status, self.message)
+        }
+    }
+}
+
+impl std::error::Error for SpotifyApiError {}
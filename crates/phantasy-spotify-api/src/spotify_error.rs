@@ -0,0 +1,149 @@
+use crate::retry_after::retry_after;
+use crate::spotify_api_error::SpotifyApiError;
+use crate::track_id::ParseIdError;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// Why a request to the Spotify API failed, split by what a caller would do about it: sign in
+/// again on `Unauthorized`, wait on `RateLimited`, fix the ID on `NotFound`.
+///
+/// Returned by `fetch` and the endpoints built directly on it. Endpoints that still return
+/// `eyre::Result` carry it inside the report, where `downcast_ref::<SpotifyError>` finds it.
+#[derive(Debug)]
+pub enum SpotifyError {
+    /// 401: the access token expired or was revoked
+    Unauthorized { message: String },
+    /// 403: the token lacks a scope, or the endpoint is closed to this app
+    Forbidden { message: String },
+    /// 404: no such track, album, or other resource, at least in the requested market
+    NotFound { message: String },
+    /// 429: too many requests; wait `retry_after`, when Spotify said how long, before retrying
+    RateLimited { retry_after: Option<Duration> },
+    /// 400: Spotify rejected the request itself, e.g. a malformed query
+    BadRequest { message: String },
+    /// Any other non-success response, such as a 5xx
+    Status(SpotifyApiError),
+    /// The response arrived but didn't have the expected shape
+    Deserialize {
+        body: String,
+        /// Which field diverged from the model, when it could be pinpointed
+        divergence: Option<String>,
+        source: serde_json::Error,
+    },
+    /// The request never got an answer, e.g. a connection failure or timeout
+    Http(reqwest::Error),
+    /// Failures before any request was sent, such as an invalid ID or a failed token refresh
+    Other(eyre::Report),
+}
+
+impl SpotifyError {
+    /// The error for a non-success response with `status`, of whose body at most `limit` bytes
+    /// were captured and `truncated` says whether there was more.
+    pub fn from_response(
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+        truncated: bool,
+    ) -> Self {
+        let api_error = SpotifyApiError::from_body(status, body, truncated);
+        match status {
+            StatusCode::UNAUTHORIZED => SpotifyError::Unauthorized {
+                message: api_error.message,
+            },
+            StatusCode::FORBIDDEN => SpotifyError::Forbidden {
+                message: api_error.message,
+            },
+            StatusCode::NOT_FOUND => SpotifyError::NotFound {
+                message: api_error.message,
+            },
+            StatusCode::TOO_MANY_REQUESTS => SpotifyError::RateLimited {
+                retry_after: retry_after(headers, SystemTime::now()),
+            },
+            StatusCode::BAD_REQUEST => SpotifyError::BadRequest {
+                message: api_error.message,
+            },
+            _ => SpotifyError::Status(api_error),
+        }
+    }
+
+    /// The HTTP status Spotify answered with, `None` when there was no error response.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            SpotifyError::Unauthorized { .. } => Some(StatusCode::UNAUTHORIZED),
+            SpotifyError::Forbidden { .. } => Some(StatusCode::FORBIDDEN),
+            SpotifyError::NotFound { .. } => Some(StatusCode::NOT_FOUND),
+            SpotifyError::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            SpotifyError::BadRequest { .. } => Some(StatusCode::BAD_REQUEST),
+            SpotifyError::Status(e) => Some(e.status),
+            SpotifyError::Deserialize { .. } | SpotifyError::Http(_) | SpotifyError::Other(_) => {
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for SpotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpotifyError::Unauthorized { message }
+            | SpotifyError::Forbidden { message }
+            | SpotifyError::NotFound { message }
+            | SpotifyError::BadRequest { message } => {
+                let status = self.status().unwrap_or_default();
+                if message.is_empty() {
+                    write!(f, "Spotify API returned {}", status)
+                } else {
+                    write!(f, "Spotify API returned {}: {}", status, message)
+                }
+            }
+            SpotifyError::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(f, "Rate limited by Spotify, retry after {:?}", retry_after),
+            SpotifyError::RateLimited { retry_after: None } => write!(f, "Rate limited by Spotify"),
+            SpotifyError::Status(e) => e.fmt(f),
+            SpotifyError::Deserialize {
+                body, divergence, ..
+            } => match divergence {
+                Some(divergence) => write!(f, "Failed to deserialize ({}):\n{}", divergence, body),
+                None => write!(f, "Failed to deserialize:\n{}", body),
+            },
+            SpotifyError::Http(e) => e.fmt(f),
+            SpotifyError::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for SpotifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            // The wrapping variants display their error, so pass on its source rather than
+            // repeating it in the chain
+            SpotifyError::Deserialize { source, .. } => Some(source),
+            SpotifyError::Http(e) => e.source(),
+            SpotifyError::Other(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for SpotifyError {
+    fn from(e: reqwest::Error) -> Self {
+        SpotifyError::Http(e)
+    }
+}
+
+impl From<eyre::Report> for SpotifyError {
+    fn from(e: eyre::Report) -> Self {
+        SpotifyError::Other(e)
+    }
+}
+
+impl From<ParseIdError> for SpotifyError {
+    fn from(e: ParseIdError) -> Self {
+        SpotifyError::Other(e.into())
+    }
+}
@@ -1,19 +1,24 @@
+use crate::paging::Paging;
+use crate::release_date::ReleaseDate;
 use serde::Deserialize;
 use serde::Serialize;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Track {
+    /// Left out of the simplified tracks in an album's `tracks`
+    #[serde(default)]
     pub album: Album,
     pub artists: Vec<Artist>,
-    #[serde(rename = "available_markets")]
+    #[serde(rename = "available_markets", default)]
     pub available_markets: Vec<String>,
     #[serde(rename = "disc_number")]
     pub disc_number: i64,
     #[serde(rename = "duration_ms")]
     pub duration_ms: i64,
     pub explicit: bool,
-    #[serde(rename = "external_ids")]
+    #[serde(rename = "external_ids", default)]
     pub external_ids: ExternalIds,
     #[serde(rename = "external_urls")]
     pub external_urls: ExternalUrls,
@@ -23,6 +28,7 @@ pub struct Track {
     pub linked_from: Option<LinkedFrom>,
     pub restrictions: Option<Restrictions>,
     pub name: String,
+    #[serde(default)]
     pub popularity: i64,
     #[serde(rename = "preview_url")]
     pub preview_url: Option<String>,
@@ -35,14 +41,68 @@ pub struct Track {
     pub is_local: bool,
 }
 
+impl Track {
+    /// Combine two responses for the same track, e.g. a simplified track from a playlist or album
+    /// with the full track from `get_track`, without refetching.
+    ///
+    /// `other` takes precedence wherever it is populated, and `self` fills the gaps. A field
+    /// counts as unpopulated when it is an empty string or list, `None`, or a zero count, which
+    /// is how the simplified objects leave out what they don't carry. `available_markets` is the
+    /// union of both, and `explicit`/`is_local` are set if either side says so.
+    pub fn merge(self, other: Track) -> Track {
+        Track {
+            album: self.album.merge(other.album),
+            artists: prefer_vec(self.artists, other.artists),
+            available_markets: union(self.available_markets, other.available_markets),
+            disc_number: prefer_nonzero(self.disc_number, other.disc_number),
+            duration_ms: prefer_nonzero(self.duration_ms, other.duration_ms),
+            explicit: self.explicit || other.explicit,
+            external_ids: self.external_ids.merge(other.external_ids),
+            external_urls: self.external_urls.merge(other.external_urls),
+            href: prefer_string(self.href, other.href),
+            id: prefer_string(self.id, other.id),
+            is_playable: other.is_playable.or(self.is_playable),
+            linked_from: other.linked_from.or(self.linked_from),
+            restrictions: other.restrictions.or(self.restrictions),
+            name: prefer_string(self.name, other.name),
+            popularity: prefer_nonzero(self.popularity, other.popularity),
+            preview_url: other.preview_url.or(self.preview_url),
+            track_number: prefer_nonzero(self.track_number, other.track_number),
+            type_field: prefer_string(self.type_field, other.type_field),
+            uri: prefer_string(self.uri, other.uri),
+            is_local: self.is_local || other.is_local,
+        }
+    }
+
+    /// The release date of the track's album, see [`Album::parsed_release_date`].
+    pub fn parsed_release_date(&self) -> Option<ReleaseDate> {
+        self.album.parsed_release_date()
+    }
+
+    pub fn release_year(&self) -> Option<i32> {
+        self.album.release_year()
+    }
+
+    /// See [`Album::released_before`].
+    pub fn released_before(&self, date: impl Into<ReleaseDate>) -> bool {
+        self.album.released_before(date)
+    }
+
+    /// See [`Album::released_after`].
+    pub fn released_after(&self, date: impl Into<ReleaseDate>) -> bool {
+        self.album.released_after(date)
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Album {
     #[serde(rename = "album_type")]
     pub album_type: String,
     #[serde(rename = "total_tracks")]
     pub total_tracks: i64,
-    #[serde(rename = "available_markets")]
+    #[serde(rename = "available_markets", default)]
     pub available_markets: Vec<String>,
     #[serde(rename = "external_urls")]
     pub external_urls: ExternalUrls,
@@ -59,15 +119,107 @@ pub struct Album {
     pub type_field: String,
     pub uri: String,
     pub artists: Vec<Artist>,
+    /// Only present when listing an artist's albums
+    #[serde(rename = "album_group")]
+    pub album_group: Option<String>,
+    /// The first page of the album's tracks, as simplified tracks without `album`. Only present
+    /// when fetching the album itself, as with `get_album`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracks: Option<Paging<Track>>,
+    /// Only present when fetching the album itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copyrights: Option<Vec<Copyright>>,
+    /// Only present when fetching the album itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Only present when fetching the album itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub popularity: Option<i64>,
+    /// The album's UPC or EAN. Only present when fetching the album itself
+    #[serde(
+        rename = "external_ids",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub external_ids: Option<ExternalIds>,
+}
+
+impl Album {
+    /// Combine two responses for the same album, with the same precedence as [`Track::merge`].
+    pub fn merge(self, other: Album) -> Album {
+        Album {
+            album_type: prefer_string(self.album_type, other.album_type),
+            total_tracks: prefer_nonzero(self.total_tracks, other.total_tracks),
+            available_markets: union(self.available_markets, other.available_markets),
+            external_urls: self.external_urls.merge(other.external_urls),
+            href: prefer_string(self.href, other.href),
+            id: prefer_string(self.id, other.id),
+            images: prefer_vec(self.images, other.images),
+            name: prefer_string(self.name, other.name),
+            release_date: prefer_string(self.release_date, other.release_date),
+            release_date_precision: prefer_string(
+                self.release_date_precision,
+                other.release_date_precision,
+            ),
+            restrictions: other.restrictions.or(self.restrictions),
+            type_field: prefer_string(self.type_field, other.type_field),
+            uri: prefer_string(self.uri, other.uri),
+            artists: prefer_vec(self.artists, other.artists),
+            album_group: other.album_group.or(self.album_group),
+            tracks: other.tracks.or(self.tracks),
+            copyrights: other.copyrights.or(self.copyrights),
+            label: other.label.or(self.label),
+            popularity: other.popularity.or(self.popularity),
+            external_ids: match (self.external_ids, other.external_ids) {
+                (Some(ids), Some(other)) => Some(ids.merge(other)),
+                (ids, other) => other.or(ids),
+            },
+        }
+    }
+
+    /// The parsed `release_date`, `None` when Spotify doesn't know it or it is malformed.
+    pub fn parsed_release_date(&self) -> Option<ReleaseDate> {
+        ReleaseDate::parse(&self.release_date, &self.release_date_precision)
+    }
+
+    pub fn release_year(&self) -> Option<i32> {
+        self.parsed_release_date().map(|date| date.year)
+    }
+
+    /// Whether the album came out before `date`, a year or a [`ReleaseDate`]. A release known
+    /// only to the year or month counts as its first day. False when the release date is unknown.
+    pub fn released_before(&self, date: impl Into<ReleaseDate>) -> bool {
+        let date = date.into();
+        self.parsed_release_date()
+            .is_some_and(|released| released.is_before(&date))
+    }
+
+    /// Whether the album came out after the whole of `date`, a year or a [`ReleaseDate`]. False
+    /// when the release date is unknown.
+    pub fn released_after(&self, date: impl Into<ReleaseDate>) -> bool {
+        let date = date.into();
+        self.parsed_release_date()
+            .is_some_and(|released| released.is_after(&date))
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct ExternalUrls {
     pub spotify: String,
 }
 
+impl ExternalUrls {
+    fn merge(self, other: ExternalUrls) -> ExternalUrls {
+        ExternalUrls {
+            spotify: prefer_string(self.spotify, other.spotify),
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Image {
     pub url: String,
@@ -76,12 +228,24 @@ pub struct Image {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Restrictions {
     pub reason: String,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Copyright {
+    pub text: String,
+    /// `C` for the copyright, `P` for the sound recording (performance) copyright
+    #[serde(rename = "type")]
+    pub type_field: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Artist {
     #[serde(rename = "external_urls")]
@@ -95,6 +259,7 @@ pub struct Artist {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct ExternalIds {
     pub isrc: Option<String>,
@@ -102,6 +267,200 @@ pub struct ExternalIds {
     pub upc: Option<String>,
 }
 
+impl ExternalIds {
+    fn merge(self, other: ExternalIds) -> ExternalIds {
+        ExternalIds {
+            isrc: other.isrc.or(self.isrc),
+            ean: other.ean.or(self.ean),
+            upc: other.upc.or(self.upc),
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct LinkedFrom {}
+
+fn prefer_string(fallback: String, preferred: String) -> String {
+    if preferred.is_empty() {
+        fallback
+    } else {
+        preferred
+    }
+}
+
+fn prefer_vec<T>(fallback: Vec<T>, preferred: Vec<T>) -> Vec<T> {
+    if preferred.is_empty() {
+        fallback
+    } else {
+        preferred
+    }
+}
+
+fn prefer_nonzero(fallback: i64, preferred: i64) -> i64 {
+    if preferred == 0 { fallback } else { preferred }
+}
+
+/// Everything in `a` followed by whatever `b` adds, keeping the first occurrence of each.
+fn union(mut a: Vec<String>, b: Vec<String>) -> Vec<String> {
+    for item in b {
+        if !a.contains(&item) {
+            a.push(item);
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    /// A track as listed in an album's `tracks`: no album, popularity, or external IDs.
+    fn simplified() -> Track {
+        Track {
+            artists: vec![Artist {
+                id: "artist".to_string(),
+                name: "Artist".to_string(),
+                ..Artist::default()
+            }],
+            available_markets: strings(&["CA", "US"]),
+            disc_number: 1,
+            duration_ms: 215_000,
+            explicit: true,
+            href: "https://api.spotify.com/v1/tracks/track".to_string(),
+            id: "track".to_string(),
+            name: "Song".to_string(),
+            preview_url: Some("https://p.scdn.co/preview".to_string()),
+            track_number: 3,
+            type_field: "track".to_string(),
+            uri: "spotify:track:track".to_string(),
+            ..Track::default()
+        }
+    }
+
+    /// The same track from `get_track`, which carries its own view of some fields.
+    fn full() -> Track {
+        Track {
+            album: Album {
+                id: "album".to_string(),
+                name: "Album".to_string(),
+                release_date: "2001-02-03".to_string(),
+                release_date_precision: "day".to_string(),
+                ..Album::default()
+            },
+            available_markets: strings(&["US", "GB"]),
+            external_ids: ExternalIds {
+                isrc: Some("USRC17607839".to_string()),
+                ..ExternalIds::default()
+            },
+            is_playable: Some(true),
+            name: "Song (Remastered)".to_string(),
+            popularity: 64,
+            preview_url: None,
+            ..simplified()
+        }
+    }
+
+    #[test]
+    fn full_track_wins_and_simplified_fills_gaps() {
+        let simplified = simplified();
+        let full = Track {
+            artists: Vec::new(),
+            explicit: false,
+            ..full()
+        };
+        let merged = simplified.clone().merge(full.clone());
+
+        // Populated in the full track
+        assert_eq!(merged.album, full.album);
+        assert_eq!(merged.name, "Song (Remastered)");
+        assert_eq!(merged.popularity, 64);
+        assert_eq!(merged.external_ids.isrc.as_deref(), Some("USRC17607839"));
+        assert_eq!(merged.is_playable, Some(true));
+        // Missing from the full track
+        assert_eq!(merged.artists, simplified.artists);
+        assert_eq!(merged.preview_url, simplified.preview_url);
+        // Unioned, in order of first appearance
+        assert_eq!(merged.available_markets, strings(&["CA", "US", "GB"]));
+        // Either side flagging it is enough
+        assert!(merged.explicit);
+    }
+
+    #[test]
+    fn merging_an_empty_track_changes_nothing() {
+        assert_eq!(full().merge(Track::default()), full());
+        assert_eq!(Track::default().merge(full()), full());
+    }
+
+    fn released(date: &str, precision: &str) -> Track {
+        Track {
+            album: Album {
+                release_date: date.to_string(),
+                release_date_precision: precision.to_string(),
+                ..Album::default()
+            },
+            ..Track::default()
+        }
+    }
+
+    fn date(date: &str, precision: &str) -> ReleaseDate {
+        ReleaseDate::parse(date, precision).unwrap()
+    }
+
+    #[test]
+    fn release_year_at_every_precision() {
+        assert_eq!(released("1981", "year").release_year(), Some(1981));
+        assert_eq!(released("1981-12", "month").release_year(), Some(1981));
+        assert_eq!(released("1981-12-15", "day").release_year(), Some(1981));
+        assert_eq!(released("0000", "year").release_year(), None);
+        assert_eq!(released("", "").release_year(), None);
+    }
+
+    #[test]
+    fn released_before_and_after_a_year_at_every_precision() {
+        for track in [
+            released("1981", "year"),
+            released("1981-12", "month"),
+            released("1981-12-15", "day"),
+        ] {
+            let date = &track.album.release_date;
+            assert!(track.released_before(1982), "{date}");
+            assert!(!track.released_before(1981), "{date}");
+            assert!(track.released_after(1980), "{date}");
+            assert!(!track.released_after(1981), "{date}");
+        }
+    }
+
+    #[test]
+    fn released_before_and_after_a_finer_date() {
+        // Known only to the year, so taken as January 1
+        let year = released("1981", "year");
+        assert!(year.released_before(date("1981-06", "month")));
+        assert!(!year.released_before(date("1981-01-01", "day")));
+        assert!(year.released_after(date("1980-12-31", "day")));
+
+        let month = released("1981-12", "month");
+        assert!(month.released_before(date("1981-12-02", "day")));
+        assert!(month.released_after(date("1981-11", "month")));
+        assert!(!month.released_after(date("1981-12", "month")));
+
+        let day = released("1981-12-15", "day");
+        assert!(day.released_before(date("1981-12-16", "day")));
+        assert!(!day.released_before(date("1981-12", "month")));
+        // Within December, so not after the whole of it
+        assert!(!day.released_after(date("1981-12", "month")));
+        assert!(day.released_after(date("1981-12-14", "day")));
+    }
+
+    #[test]
+    fn unknown_release_is_neither_before_nor_after() {
+        let unknown = released("", "");
+        assert!(!unknown.released_before(3000));
+        assert!(!unknown.released_after(1));
+    }
+}
@@ -1,26 +1,42 @@
-
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::audio_analysis::AudioAnalysisTrack;
+use crate::musical_key::Mode;
+use crate::musical_key::PitchClass;
 use crate::uri::Uri;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Numeric features Spotify leaves out default to zero, like those of tracks it couldn't analyse,
+/// so check `is_analyzed` before trusting them.
+///
+/// The default has every field zeroed or empty and `/` for its URLs, handy in tests that set
+/// only the fields they care about.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct TrackAudioFeatures {
+    #[serde(default)]
     pub acousticness: f64,
     #[serde(rename = "analysis_url")]
     pub analysis_url: Uri,
+    #[serde(default)]
     pub danceability: f64,
-    #[serde(rename = "duration_ms")]
+    #[serde(rename = "duration_ms", default)]
     pub duration_ms: i64,
+    #[serde(default)]
     pub energy: f64,
     pub id: String,
+    #[serde(default)]
     pub instrumentalness: f64,
     pub key: i64,
+    #[serde(default)]
     pub liveness: f64,
+    #[serde(default)]
     pub loudness: f64,
     pub mode: i64,
+    #[serde(default)]
     pub speechiness: f64,
+    #[serde(default)]
     pub tempo: f64,
     #[serde(rename = "time_signature")]
     pub time_signature: i64,
@@ -29,5 +45,86 @@ pub struct TrackAudioFeatures {
     #[serde(rename = "type")]
     pub type_field: String,
     pub uri: String,
+    #[serde(default)]
     pub valence: f64,
+    /// Where the values came from. Absent from Spotify's responses, where it is `Features`
+    #[serde(default, skip_serializing_if = "FeaturesOrigin::is_features")]
+    pub origin: FeaturesOrigin,
+}
+
+/// Which endpoint a `TrackAudioFeatures` was filled from.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FeaturesOrigin {
+    /// The official audio features
+    #[default]
+    Features,
+    /// The audio analysis summary, because the features endpoint was unavailable. Only the
+    /// tempo, key, mode, time signature, loudness, and duration are known; the perceptual
+    /// features such as energy and valence are left at zero
+    AudioAnalysis,
+}
+
+impl FeaturesOrigin {
+    fn is_features(&self) -> bool {
+        *self == FeaturesOrigin::Features
+    }
+}
+
+impl TrackAudioFeatures {
+    /// The features that can be read off the audio analysis summary of track `id`, flagged with
+    /// `FeaturesOrigin::AudioAnalysis`.
+    pub fn from_analysis(id: &str, analysis: &AudioAnalysisTrack) -> eyre::Result<Self> {
+        Ok(Self {
+            analysis_url: Uri(format!("https://api.spotify.com/v1/audio-analysis/{}", id).parse()?),
+            duration_ms: (analysis.duration * 1000.0).round() as i64,
+            id: id.to_string(),
+            key: analysis.key,
+            loudness: analysis.loudness,
+            mode: analysis.mode,
+            tempo: analysis.tempo,
+            time_signature: analysis.time_signature,
+            track_href: Uri(format!("https://api.spotify.com/v1/tracks/{}", id).parse()?),
+            type_field: "audio_features".to_string(),
+            uri: format!("spotify:track:{}", id),
+            origin: FeaturesOrigin::AudioAnalysis,
+            ..Self::default()
+        })
+    }
+
+    /// Whether Spotify actually analysed the track. Unanalysed tracks come back with every
+    /// feature zeroed, which would otherwise read as silent, tuneless, zero-BPM audio.
+    pub fn is_analyzed(&self) -> bool {
+        [
+            self.acousticness,
+            self.danceability,
+            self.energy,
+            self.instrumentalness,
+            self.liveness,
+            self.loudness,
+            self.speechiness,
+            self.tempo,
+            self.valence,
+        ]
+        .iter()
+        .any(|&feature| feature != 0.0)
+    }
+
+    /// The detected key, `None` when Spotify couldn't detect one.
+    pub fn pitch_class(&self) -> Option<PitchClass> {
+        PitchClass::from_key(self.key)
+    }
+
+    pub fn mode_kind(&self) -> Mode {
+        Mode::from_mode(self.mode)
+    }
+
+    /// The key and mode spelled out, e.g. "C# minor", or "Unknown" without a detected key.
+    pub fn key_name(&self) -> String {
+        match self.pitch_class() {
+            Some(pitch_class) => format!("{} {}", pitch_class, self.mode_kind()),
+            None => "Unknown".to_string(),
+        }
+    }
 }
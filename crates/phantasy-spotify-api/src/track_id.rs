@@ -1,7 +1,92 @@
+use std::fmt;
 use std::ops::Deref;
+use url::Url;
 
-#[derive(Debug)]
+/// Length of every Spotify base-62 ID
+const ID_LEN: usize = 22;
+
+#[derive(Debug, Clone)]
 pub struct TrackId(pub String);
+impl TrackId {
+    /// Read a track ID from anything a user is likely to paste: a bare ID, a `spotify:track:`
+    /// URI, or an `open.spotify.com/track/` link, whose query such as `?si=` is dropped.
+    ///
+    /// Checks that the ID looks like a Spotify track ID, so a typo errors here rather than as a
+    /// 404 from the API.
+    pub fn parse(input: impl AsRef<str>) -> Result<TrackId, ParseIdError> {
+        let input = input.as_ref().trim();
+        if input.starts_with("spotify:") {
+            TrackId::from_uri(input)
+        } else if input.contains("://") || input.starts_with("open.spotify.com/") {
+            TrackId::from_url(input)
+        } else {
+            TrackId::from_bare_id(input)
+        }
+    }
+
+    /// Parse a `spotify:track:<id>` URI.
+    pub fn from_uri(uri: &str) -> Result<TrackId, ParseIdError> {
+        let mut parts = uri.trim().split(':');
+        if parts.next() != Some("spotify") {
+            return Err(ParseIdError::Unrecognized {
+                input: uri.to_string(),
+            });
+        }
+        let (kind, id) = (parts.next().unwrap_or_default(), parts.next());
+        if parts.next().is_some() {
+            return Err(ParseIdError::Unrecognized {
+                input: uri.to_string(),
+            });
+        }
+        TrackId::from_kind_and_id(kind, id)
+    }
+
+    /// Parse an `https://open.spotify.com/track/<id>` link. The scheme may be left off, and a
+    /// localized `/intl-<lang>/` prefix is skipped.
+    pub fn from_url(url: &str) -> Result<TrackId, ParseIdError> {
+        let url = url.trim();
+        let parsed = if url.contains("://") {
+            Url::parse(url)
+        } else {
+            Url::parse(&format!("https://{}", url))
+        };
+        let parsed = match parsed {
+            Ok(parsed) if parsed.host_str() == Some("open.spotify.com") => parsed,
+            _ => {
+                return Err(ParseIdError::Unrecognized {
+                    input: url.to_string(),
+                });
+            }
+        };
+        let mut segments = parsed
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty() && !segment.starts_with("intl-"));
+        let kind = segments.next().unwrap_or_default();
+        TrackId::from_kind_and_id(kind, segments.next())
+    }
+
+    fn from_kind_and_id(kind: &str, id: Option<&str>) -> Result<TrackId, ParseIdError> {
+        if kind != "track" {
+            return Err(ParseIdError::WrongKind {
+                expected: "track",
+                found: kind.to_string(),
+            });
+        }
+        match id {
+            Some(id) if !id.is_empty() => TrackId::from_bare_id(id),
+            _ => Err(ParseIdError::MissingId),
+        }
+    }
+
+    fn from_bare_id(id: &str) -> Result<TrackId, ParseIdError> {
+        if id.len() != ID_LEN || !id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(ParseIdError::InvalidId { id: id.to_string() });
+        }
+        Ok(TrackId(id.to_string()))
+    }
+}
 impl std::fmt::Display for TrackId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -17,4 +102,136 @@ impl AsRef<str> for TrackId {
     fn as_ref(&self) -> &str {
         &self.0
     }
-}
\ No newline at end of file
+}
+
+/// Why a string couldn't be read as a Spotify ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseIdError {
+    /// Neither a bare ID, a `spotify:` URI, nor an `open.spotify.com` link
+    Unrecognized { input: String },
+    /// A URI or link to another kind of resource, such as an album
+    WrongKind {
+        expected: &'static str,
+        found: String,
+    },
+    /// A URI or link with nothing where the ID belongs
+    MissingId,
+    /// Not 22 base-62 letters and digits
+    InvalidId { id: String },
+}
+
+impl fmt::Display for ParseIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseIdError::Unrecognized { input } => write!(
+                f,
+                "{:?} is not a Spotify ID, spotify: URI, or open.spotify.com link",
+                input
+            ),
+            ParseIdError::WrongKind { expected, found } if found.is_empty() => {
+                write!(
+                    f,
+                    "Expected a link to a {}, found no resource type",
+                    expected
+                )
+            }
+            ParseIdError::WrongKind { expected, found } => {
+                write!(f, "Expected a link to a {}, not to {:?}", expected, found)
+            }
+            ParseIdError::MissingId => write!(f, "The Spotify link has no ID"),
+            ParseIdError::InvalidId { id } => write!(
+                f,
+                "{:?} is not a Spotify ID, expected {} letters and digits",
+                id, ID_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseIdError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "4uLU6hMCjMI75M1A2tKUQC";
+
+    fn parse(input: &str) -> Result<String, ParseIdError> {
+        TrackId::parse(input).map(|id| id.0)
+    }
+
+    #[test]
+    fn bare_id() {
+        assert_eq!(parse(ID), Ok(ID.to_string()));
+        assert_eq!(parse(&format!("  {ID}\n")), Ok(ID.to_string()));
+    }
+
+    #[test]
+    fn uri() {
+        assert_eq!(parse(&format!("spotify:track:{ID}")), Ok(ID.to_string()));
+    }
+
+    #[test]
+    fn url() {
+        for url in [
+            format!("https://open.spotify.com/track/{ID}"),
+            format!("open.spotify.com/track/{ID}"),
+            format!("https://open.spotify.com/intl-de/track/{ID}"),
+        ] {
+            assert_eq!(parse(&url), Ok(ID.to_string()), "{url}");
+        }
+    }
+
+    #[test]
+    fn url_with_query() {
+        assert_eq!(
+            parse(&format!(
+                "https://open.spotify.com/track/{ID}?si=1a2b3c4d5e6f7a8b"
+            )),
+            Ok(ID.to_string())
+        );
+        assert_eq!(
+            parse(&format!("https://open.spotify.com/track/{ID}?si=x#frag")),
+            Ok(ID.to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(
+            parse("not an id"),
+            Err(ParseIdError::InvalidId {
+                id: "not an id".to_string()
+            })
+        );
+        assert_eq!(
+            parse(&ID[1..]),
+            Err(ParseIdError::InvalidId {
+                id: ID[1..].to_string()
+            })
+        );
+        assert_eq!(
+            parse(&format!("spotify:album:{ID}")),
+            Err(ParseIdError::WrongKind {
+                expected: "track",
+                found: "album".to_string()
+            })
+        );
+        assert_eq!(
+            parse(&format!("spotify:track:{ID}:extra")),
+            Err(ParseIdError::Unrecognized {
+                input: format!("spotify:track:{ID}:extra")
+            })
+        );
+        assert_eq!(
+            parse("https://open.spotify.com/track/"),
+            Err(ParseIdError::MissingId)
+        );
+        assert_eq!(
+            parse(&format!("https://example.com/track/{ID}")),
+            Err(ParseIdError::Unrecognized {
+                input: format!("https://example.com/track/{ID}")
+            })
+        );
+    }
+}
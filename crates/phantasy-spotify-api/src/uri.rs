@@ -2,7 +2,8 @@
 use serde::Deserialize;
 use serde::Serialize;
 
-#[derive(Debug, Clone, PartialEq)]
+/// Defaults to `/`, a valid if meaningless placeholder.
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Uri(pub http::Uri);
 impl<'de> Deserialize<'de> for Uri {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -22,4 +23,15 @@ impl Serialize for Uri {
     {
         serializer.serialize_str(&self.0.to_string())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Uri {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Uri".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": "string", "format": "uri" })
+    }
+}
@@ -1,13 +1,49 @@
+use clap::Parser;
+use clap::Subcommand;
 use eyre::WrapErr;
 use eyre::eyre;
+use phantasy_fingerprint::cache::DEFAULT_CACHE_DIR;
+use phantasy_fingerprint::cache::build_and_save_fingerprint;
+use phantasy_fingerprint::cache::cache_file_for;
+use phantasy_fingerprint::cache::cache_files_by_quick_hash;
+use phantasy_fingerprint::cache::find_cache_file;
+use phantasy_fingerprint::cache::is_cache_file;
+use phantasy_fingerprint::cache::load_fingerprint;
+use phantasy_fingerprint::cache::load_or_build_fingerprint;
+use phantasy_fingerprint::cache::load_or_build_fingerprint_with_config;
+use phantasy_fingerprint::cache::save_fingerprint;
+use phantasy_fingerprint::cache::save_fingerprint_for_duplicate;
+use phantasy_fingerprint::compute_fingerprint::HOP_SIZE;
+use phantasy_fingerprint::compute_fingerprint::WINDOW_SIZE;
+use phantasy_fingerprint::compute_fingerprint::compute_fingerprint_mid_side;
+use phantasy_fingerprint::compute_fingerprint::compute_fingerprint_with_config;
+use phantasy_fingerprint::compute_spectrogram::compute_spectrogram;
+use phantasy_fingerprint::decode::can_decode;
+use phantasy_fingerprint::decode::decode_to_mid_side_f32;
+use phantasy_fingerprint::decode::decode_to_mono_f32;
+use phantasy_fingerprint::decode::detect_channel_layout;
+use phantasy_fingerprint::decode::detect_sample_rate;
+use phantasy_fingerprint::export_histogram::OffsetHistogram;
+use phantasy_fingerprint::extract_snippet::extract_snippet_with_fade;
+use phantasy_fingerprint::find_aligned_regions::AlignedRegionsConfig;
+use phantasy_fingerprint::find_aligned_regions::find_aligned_regions;
+use phantasy_fingerprint::find_matches::OffsetBin;
+use phantasy_fingerprint::find_matches::find_matches;
+use phantasy_fingerprint::find_matches::offset_histogram;
+use phantasy_fingerprint::fingerprint_config::ChannelMode;
+use phantasy_fingerprint::fingerprint_config::FingerprintConfig;
+use phantasy_fingerprint::fingerprint_data::SourceInfo;
+use phantasy_fingerprint::fingerprint_error::FingerprintError;
+use phantasy_fingerprint::fingerprint_index::FingerprintIndex;
+use phantasy_fingerprint::fingerprint_pipeline::PipelineConfig;
+use phantasy_fingerprint::fingerprint_pipeline::fingerprint_files_pipelined;
+use phantasy_fingerprint::match_lines::write_match_line;
+use phantasy_fingerprint::resample::resample;
+use phantasy_fingerprint::snap_to_onset::snap_to_onset;
+use phantasy_fingerprint::track_metadata::MetadataTable;
 use phantasy_init::init;
-use serde::Deserialize;
-use serde::Serialize;
 use std::collections::HashMap;
-use std::fs::File;
 use std::fs::{self};
-use std::io::BufReader;
-use std::io::BufWriter;
 use std::path::Path;
 use std::path::PathBuf;
 use tokio::process::Command;
@@ -15,10 +51,104 @@ use tracing::debug;
 use tracing::info;
 use tracing::warn;
 
+/// Rate every fingerprint is computed at, cached or not. Files at other rates are resampled to
+/// it, so tracks at 44.1 and 48 kHz match each other and their offsets come out in true seconds
+const ANALYSIS_RATE: usize = 48_000;
+
+#[derive(Debug, Parser)]
+#[command(about = "Find where a sample is used across a music directory")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// Directory holding cached track fingerprints
+    #[arg(long, global = true, default_value = DEFAULT_CACHE_DIR)]
+    cache_dir: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Search `MUSIC_DIR` for the `SAMPLE_PATH` snippet (the default)
+    Match {
+        /// Also write one JSON object per line to this file as each track is matched
+        #[arg(long)]
+        jsonl: Option<PathBuf>,
+    },
+    /// Fingerprint every uncached decodable file in `MUSIC_DIR`, decoding and hashing in parallel
+    Build {
+        /// Fingerprint threads, defaults to the available parallelism
+        #[arg(long)]
+        workers: Option<usize>,
+        /// Decoded files allowed to queue up for a fingerprint thread
+        #[arg(long)]
+        channel_depth: Option<usize>,
+    },
+    /// Check cached fingerprints against the files they were built from
+    Verify {
+        /// Rebuild fingerprints whose source file has changed
+        #[arg(long)]
+        rebuild: bool,
+    },
+    /// Find every stretch where two long recordings overlap
+    Overlap {
+        /// The recording to search for
+        query: PathBuf,
+        /// The recording to search within
+        track: PathBuf,
+        /// Length of each query window, in seconds
+        #[arg(long, default_value_t = AlignedRegionsConfig::default().query_window_secs)]
+        window_secs: f32,
+        /// Distance between query windows, in seconds. Smaller finds shorter overlaps but is slower
+        #[arg(long, default_value_t = AlignedRegionsConfig::default().query_hop_secs)]
+        hop_secs: f32,
+    },
+    /// Show how hashes of a recording spread over the hash space
+    Stats {
+        /// The recording to inspect
+        track: PathBuf,
+        /// How many of the busiest hashes to list
+        #[arg(long, default_value_t = 10)]
+        top_k: usize,
+    },
+}
+
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     init()?;
 
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Commands::Match { jsonl: None }) {
+        Commands::Match { jsonl } => run_match(&cli.cache_dir, jsonl.as_deref()).await,
+        Commands::Build {
+            workers,
+            channel_depth,
+        } => {
+            let defaults = PipelineConfig::default();
+            let config = PipelineConfig {
+                workers: workers.unwrap_or(defaults.workers),
+                channel_depth: channel_depth.unwrap_or(defaults.channel_depth),
+            };
+            build_library(&cli.cache_dir, &config)
+        }
+        Commands::Verify { rebuild } => verify_fingerprints(&cli.cache_dir, rebuild),
+        Commands::Overlap {
+            query,
+            track,
+            window_secs,
+            hop_secs,
+        } => {
+            let config = AlignedRegionsConfig {
+                query_window_secs: window_secs,
+                query_hop_secs: hop_secs,
+            };
+            find_overlap(&cli.cache_dir, &query, &track, &config)
+        }
+        Commands::Stats { track, top_k } => show_collision_stats(&cli.cache_dir, &track, top_k),
+    }
+}
+
+/// Search every decodable file in `MUSIC_DIR` for the configured sample snippet, streaming
+/// each outcome to `jsonl` as well when given.
+async fn run_match(cache_dir: &Path, jsonl: Option<&Path>) -> eyre::Result<()> {
     // Read environment variables
     let music_dir = var("MUSIC_DIR")?;
     let music_dir = PathBuf::from(music_dir);
@@ -26,48 +156,189 @@ async fn main() -> eyre::Result<()> {
     let mut sample_path = PathBuf::from(var("SAMPLE_PATH")?);
     let sample_begin = var("SAMPLE_BEGIN")?.parse::<f32>()?;
     let sample_end = var("SAMPLE_END")?.parse::<f32>()?;
+    let explain = std::env::var("EXPLAIN_MATCHES").is_ok_and(|v| v == "1" || v == "true");
+    // Optionally only look for the sample within this part of each track, in seconds
+    let search_window = match (var("SEARCH_WINDOW_BEGIN"), var("SEARCH_WINDOW_END")) {
+        (Ok(begin), Ok(end)) => Some((begin.parse::<f32>()?, end.parse::<f32>()?)),
+        _ => None,
+    };
+    // Optionally fade the snippet edges over a few milliseconds so the cut doesn't add peaks
+    let fade_ms = match var("SNIPPET_FADE_MS") {
+        Ok(ms) => ms.parse::<f32>()?,
+        Err(_) => 0.0,
+    };
+    // Optionally snap each match onto the nearest onset within this many seconds
+    let snap_tolerance = match var("SNAP_TOLERANCE") {
+        Ok(secs) => Some(secs.parse::<f32>()?),
+        Err(_) => None,
+    };
+    // Optionally dump each track's full offset histogram here for plotting, as CSV or JSON
+    let histogram_dir = std::env::var("HISTOGRAM_DIR").ok().map(PathBuf::from);
+    let histogram_json = std::env::var("HISTOGRAM_FORMAT").is_ok_and(|v| v == "json");
+    if let Some(dir) = &histogram_dir {
+        fs::create_dir_all(dir)?;
+    }
+    let snippet_id = format!("{}@{}-{}", sample_path.display(), sample_begin, sample_end);
+
+    // Decode the sample as is when it's a supported format, else convert it to OGG
+    sample_path = ensure_decodable(sample_path).await?;
+    info!("Using sample: {:?}", sample_path);
 
-    // Ensure sample is OGG, else convert
-    sample_path = ensure_ogg(sample_path).await?;
-    info!("Using sample OGG: {:?}", sample_path);
+    let config = snippet_config();
+    let track_config = track_config()?;
 
     // Decode sample snippet
-    let sample_pcm = decode_ogg_to_mono_f32(&sample_path)?;
-    let sample_rate = 48_000.0; // Hard-coded for simplicity; real code should detect from decode
-    let snippet = extract_snippet(&sample_pcm, sample_rate, sample_begin, sample_end);
+    let sample_channels = detect_channel_layout(&sample_path)?.channels();
+    let sample_rate = ANALYSIS_RATE;
+    // The snippet is cut at the file's own rate, then brought to the rate tracks are
+    // fingerprinted at, as their cached fingerprints were
+    let file_rate = detect_sample_rate(&sample_path)? as usize;
+    if file_rate != sample_rate {
+        info!(
+            "Sample is at {} Hz, resampling the snippet to {} Hz",
+            file_rate, sample_rate
+        );
+    }
+    let extract = |pcm: &[f32]| {
+        let snippet = extract_snippet_with_fade(
+            pcm,
+            file_rate as f32,
+            sample_begin,
+            sample_end,
+            fade_ms / 1000.0,
+        );
+        resample(&snippet, file_rate, sample_rate, config.resample_quality)
+    };
 
     // Compute (or load) fingerprint of sample snippet
     // We'll do it in-memory for the snippet itself
-    let snippet_fp = compute_fingerprint(&snippet, sample_rate as usize)?;
+    let snippet_fp = match config.channel_mode {
+        ChannelMode::Mono => {
+            let (sample_pcm, _) = decode_to_mono_f32(&sample_path)?;
+            compute_fingerprint_with_config(&extract(&sample_pcm), sample_rate, &config)?
+        }
+        ChannelMode::MidSide => {
+            let (mid, side) = decode_to_mid_side_f32(&sample_path)?;
+            compute_fingerprint_mid_side(&extract(&mid), &extract(&side), sample_rate, &config)?
+        }
+    };
 
     info!("Snippet fingerprint length: {}", snippet_fp.pairs.len());
 
-    // Gather OGG files
-    let mut ogg_files = Vec::new();
+    // Gather the files we can decode
+    let mut audio_files = Vec::new();
     for entry in fs::read_dir(&music_dir)? {
         let path = entry?.path();
-        if path.extension().map_or(false, |ext| ext == "ogg") {
-            ogg_files.push(path);
+        if can_decode(&path, false) {
+            audio_files.push(path);
         }
     }
-    info!("Found {} OGG files", ogg_files.len());
+    info!("Found {} decodable files", audio_files.len());
+
+    let mut jsonl = jsonl.map(fs::File::create).transpose()?;
+    // Name matched tracks by artist and title where the cache knows them
+    let metadata = MetadataTable::load(cache_dir)?;
+    let describe = |track_path: &Path| match metadata.get(track_path) {
+        Some(track) => format!("{} ({})", track.label(), track_path.display()),
+        None => track_path.display().to_string(),
+    };
 
     // For each track, load (or build) a fingerprint, then compare with snippet's fingerprint
-    for track_path in &ogg_files {
-        match find_matches(track_path, &snippet_fp, sample_rate as usize).await {
-            Ok(Some((best_offset_sec, best_count))) => {
-                info!(
-                    "Likely match in {} at ~{:.2} sec (overlap count = {})",
+    for track_path in &audio_files {
+        let result = load_or_build_fingerprint_with_config(
+            track_path,
+            cache_dir,
+            sample_rate,
+            &track_config,
+        )
+        .map(|track_fp| {
+            // Downmixes of different layouts differ, which can quietly sink a match
+            if let Some(track_channels) = track_fp.channels
+                && track_channels != sample_channels
+            {
+                warn!(
+                    "{} has {} channels but the sample has {}, matches may be weaker",
                     track_path.display(),
-                    best_offset_sec,
-                    best_count
+                    track_channels,
+                    sample_channels
+                );
+            }
+            if let Some(dir) = &histogram_dir
+                && let Err(e) = export_histogram(
+                    dir,
+                    &snippet_id,
+                    track_path,
+                    histogram_json,
+                    &offset_histogram(&track_fp, &snippet_fp, sample_rate, search_window),
+                )
+            {
+                warn!(
+                    "Couldn't export the histogram for {}: {:?}",
+                    track_path.display(),
+                    e
+                );
+            }
+            find_matches(&track_fp, &snippet_fp, sample_rate, search_window, explain)
+        });
+        if let (Some(out), Ok(result)) = (&mut jsonl, &result) {
+            write_match_line(out, track_path, result.as_ref())?;
+        }
+        match result {
+            Ok(Some(result)) => {
+                info!(
+                    "Likely match in {} at ~{:.2} sec (overlap count = {}, p = {:.1e}, coherence = {:.1})",
+                    describe(track_path),
+                    result.offset_sec,
+                    result.count,
+                    result.p_value,
+                    result.coherence
                 );
+                if let Some(tolerance) = snap_tolerance {
+                    // Onsets are found in the file as decoded, offsets being in seconds
+                    let (track_pcm, track_rate) = decode_to_mono_f32(track_path)?;
+                    let spec = compute_spectrogram(
+                        &track_pcm,
+                        track_rate as usize,
+                        WINDOW_SIZE,
+                        HOP_SIZE,
+                    )?;
+                    let snapped = snap_to_onset(
+                        &spec,
+                        track_rate as usize,
+                        HOP_SIZE,
+                        result.offset_sec,
+                        tolerance,
+                    );
+                    info!(
+                        "  Nearest onset at ~{:.2} sec (raw {:.2} sec)",
+                        snapped.snapped_offset_sec, snapped.raw_offset_sec
+                    );
+                }
+                for pair in result.supporting_pairs.iter().flatten() {
+                    debug!(
+                        "  snippet frame {} <-> track frame {} via (f1={}, f2={}, dt={})",
+                        pair.snippet_anchor,
+                        pair.track_anchor,
+                        pair.hash.0,
+                        pair.hash.1,
+                        pair.hash.2
+                    );
+                }
             }
             Ok(None) => {
                 info!("No strong match in {}", track_path.display());
             }
+            // A corrupt or unsupported file shouldn't stop the rest of the scan
+            Err(
+                e @ (FingerprintError::Decode { .. }
+                | FingerprintError::UnsupportedFormat { .. }
+                | FingerprintError::TooShort { .. }),
+            ) => {
+                warn!("Skipping {}: {:?}", track_path.display(), e);
+            }
             Err(e) => {
-                warn!("Error matching {}: {:?}", track_path.display(), e);
+                return Err(eyre::Error::new(e)
+                    .wrap_err(format!("Error matching {}", track_path.display())));
             }
         }
     }
@@ -75,14 +346,103 @@ async fn main() -> eyre::Result<()> {
     Ok(())
 }
 
+/// Write one track's offset histogram into `dir` as CSV, or JSON when `json` is set, named
+/// after the track and headed by the snippet and track it compares.
+fn export_histogram(
+    dir: &Path,
+    snippet_id: &str,
+    track_path: &Path,
+    json: bool,
+    bins: &[OffsetBin],
+) -> eyre::Result<()> {
+    let track_id = track_path.display().to_string();
+    let histogram = OffsetHistogram {
+        snippet: snippet_id,
+        track: &track_id,
+        bins,
+    };
+    let stem = track_path
+        .file_stem()
+        .ok_or_else(|| eyre!("{} has no file name", track_path.display()))?;
+    let mut out = dir.join(stem);
+    let file = if json {
+        out.set_extension("json");
+        let file = fs::File::create(&out)?;
+        histogram.write_json(std::io::BufWriter::new(file))?;
+        out
+    } else {
+        out.set_extension("csv");
+        let file = fs::File::create(&out)?;
+        histogram.write_csv(std::io::BufWriter::new(file))?;
+        out
+    };
+    debug!("Wrote offset histogram to {}", file.display());
+    Ok(())
+}
+
+/// Report every region of `query` that also appears in `track`.
+fn find_overlap(
+    cache_dir: &Path,
+    query: &Path,
+    track: &Path,
+    config: &AlignedRegionsConfig,
+) -> eyre::Result<()> {
+    let sample_rate = ANALYSIS_RATE;
+    let query_fp = load_or_build_fingerprint(query, cache_dir, sample_rate)?;
+    let track_fp = load_or_build_fingerprint(track, cache_dir, sample_rate)?;
+
+    let regions = find_aligned_regions(&query_fp, &track_fp, sample_rate, config);
+    if regions.is_empty() {
+        info!(
+            "No overlap between {} and {}",
+            query.display(),
+            track.display()
+        );
+    }
+    for region in regions {
+        info!(
+            "{:.2}-{:.2} sec of {} lines up with {:.2}-{:.2} sec of {} (overlap count = {})",
+            region.query_start_sec,
+            region.query_end_sec,
+            query.display(),
+            region.track_start_sec,
+            region.track_end_sec,
+            track.display(),
+            region.count
+        );
+    }
+    Ok(())
+}
+
+/// Report the postings-list length distribution of `track`'s fingerprint.
+fn show_collision_stats(cache_dir: &Path, track: &Path, top_k: usize) -> eyre::Result<()> {
+    let sample_rate = ANALYSIS_RATE;
+    let track_fp = load_or_build_fingerprint(track, cache_dir, sample_rate)?;
+    let stats = FingerprintIndex::from_fingerprint(&track_fp).collision_stats(top_k);
+
+    info!(
+        "{} distinct hashes over {} postings in {}",
+        stats.distinct_hashes,
+        stats.total_postings,
+        track.display()
+    );
+    for (len, hashes) in &stats.histogram {
+        info!("  {} hashes occur {} times", hashes, len);
+    }
+    for ((f1, f2, dt), len) in &stats.busiest {
+        info!("  (f1={}, f2={}, dt={}) occurs {} times", f1, f2, dt, len);
+    }
+    Ok(())
+}
+
 // Read an env var or bail
 fn var(key: &str) -> eyre::Result<String> {
     std::env::var(key).map_err(|_| eyre!("Missing env var: {}", key))
 }
 
-/// Ensure the given path is OGG. If not, convert via `ffmpeg`.
-async fn ensure_ogg(path: PathBuf) -> eyre::Result<PathBuf> {
-    if path.extension().map_or(false, |ext| ext == "ogg") {
+/// Ensure the given path can be decoded directly. If not, convert it to OGG via `ffmpeg`.
+async fn ensure_decodable(path: PathBuf) -> eyre::Result<PathBuf> {
+    if can_decode(&path, false) {
         return Ok(path);
     }
     // Convert
@@ -109,258 +469,150 @@ async fn ensure_ogg(path: PathBuf) -> eyre::Result<PathBuf> {
     Ok(new_path)
 }
 
-/// Extract snippet from PCM given time range in seconds.
-fn extract_snippet<'a>(pcm: &'a [f32], sr: f32, begin: f32, end: f32) -> &'a [f32] {
-    let start_idx = (begin * sr).round() as usize;
-    let end_idx = (end * sr).round() as usize;
-    let start_idx = start_idx.min(pcm.len());
-    let end_idx = end_idx.min(pcm.len());
-    &pcm[start_idx..end_idx]
+/// How snippets are fingerprinted. Optionally fingerprint the mid and side channels
+/// separately, for tracks and snippet alike.
+fn snippet_config() -> FingerprintConfig {
+    FingerprintConfig {
+        channel_mode: if std::env::var("MID_SIDE").is_ok_and(|v| v == "1" || v == "true") {
+            ChannelMode::MidSide
+        } else {
+            ChannelMode::Mono
+        },
+        ..FingerprintConfig::default()
+    }
 }
 
-/// Decode an OGG file to raw mono f32 PCM (using i16 as intermediate).
-fn decode_ogg_to_mono_f32(path: &Path) -> eyre::Result<Vec<f32>> {
-    use lewton::inside_ogg::OggStreamReader;
-    use std::fs::File;
-    use std::io::BufReader;
-
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-    let mut ogg_reader = OggStreamReader::new(&mut reader)?;
+/// How tracks are fingerprinted, by `match` and `build` alike. Optionally leave talk-over
+/// intros and outros out of track fingerprints, but not the snippet's, and fingerprint ripped
+/// tracks with a few corrupt packets rather than skipping them. Tracks already in the cache
+/// keep whatever they were built with.
+fn track_config() -> eyre::Result<FingerprintConfig> {
+    Ok(FingerprintConfig {
+        trim_head_secs: var("TRIM_HEAD_SECS").map_or(Ok(0.0), |secs| secs.parse::<f32>())?,
+        trim_tail_secs: var("TRIM_TAIL_SECS").map_or(Ok(0.0), |secs| secs.parse::<f32>())?,
+        skip_corrupt_packets: var("SKIP_CORRUPT_PACKETS").is_ok_and(|v| v == "1" || v == "true"),
+        ..snippet_config()
+    })
+}
 
-    let mut pcm = Vec::new();
-    while let Some(packet) = ogg_reader.read_dec_packet_generic::<Vec<Vec<i16>>>()? {
-        let num_channels = packet.len();
-        if num_channels == 0 {
+/// Fingerprint the decodable files in `MUSIC_DIR` that have no cached fingerprint yet, with
+/// the same config `match` builds missing fingerprints with.
+fn build_library(cache_dir: &Path, config: &PipelineConfig) -> eyre::Result<()> {
+    let sample_rate = ANALYSIS_RATE;
+    let music_dir = PathBuf::from(var("MUSIC_DIR")?);
+    fs::create_dir_all(cache_dir)?;
+
+    // Copies and renames of files already fingerprinted reuse the existing fingerprint, as do
+    // copies of each other within this build once the first of them is done
+    let cached_by_hash = cache_files_by_quick_hash(cache_dir)?;
+    let mut todo = Vec::new();
+    let mut todo_by_hash: HashMap<u64, PathBuf> = HashMap::new();
+    let mut duplicates_of_todo = Vec::new();
+    let mut deduplicated = 0;
+    for entry in fs::read_dir(&music_dir)? {
+        let path = entry?.path();
+        if !can_decode(&path, false) || find_cache_file(&path, cache_dir).is_some() {
             continue;
         }
-        let samples_per_channel = packet[0].len();
-        for i in 0..samples_per_channel {
-            let mut sum = 0.0;
-            for ch in 0..num_channels {
-                sum += packet[ch][i] as f32;
-            }
-            pcm.push(sum / num_channels as f32);
+        let quick_hash = SourceInfo::quick_hash_of(&path)?;
+        if let Some(existing) = cached_by_hash.get(&quick_hash) {
+            debug!("{} duplicates {:?}", path.display(), existing);
+            save_fingerprint_for_duplicate(existing, &path, &cache_file_for(&path, cache_dir))?;
+            deduplicated += 1;
+        } else if let Some(original) = todo_by_hash.get(&quick_hash) {
+            duplicates_of_todo.push((path, original.clone()));
+        } else {
+            todo_by_hash.insert(quick_hash, path.clone());
+            todo.push(path);
         }
     }
-    Ok(pcm)
-}
-
-//
-// Shazam-Style Fingerprint
-//
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct FingerprintData {
-    /// Pairs of (f1, f2, deltaTime), mapped to the "anchor time" offset
-    /// We store them in a Vec for demonstration, but you might store differently.
-    pairs: Vec<FPHashEntry>,
-}
-
-// Each "hash" from a peak pair
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct FPHashEntry {
-    f1: u16,
-    f2: u16,
-    delta_t: u16,
-    /// The offset (in spectrogram frames) when this pair occurred
-    anchor_time: u32,
-}
-
-/// Build a basic fingerprint from PCM data
-fn compute_fingerprint(pcm: &[f32], sample_rate: usize) -> eyre::Result<FingerprintData> {
-    // 1) Build a spectrogram
-    //    For demonstration, we’ll keep it smaller windows to be faster
-    let window_size = 1024;
-    let hop_size = 512;
-    let spec = compute_spectrogram(pcm, sample_rate, window_size, hop_size)?;
-
-    // 2) Find local maxima in each time slice
-    let peaks_by_time = find_peaks(&spec);
-
-    // 3) Create pairs (f1, f2, delta_t)
-    //    We'll pair each peak with a handful of future peaks to get (f1, f2, Δt).
-    let fan_value = 5; // how many peaks to pair with
-    let mut pairs = Vec::new();
-
-    for (t, peaks) in peaks_by_time.iter().enumerate() {
-        for (_i, &f1) in peaks.iter().enumerate() {
-            // Pair with up to fan_value subsequent peaks in next frames
-            for future_t in (t + 1)..(t + 10).min(peaks_by_time.len()) {
-                // pick up to fan_value peaks from the future frame
-                let future_peaks = &peaks_by_time[future_t];
-                for (j, &f2) in future_peaks.iter().enumerate() {
-                    if j >= fan_value {
-                        break;
-                    }
-                    let delta_t = (future_t - t) as u16;
-                    pairs.push(FPHashEntry {
-                        f1,
-                        f2,
-                        delta_t,
-                        anchor_time: t as u32,
-                    });
-                }
+    info!(
+        "Fingerprinting {} files with {} workers",
+        todo.len(),
+        config.workers
+    );
+
+    let track_config = track_config()?;
+    for (path, result) in fingerprint_files_pipelined(&todo, sample_rate, &track_config, config) {
+        match result {
+            Ok(data) => save_fingerprint(&data, &cache_file_for(&path, cache_dir))?,
+            // A corrupt or unsupported file shouldn't stop the rest of the build
+            Err(
+                e @ (FingerprintError::Decode { .. }
+                | FingerprintError::UnsupportedFormat { .. }
+                | FingerprintError::TooShort { .. }),
+            ) => {
+                warn!("Skipping {}: {:?}", path.display(), e);
+            }
+            Err(e) => {
+                return Err(eyre::Error::new(e)
+                    .wrap_err(format!("Error fingerprinting {}", path.display())));
             }
         }
     }
 
-    Ok(FingerprintData { pairs })
-}
-
-/// Compute a spectrogram of `pcm` with Hann window. Return matrix of shape (n_freq, n_frames).
-fn compute_spectrogram(
-    pcm: &[f32],
-    _sample_rate: usize,
-    window_size: usize,
-    hop_size: usize,
-) -> eyre::Result<Vec<Vec<f32>>> {
-    use rustfft::FftPlanner;
-    use rustfft::num_complex::Complex;
-    use rustfft::num_traits::Zero;
-
-    let n_hops = (pcm.len().saturating_sub(window_size)) / hop_size + 1;
-    let n_freqs = window_size / 2;
-
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(window_size);
-
-    let mut spectrogram = vec![vec![0.0; n_hops]; n_freqs];
-    let mut buffer = vec![Complex::<f32>::zero(); window_size];
-
-    // Hann window
-    let window_func: Vec<f32> = (0..window_size)
-        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / window_size as f32).cos())
-        .collect();
-
-    for hop_idx in 0..n_hops {
-        let offset = hop_idx * hop_size;
-        for i in 0..window_size {
-            buffer[i].re = pcm[offset + i] * window_func[i];
-            buffer[i].im = 0.0;
-        }
-        fft.process(&mut buffer);
-
-        for freq_bin in 0..n_freqs {
-            let re = buffer[freq_bin].re;
-            let im = buffer[freq_bin].im;
-            let mag = (re * re + im * im).sqrt();
-            spectrogram[freq_bin][hop_idx] = mag;
+    for (path, original) in duplicates_of_todo {
+        // The original may have failed to decode, in which case so will its copy
+        if let Some(existing) = find_cache_file(&original, cache_dir) {
+            save_fingerprint_for_duplicate(&existing, &path, &cache_file_for(&path, cache_dir))?;
+            deduplicated += 1;
         }
     }
-
-    Ok(spectrogram)
+    info!("Reused fingerprints for {} duplicate files", deduplicated);
+    Ok(())
 }
 
-/// Find "peaks" per time slice — naive approach: pick top N frequencies by magnitude.
-fn find_peaks(spectrogram: &Vec<Vec<f32>>) -> Vec<Vec<u16>> {
-    // spectrogram[freq_bin][time]
-    let n_freqs = spectrogram.len();
-    if n_freqs == 0 {
-        return Vec::new();
-    }
-    let n_hops = spectrogram[0].len();
-    let top_n = 5;
-
-    let mut peaks_by_time = Vec::with_capacity(n_hops);
-    for time_idx in 0..n_hops {
-        // gather (freq_bin, magnitude)
-        let mut freq_mags: Vec<(u16, f32)> = (0..n_freqs)
-            .map(|f| (f as u16, spectrogram[f][time_idx]))
-            .collect();
-        // sort by magnitude descending
-        freq_mags.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-        // pick top N
-        let top_peaks: Vec<u16> = freq_mags.into_iter().take(top_n).map(|(f, _)| f).collect();
-
-        peaks_by_time.push(top_peaks);
-    }
+/// Compare every cached fingerprint in `cache_dir` against its source file, optionally rebuilding
+/// the stale ones, and report how many are ok, stale, or missing their source.
+fn verify_fingerprints(cache_dir: &Path, rebuild: bool) -> eyre::Result<()> {
+    let sample_rate = ANALYSIS_RATE;
 
-    peaks_by_time
-}
-
-/// Load or build a track’s fingerprint, then see how many collisions it has with `snippet_fp`.
-async fn find_matches(
-    track_path: &Path,
-    snippet_fp: &FingerprintData,
-    sample_rate: usize,
-) -> eyre::Result<Option<(f32, usize)>> {
-    // 1) Load or build track fingerprint
-    let track_fp = load_or_build_fingerprint(track_path, sample_rate)?;
-
-    // 2) Map (f1, f2, delta_t) -> list of anchor_times for the track
-    //    We could store that directly in the fingerprint, or we can reconstruct it here.
-    let mut track_map: HashMap<(u16, u16, u16), Vec<u32>> = HashMap::new();
-    for hash_ent in &track_fp.pairs {
-        let key = (hash_ent.f1, hash_ent.f2, hash_ent.delta_t);
-        track_map.entry(key).or_default().push(hash_ent.anchor_time);
-    }
+    let (mut ok, mut stale, mut rebuilt, mut missing_source) = (0, 0, 0, 0);
+    for entry in fs::read_dir(cache_dir)? {
+        let hash_file = entry?.path();
+        if !is_cache_file(&hash_file) {
+            continue;
+        }
 
-    // 3) For each snippet hash, check collisions
-    //    We'll compute an "offset difference" = track_anchor_time - snippet_anchor_time
-    //    The best match is the offset that appears the most frequently
-    let mut offset_count: HashMap<i32, usize> = HashMap::new();
-
-    for snippet_ent in &snippet_fp.pairs {
-        let key = (snippet_ent.f1, snippet_ent.f2, snippet_ent.delta_t);
-        if let Some(track_times) = track_map.get(&key) {
-            for &track_anchor_time in track_times {
-                let diff = track_anchor_time as i32 - snippet_ent.anchor_time as i32;
-                *offset_count.entry(diff).or_insert(0) += 1;
+        let cached = match load_fingerprint(&hash_file) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("Failed to load {:?}: {:?}", hash_file, e);
+                stale += 1;
+                continue;
             }
+        };
+        let Some(source) = cached.source else {
+            warn!("{:?} does not record its source file", hash_file);
+            missing_source += 1;
+            continue;
+        };
+        if !source.path.exists() {
+            warn!(
+                "Source {:?} of {:?} no longer exists",
+                source.path, hash_file
+            );
+            missing_source += 1;
+            continue;
         }
-    }
 
-    // 4) Find best offset by collisions
-    if offset_count.is_empty() {
-        return Ok(None);
-    }
-    let (best_offset, best_count) = offset_count.into_iter().max_by_key(|(_, c)| *c).unwrap();
-
-    // 5) Convert that offset from spectrogram frames to seconds
-    //    Each "time step" in the spectrogram corresponds to `hop_size / sample_rate` seconds.
-    //    (We used hop_size=512 in the fingerprint, so offset in frames * 512 / sr)
-    let hop_size = 512;
-    let offset_sec = best_offset as f32 * (hop_size as f32 / sample_rate as f32);
-
-    // If best_count is above some arbitrary threshold, consider it a match
-    // For real usage, you'll want a more systematic approach
-    if best_count > 5 {
-        Ok(Some((offset_sec, best_count)))
-    } else {
-        Ok(None)
-    }
-}
+        if source.is_unchanged(&SourceInfo::read(&source.path)?) {
+            debug!("{:?} is up to date", hash_file);
+            ok += 1;
+            continue;
+        }
 
-/// Load from `hashes/` if possible, else build and save
-fn load_or_build_fingerprint(
-    track_path: &Path,
-    sample_rate: usize,
-) -> eyre::Result<FingerprintData> {
-    let hash_dir = PathBuf::from("hashes");
-    if !hash_dir.exists() {
-        fs::create_dir_all(&hash_dir)?;
+        warn!("{:?} is stale, {:?} has changed", hash_file, source.path);
+        stale += 1;
+        if rebuild {
+            build_and_save_fingerprint(&source.path, &hash_file, sample_rate)?;
+            rebuilt += 1;
+        }
     }
 
-    let file_stem = track_path.file_stem().unwrap_or_default().to_string_lossy();
-    let hash_file = hash_dir.join(format!("{}.json", file_stem));
-
-    if hash_file.exists() {
-        // load
-        debug!("Loading fingerprint from {:?}", hash_file);
-        let f = File::open(&hash_file)?;
-        let reader = BufReader::new(f);
-        let data: FingerprintData = serde_json::from_reader(reader)?;
-        Ok(data)
-    } else {
-        // build
-        info!("Building fingerprint for {:?}", track_path);
-        let pcm = decode_ogg_to_mono_f32(track_path)?;
-        let data = compute_fingerprint(&pcm, sample_rate)?;
-        // save
-        let f = File::create(&hash_file)?;
-        let writer = BufWriter::new(f);
-        serde_json::to_writer_pretty(writer, &data)?;
-        Ok(data)
-    }
+    info!(
+        "Verified fingerprints: {} ok, {} stale ({} rebuilt), {} missing source",
+        ok, stale, rebuilt, missing_source
+    );
+    Ok(())
 }